@@ -1,3 +1,3 @@
 #!/bin/bash
-cargo run -r -- -e flag.bmp -l 0 -h 16777215 
-cargo run -r -- -d flag.bmp.ac
\ No newline at end of file
+cargo run -r -- -e flag.bmp -l 0 -h 16777215 -f
+cargo run -r -- -d flag.bmp.ac -f
\ No newline at end of file
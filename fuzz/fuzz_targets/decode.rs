@@ -0,0 +1,38 @@
+#![no_main]
+
+use arithmetic_coding::container;
+use arithmetic_coding::container::Container;
+use arithmetic_coding::limits::DecodeLimits;
+use arithmetic_coding::tiles::Region;
+use libfuzzer_sys::fuzz_target;
+use std::io::sink;
+
+// todas as formas de decodificação da biblioteca
+fn decode_all(data: &[u8], limits: &DecodeLimits) {
+    let _ = container::decode(data, limits, sink());
+    let _ = container::decode_partial(data, limits, sink());
+    if let Ok(container) = Container::parse(data, limits) {
+        let _ = container.decode_region(limits, Region::new(1, 1, 3, 3), sink());
+    }
+    if let Ok(container) = Container::parse(data, limits) {
+        let _ = container.decode_preview(limits, 1, sink());
+    }
+    if let Ok(container) = Container::parse(data, limits) {
+        if let Ok(frames) = container.frame_count() {
+            let _ = container.decode_frames(limits, 0, frames.min(3).saturating_sub(1), |_, _| Ok(()));
+        }
+        let _ = container.decode_frame(limits, 1, sink());
+    }
+}
+
+// qualquer sequência de bytes deve ser decodificada ou rejeitada com erro, nunca causar pânico,
+// tanto com limites apertados quanto com os limites padrão da linha de comando
+fuzz_target!(|data: &[u8]| {
+    let limits = DecodeLimits {
+        max_output_bytes: Some(1 << 20),
+        max_model_size: Some(1 << 16),
+        max_memory: Some(1 << 24),
+    };
+    decode_all(data, &limits);
+    decode_all(data, &DecodeLimits::new());
+});
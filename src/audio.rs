@@ -0,0 +1,387 @@
+use std::io::Write;
+use std::ops::Range;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::model;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Result;
+
+// quadros de cada bloco, que escolhe a representação estéreo e o preditor de cada canal
+pub const BLOCK_SIZE: usize = 4096;
+
+// preditores polinomiais fixos de ordem 0 a 4, como no FLAC, seguidos da LPC
+const FIXED_ORDERS: usize = 5;
+const PREDICTORS: usize = FIXED_ORDERS + 1;
+pub const LPC_ORDER: usize = 12;
+// bits fracionários dos coeficientes quantizados da LPC, gravados com 16 bits
+const PRECISION: u32 = 12;
+
+// quantidade de bits do resíduo mapeado para inteiros sem sinal (0, -1, 1, -2, ...), de 0 a 32
+const CATEGORIES: usize = 33;
+// bits seguintes ao mais significativo codificados com um modelo por quantidade de bits; os
+// demais, quase aleatórios, vão em grupos de até 8 bits
+const HIGH_BITS: u32 = 4;
+const CHUNK_BITS: u32 = 8;
+// a média móvel dos resíduos do canal, com peso 1/16 para o último, escolhe o modelo da
+// quantidade de bits
+const AVERAGE_SHIFT: u32 = 4;
+
+// amostras de cada canal e, em estéreo, também as de meio e lado, para que a predição de cada
+// bloco tenha o histórico completo na representação escolhida
+struct Signals {
+    channels: Vec<Vec<i64>>,
+    mid: Vec<i64>,
+    side: Vec<i64>,
+}
+
+impl Signals {
+    fn new(channels: usize, frames: usize) -> Self {
+        let stereo_len = if channels == 2 { frames } else { 0 };
+        Self {
+            channels: vec![vec![0; frames]; channels],
+            mid: vec![0; stereo_len],
+            side: vec![0; stereo_len],
+        }
+    }
+
+    fn from_channels(channels: &[Vec<i32>]) -> Self {
+        let frames = channels.first().map_or(0, Vec::len);
+        let mut signals = Self::new(channels.len(), frames);
+        for (signal, channel) in signals.channels.iter_mut().zip(channels.iter()) {
+            for (sample, &value) in signal.iter_mut().zip(channel.iter()) {
+                *sample = value as i64;
+            }
+        }
+        signals.complete(0..frames, false);
+        signals
+    }
+
+    fn frames(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    // calcula, nos quadros de `block`, a representação que não foi codificada: meio igual a
+    // (esquerdo + direito) / 2 arredondado para baixo e lado igual a esquerdo - direito; a
+    // soma tem a paridade do lado, então nada se perde
+    fn complete(&mut self, block: Range<usize>, mid_side: bool) {
+        if self.channels.len() != 2 {
+            return;
+        }
+        for index in block {
+            if mid_side {
+                let side = self.side[index];
+                let left = (2 * self.mid[index] + (side & 1) + side) >> 1;
+                self.channels[0][index] = left;
+                self.channels[1][index] = left - side;
+            } else {
+                let (left, right) = (self.channels[0][index], self.channels[1][index]);
+                self.mid[index] = (left + right) >> 1;
+                self.side[index] = left - right;
+            }
+        }
+    }
+
+    // meio e lado quando custam menos que esquerdo e direito, estimados com o preditor de ordem 2
+    fn prefers_mid_side(&self, block: Range<usize>, limit: i64) -> bool {
+        let cost = |signal: &[i64]| estimate(signal, block.clone(), 2, &[], limit);
+        cost(&self.mid) + cost(&self.side) < cost(&self.channels[0]) + cost(&self.channels[1])
+    }
+}
+
+// modelos de um canal codificado, que é o meio ou o lado nos blocos em estéreo com meio e lado
+struct ChannelModels {
+    predictor: AdaptiveModel,
+    coefficients: [AdaptiveModel; 2],
+    categories: Vec<AdaptiveModel>,
+    high_bits: Vec<AdaptiveModel>,
+    chunks: Vec<AdaptiveModel>,
+    average: u64,
+}
+
+impl ChannelModels {
+    fn new(max_total: u64) -> Result<Self> {
+        Ok(Self {
+            predictor: AdaptiveModel::new(PREDICTORS, max_total)?,
+            coefficients: [AdaptiveModel::new(256, max_total)?, AdaptiveModel::new(256, max_total)?],
+            categories: vec![AdaptiveModel::new(CATEGORIES, max_total)?; CATEGORIES],
+            high_bits: (0..CATEGORIES)
+                .map(|category| AdaptiveModel::new(1 << high_len(category as u32).max(1), max_total))
+                .collect::<Result<_>>()?,
+            chunks: (1..=CHUNK_BITS).map(|len| AdaptiveModel::new(1 << len, max_total)).collect::<Result<_>>()?,
+            average: 0,
+        })
+    }
+
+    fn memory_len() -> u64 {
+        let high_bits: u64 = (0..CATEGORIES).map(|category| AdaptiveModel::memory_len(1 << high_len(category as u32).max(1))).sum();
+        let chunks: u64 = (1..=CHUNK_BITS).map(|len| AdaptiveModel::memory_len(1 << len)).sum();
+        AdaptiveModel::memory_len(PREDICTORS)
+            + 2 * AdaptiveModel::memory_len(256)
+            + CATEGORIES as u64 * AdaptiveModel::memory_len(CATEGORIES)
+            + high_bits
+            + chunks
+    }
+}
+
+// memória usada na decodificação, com as duas representações dos canais em estéreo
+pub fn memory_len(channels: usize, frames: u64) -> u64 {
+    let signals = if channels == 2 { 4 } else { channels as u64 };
+    frames
+        .saturating_mul(signals * size_of::<i64>() as u64)
+        .saturating_add(AdaptiveModel::memory_len(2))
+        .saturating_add(ChannelModels::memory_len() * channels as u64)
+}
+
+// maior quantidade de quadros que `len` bytes codificados podem conter: cada amostra codifica
+// ao menos a sua quantidade de bits
+pub fn max_frames(len: usize, channels: usize) -> u64 {
+    model::max_symbols(len, CATEGORIES) / channels.max(1) as u64
+}
+
+// `bits` é a quantidade de bits das amostras; os canais devem ter a mesma quantidade de quadros
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, channels: &[Vec<i32>], bits: u16) -> Result<()> {
+    let source = Signals::from_channels(channels);
+    let mut coded = Signals::new(channels.len(), source.frames());
+    code_signals(encoder, &mut coded, Some(&source), bits)
+}
+
+pub fn decode(decoder: &mut ArithmeticDecoder, channels: usize, frames: usize, bits: u16) -> Result<Vec<Vec<i32>>> {
+    let mut signals = Signals::new(channels, frames);
+    code_signals(decoder, &mut signals, None, bits)?;
+    Ok(signals
+        .channels
+        .iter()
+        .map(|signal| signal.iter().map(|&sample| sample as i32).collect())
+        .collect())
+}
+
+// em cada bloco, o estéreo é codificado como esquerdo e direito ou como meio e lado, e cada
+// canal com o preditor que a estimativa de custo escolher
+fn code_signals<C: Coder>(coder: &mut C, signals: &mut Signals, source: Option<&Signals>, bits: u16) -> Result<()> {
+    let mut stereo_model = AdaptiveModel::new(2, coder.max_total())?;
+    let mut models = (0..signals.channels.len())
+        .map(|_| ChannelModels::new(coder.max_total()))
+        .collect::<Result<Vec<_>>>()?;
+    // o lado ocupa um bit a mais que as amostras
+    let limit = 1i64 << bits;
+
+    let frames = signals.frames();
+    for start in (0..frames).step_by(BLOCK_SIZE) {
+        let block = start..(start + BLOCK_SIZE).min(frames);
+        let mid_side = signals.channels.len() == 2 && {
+            let choice = source.is_some_and(|source| source.prefers_mid_side(block.clone(), limit));
+            coder.code(&mut stereo_model, choice as u32)? == 1
+        };
+        for (slot, models) in models.iter_mut().enumerate() {
+            let (signal, original) = match (mid_side, slot) {
+                (true, 0) => (&mut signals.mid, source.map(|source| &source.mid)),
+                (true, _) => (&mut signals.side, source.map(|source| &source.side)),
+                (false, _) => (&mut signals.channels[slot], source.map(|source| &source.channels[slot])),
+            };
+            code_block(coder, models, signal, original.map(Vec::as_slice), block.clone(), limit)?;
+        }
+        signals.complete(block, mid_side);
+    }
+    Ok(())
+}
+
+fn code_block<C: Coder>(coder: &mut C, models: &mut ChannelModels, signal: &mut [i64], original: Option<&[i64]>, block: Range<usize>, limit: i64) -> Result<()> {
+    let (predictor, coefficients) = original.map_or((0, Vec::new()), |original| choose_predictor(original, block.clone(), limit));
+    let predictor = coder.code(&mut models.predictor, predictor as u32)? as usize;
+    let mut coefficients = if predictor == FIXED_ORDERS { coefficients } else { Vec::new() };
+    if predictor == FIXED_ORDERS {
+        coefficients.resize(LPC_ORDER, 0);
+        for coefficient in coefficients.iter_mut() {
+            let [high, low] = (*coefficient as i16).to_be_bytes();
+            let high = coder.code(&mut models.coefficients[0], high as u32)? as u8;
+            let low = coder.code(&mut models.coefficients[1], low as u32)? as u8;
+            *coefficient = i16::from_be_bytes([high, low]) as i64;
+        }
+    }
+
+    for index in block {
+        let predicted = predict(signal, index, predictor, &coefficients, limit);
+        let residual = original.map_or(0, |original| original[index] - predicted);
+        let residual = code_residual(coder, models, residual)?;
+        signal[index] = predicted + residual;
+    }
+    Ok(())
+}
+
+// a quantidade de bits do resíduo, depois os bits altos e os grupos de bits baixos
+fn code_residual<C: Coder>(coder: &mut C, models: &mut ChannelModels, residual: i64) -> Result<i64> {
+    let value = ((residual << 1) ^ (residual >> 63)) as u64;
+    let context = (bit_length(models.average >> AVERAGE_SHIFT) as usize).min(CATEGORIES - 1);
+    let category = coder.code(&mut models.categories[context], bit_length(value))?;
+    let value = match category {
+        0 | 1 => category as u64,
+        _ => {
+            let mut rest = category - 1;
+            let len = high_len(category);
+            rest -= len;
+            let high = coder.code(&mut models.high_bits[category as usize], ((value >> rest) & mask(len)) as u32)? as u64;
+            let mut decoded = (1 << (category - 1)) | (high << rest);
+            while rest > 0 {
+                let len = rest.min(CHUNK_BITS);
+                rest -= len;
+                let chunk = coder.code(&mut models.chunks[len as usize - 1], ((value >> rest) & mask(len)) as u32)? as u64;
+                decoded |= chunk << rest;
+            }
+            decoded
+        }
+    };
+    models.average = models.average - (models.average >> AVERAGE_SHIFT) + value;
+    Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+// preditor com o menor custo estimado no bloco, com os coeficientes quando é a LPC
+fn choose_predictor(signal: &[i64], block: Range<usize>, limit: i64) -> (usize, Vec<i64>) {
+    let mut best = (0, Vec::new());
+    let mut best_cost = u64::MAX;
+    for order in 0..FIXED_ORDERS {
+        let cost = estimate(signal, block.clone(), order, &[], limit);
+        if cost < best_cost {
+            best = (order, Vec::new());
+            best_cost = cost;
+        }
+    }
+    if let Some(coefficients) = lpc(&signal[block.clone()]) {
+        let cost = estimate(signal, block, FIXED_ORDERS, &coefficients, limit).saturating_add(16 * LPC_ORDER as u64);
+        if cost < best_cost {
+            best = (FIXED_ORDERS, coefficients);
+        }
+    }
+    best
+}
+
+// soma da quantidade de bits dos resíduos, aproximação do tamanho codificado
+fn estimate(signal: &[i64], block: Range<usize>, predictor: usize, coefficients: &[i64], limit: i64) -> u64 {
+    block
+        .map(|index| {
+            let residual = signal[index] - predict(signal, index, predictor, coefficients, limit);
+            bit_length(((residual << 1) ^ (residual >> 63)) as u64) as u64 + 1
+        })
+        .sum()
+}
+
+// amostras anteriores ao início do arquivo valem zero; a previsão é limitada à faixa do lado
+fn predict(signal: &[i64], index: usize, predictor: usize, coefficients: &[i64], limit: i64) -> i64 {
+    let past = |distance: usize| if index >= distance { signal[index - distance] } else { 0 };
+    let predicted = match predictor {
+        0 => 0,
+        1 => past(1),
+        2 => 2 * past(1) - past(2),
+        3 => 3 * past(1) - 3 * past(2) + past(3),
+        4 => 4 * past(1) - 6 * past(2) + 4 * past(3) - past(4),
+        _ => coefficients.iter().enumerate().map(|(lag, &coefficient)| coefficient * past(lag + 1)).sum::<i64>() >> PRECISION,
+    };
+    predicted.clamp(-limit, limit)
+}
+
+// coeficientes pela autocorrelação do bloco, com a janela de Welch, e pela recursão de
+// Levinson-Durbin, quantizados com `PRECISION` bits fracionários
+fn lpc(samples: &[i64]) -> Option<Vec<i64>> {
+    if samples.len() <= LPC_ORDER {
+        return None;
+    }
+    let half = (samples.len() - 1) as f64 / 2.0;
+    let windowed: Vec<f64> = samples
+        .iter()
+        .enumerate()
+        .map(|(index, &sample)| sample as f64 * (1.0 - ((index as f64 - half) / (half + 1.0)).powi(2)))
+        .collect();
+    let autocorrelation: Vec<f64> = (0..=LPC_ORDER)
+        .map(|lag| windowed[lag..].iter().zip(windowed.iter()).map(|(&a, &b)| a * b).sum())
+        .collect();
+    let mut error = autocorrelation[0];
+    if error <= 0.0 {
+        return None;
+    }
+    let mut coefficients = vec![0.0; LPC_ORDER];
+    for order in 0..LPC_ORDER {
+        let mut reflection = autocorrelation[order + 1];
+        for lag in 0..order {
+            reflection -= coefficients[lag] * autocorrelation[order - lag];
+        }
+        reflection /= error;
+        let previous = coefficients.clone();
+        coefficients[order] = reflection;
+        for lag in 0..order {
+            coefficients[lag] = previous[lag] - reflection * previous[order - 1 - lag];
+        }
+        error *= 1.0 - reflection * reflection;
+        if error <= 0.0 {
+            break;
+        }
+    }
+    let scale = (1u32 << PRECISION) as f64;
+    Some(coefficients.iter().map(|&coefficient| (coefficient * scale).round().clamp(i16::MIN as f64, i16::MAX as f64) as i64).collect())
+}
+
+fn high_len(category: u32) -> u32 {
+    category.saturating_sub(1).min(HIGH_BITS)
+}
+
+fn bit_length(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
+
+fn mask(len: u32) -> u64 {
+    (1 << len) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        Rng,
+    };
+
+    fn round_trip(channels: &[Vec<i32>], bits: u16) -> usize {
+        let encoded = encode(|encoder| super::encode(encoder, channels, bits));
+        let frames = channels[0].len();
+        let decoded = decode(&encoded, |decoder| super::decode(decoder, channels.len(), frames, bits)).unwrap();
+        assert_eq!(decoded, channels);
+        encoded.len()
+    }
+
+    #[test]
+    fn every_depth_round_trips() {
+        let mut rng = Rng(5);
+        for bits in [8u16, 16, 24] {
+            let max = (1i64 << (bits - 1)) - 1;
+            let tone = |offset: f64| -> Vec<i32> {
+                (0..BLOCK_SIZE + 1000).map(|frame| ((frame as f64 * 0.01 + offset).sin() * max as f64 * 0.8) as i32).collect()
+            };
+            // um canal correlacionado com o outro e outro com os extremos do intervalo
+            let left = tone(0.0);
+            let right: Vec<i32> = left.iter().map(|&sample| sample / 2 + rng.below(5) as i32).collect();
+            let len = round_trip(&[left.clone(), right], bits);
+            assert!(len < left.len() * bits as usize / 8);
+            let extremes: Vec<i32> = (0..500).map(|frame| if frame % 2 == 0 { max as i32 } else { -max as i32 - 1 }).collect();
+            round_trip(&[extremes], bits);
+        }
+        round_trip(&[vec![], vec![]], 16);
+    }
+
+    // um arquivo forjado com mais quadros do que os dados comportam termina em erro ou em
+    // silêncio, sem ler além da região codificada
+    #[test]
+    fn truncated_data_does_not_panic() {
+        let channels = vec![(0..3000).map(|frame| (frame * 37 % 2001) - 1000).collect::<Vec<i32>>()];
+        let encoded = encode(|encoder| super::encode(encoder, &channels, 16));
+        let container = crate::container::Container::parse(&encoded, &crate::limits::DecodeLimits::new()).unwrap();
+        let truncated = &container.encoded_data[..container.encoded_data.len() / 8 * 4];
+        let mut decoder = ArithmeticDecoder::new(container.ac.clone(), truncated, 32).unwrap();
+        if let Ok(decoded) = super::decode(&mut decoder, 1, 3000, 16) {
+            assert_ne!(decoded, channels);
+        }
+        assert!(max_frames(encoded.len(), 2) < max_frames(encoded.len(), 1));
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Error;
+use crate::Result;
+
+// gabarito de 16 pixels já codificados, como o modelo 0 da região genérica do JBIG2 com os
+// pixels adaptativos nas posições padrão: (dx, dy) em relação ao pixel atual
+const TEMPLATE: [(isize, isize); 16] = [
+    (-2, -2), (-1, -2), (0, -2), (1, -2), (2, -2),
+    (-3, -1), (-2, -1), (-1, -1), (0, -1), (1, -1), (2, -1), (3, -1),
+    (-4, 0), (-3, 0), (-2, 0), (-1, 0),
+];
+const CONTEXTS: usize = 1 << TEMPLATE.len();
+
+// memória usada pelos modelos binários de todos os contextos
+pub fn memory_len() -> u64 {
+    AdaptiveModel::memory_len(2).saturating_mul(CONTEXTS as u64)
+}
+
+// o modo requer um único plano de 1 bit, como o dos BMP de 1 bit por pixel
+pub fn validate(depths: &[u8]) -> Result<()> {
+    if depths != [1] {
+        return Err(Error::UnsupportedImage("o modo bilevel requer imagem de 1 bit por pixel"));
+    }
+    Ok(())
+}
+
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image) -> Result<()> {
+    validate(&image.depths())?;
+    let mut coded = Image::new(image.width, image.height, &image.depths())?;
+    code_pixels(encoder, &mut coded, Some(image))
+}
+
+// reconstrói o plano de `image`, que já deve ter as dimensões gravadas
+pub fn decode(decoder: &mut ArithmeticDecoder, image: &mut Image) -> Result<()> {
+    validate(&image.depths())?;
+    code_pixels(decoder, image, None)
+}
+
+// os pixels são percorridos linha a linha, cada um com o modelo do contexto formado pelos
+// vizinhos do gabarito; pixels fora da imagem valem 0
+fn code_pixels<C: Coder>(coder: &mut C, image: &mut Image, source: Option<&Image>) -> Result<()> {
+    let mut models = vec![AdaptiveModel::new(2, coder.max_total())?; CONTEXTS];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let context = context(image, x, y);
+            let symbol = source.map_or(0, |source| source.get(0, x, y));
+            let pixel = coder.code(&mut models[context], symbol as u32)?;
+            image.set(0, x, y, pixel as u16);
+        }
+    }
+    Ok(())
+}
+
+fn context(image: &Image, x: usize, y: usize) -> usize {
+    TEMPLATE.iter().fold(0, |context, &(dx, dy)| {
+        let pixel = match (x.checked_add_signed(dx), y.checked_add_signed(dy)) {
+            (Some(x), Some(y)) if x < image.width => image.get(0, x, y) as usize,
+            _ => 0,
+        };
+        (context << 1) | pixel
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmp;
+    use crate::testing::{
+        bilevel_bmp,
+        decode,
+        encode,
+        photo,
+    };
+
+    #[test]
+    fn lossless_round_trip() {
+        let (_, image) = bmp::split(&bilevel_bmp(70, 45)).unwrap();
+        let encoded = encode(|encoder| super::encode(encoder, &image));
+        // o contexto dos vizinhos torna o círculo muito barato
+        assert!(encoded.len() < 70 * 45 / 8 / 3);
+        let mut decoded = Image::new(70, 45, &[1]).unwrap();
+        decode(&encoded, |decoder| super::decode(decoder, &mut decoded)).unwrap();
+        assert_eq!(decoded.planes, image.planes);
+    }
+
+    #[test]
+    fn other_depths_are_rejected() {
+        let mut encoder = ArithmeticEncoder::new(0, (1 << 30) - 1, Vec::new()).unwrap();
+        assert!(matches!(super::encode(&mut encoder, &photo(4, 4, &[8])), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(validate(&[1, 1]), Err(Error::UnsupportedImage(_))));
+        assert!(validate(&[1]).is_ok());
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::ArithmeticCoding;
+use crate::Error;
+use crate::Result;
+
+// contexto de cada bit: os bits já codificados no mesmo plano de bits dos vizinhos à
+// esquerda, acima à esquerda, acima e acima à direita (16), o estado da própria amostra nos
+// planos mais altos (3) e quantos dos vizinhos à esquerda, acima, à direita e abaixo já são
+// significativos nesses planos (0, 1, 2 ou mais)
+const CONTEXTS: usize = 16 * 3 * 3;
+
+// memória usada pelos modelos de um plano de bits de cada canal
+pub fn memory_len(planes: usize) -> u64 {
+    AdaptiveModel::memory_len(2)
+        .saturating_mul(CONTEXTS as u64)
+        .saturating_mul(planes as u64)
+}
+
+// cada plano de bits de cada canal é gravado em um trecho próprio da região codificada,
+// terminado com a última palavra completa, para que um arquivo truncado depois de qualquer
+// plano ainda possa ser decodificado. Retorna a posição do fim de cada trecho
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image) -> Result<Vec<u64>> {
+    let mut coded = Image::new(image.width, image.height, &image.depths())?;
+    let mut ends = Vec::new();
+    for (level, plane) in planes(image) {
+        code_plane(encoder, &mut coded, Some(image), level, plane)?;
+        ends.push(encoder.restart()?);
+    }
+    Ok(ends)
+}
+
+// reconstrói os planos de `image`, que já deve ter as dimensões e profundidades gravadas, a
+// partir dos trechos inteiros em `data`, que pode ser apenas o início da região codificada;
+// com `skip` maior que zero, os `skip` planos de bits menos significativos não são lidos. Os
+// bits que faltam recebem o ponto médio do intervalo possível. Retorna quantos trechos foram
+// decodificados
+pub fn decode(ac: &ArithmeticCoding, data: &[u8], ends: &[u64], image: &mut Image, skip: u8) -> Result<usize> {
+    let depth = max_depth(image);
+    if skip > depth {
+        return Err(Error::UnsupportedImage("quantidade de planos de bits inválida"));
+    }
+    let planes = planes(image);
+    if ends.len() != planes.len() {
+        return Err(Error::InvalidContainer("índice de planos de bits inválido"));
+    }
+    ac.validate()?;
+
+    let mut decoded = vec![0u8; image.planes.len()];
+    let mut start = 0;
+    let mut count = 0;
+    for (&(level, plane), &end) in planes.iter().zip(ends.iter()) {
+        if level >= depth - skip || end > data.len() as u64 {
+            break;
+        }
+        if start > end {
+            return Err(Error::InvalidContainer("índice de planos de bits inválido"));
+        }
+        let mut decoder = ArithmeticDecoder::new(ac.clone(), &data[start as usize..end as usize], 32)?;
+        code_plane(&mut decoder, image, None, level, plane)?;
+        decoded[plane] += 1;
+        start = end;
+        count += 1;
+    }
+
+    for (plane, &decoded) in image.planes.iter_mut().zip(decoded.iter()) {
+        let missing = plane.depth - decoded;
+        if missing > 0 {
+            for sample in plane.samples.iter_mut() {
+                *sample |= 1 << (missing - 1);
+            }
+        }
+    }
+    Ok(count)
+}
+
+fn max_depth(image: &Image) -> u8 {
+    image.planes.iter().map(|plane| plane.depth).max().unwrap_or(0)
+}
+
+// os planos de bits são percorridos do mais significativo para o menos significativo, com
+// os canais alinhados pelo bit mais significativo: pares (nível, canal) na ordem de gravação
+fn planes(image: &Image) -> Vec<(u8, usize)> {
+    (0..max_depth(image))
+        .flat_map(|level| {
+            image
+                .planes
+                .iter()
+                .enumerate()
+                .filter(move |(_, plane)| level < plane.depth)
+                .map(move |(plane, _)| (level, plane))
+        })
+        .collect()
+}
+
+// cada plano de bits de cada canal usa modelos novos; os bits 1 são acumulados nas amostras de
+// `image`
+fn code_plane<C: Coder>(coder: &mut C, image: &mut Image, source: Option<&Image>, level: u8, plane: usize) -> Result<()> {
+    let bit = image.planes[plane].depth - 1 - level;
+    let mut models = vec![AdaptiveModel::new(2, coder.max_total())?; CONTEXTS];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let context = context(image, plane, x, y, bit);
+            let symbol = source.map_or(0, |source| (source.get(plane, x, y) >> bit) & 1);
+            if coder.code(&mut models[context], symbol as u32)? == 1 {
+                let sample = image.get(plane, x, y);
+                image.set(plane, x, y, sample | (1 << bit));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn context(image: &Image, plane: usize, x: usize, y: usize, bit: u8) -> usize {
+    let higher = |x: usize, y: usize| image.get(plane, x, y) as u32 >> (bit + 1);
+    let current = |x: usize, y: usize| ((image.get(plane, x, y) >> bit) & 1) as usize;
+
+    let left = if x > 0 { current(x - 1, y) } else { 0 };
+    let up_left = if x > 0 && y > 0 { current(x - 1, y - 1) } else { 0 };
+    let up = if y > 0 { current(x, y - 1) } else { 0 };
+    let up_right = if x + 1 < image.width && y > 0 { current(x + 1, y - 1) } else { 0 };
+    let neighbours = left | (up_left << 1) | (up << 2) | (up_right << 3);
+
+    // não significativa, significativa desde o plano anterior ou há mais tempo
+    let state = match higher(x, y) {
+        0 => 0,
+        1 => 1,
+        _ => 2,
+    };
+
+    let significant = [
+        x > 0 && higher(x - 1, y) != 0,
+        y > 0 && higher(x, y - 1) != 0,
+        x + 1 < image.width && higher(x + 1, y) != 0,
+        y + 1 < image.height && higher(x, y + 1) != 0,
+    ];
+    let count = significant.iter().filter(|&&significant| significant).count().min(2);
+
+    (neighbours * 3 + state) * 3 + count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::limits::DecodeLimits;
+    use crate::testing::{
+        encode,
+        photo,
+    };
+
+    fn encode_planes(image: &Image) -> (Vec<u8>, Vec<u64>) {
+        let mut ends = Vec::new();
+        let encoded = encode(|encoder| {
+            ends = super::encode(encoder, image)?;
+            Ok(())
+        });
+        (encoded, ends)
+    }
+
+    // as amostras de `decoded` devem ter os `bits` bits mais altos de `original` e o restante no
+    // ponto médio
+    fn assert_coarse(decoded: &Image, original: &Image, bits: &[u8]) {
+        for ((plane, original), &bits) in decoded.planes.iter().zip(original.planes.iter()).zip(bits.iter()) {
+            let missing = plane.depth - bits;
+            for (&sample, &expected) in plane.samples.iter().zip(original.samples.iter()) {
+                assert_eq!(sample >> missing, expected >> missing);
+                if missing > 0 {
+                    assert_eq!(sample & ((1 << missing) - 1), 1 << (missing - 1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lossless_round_trip_and_previews() {
+        let image = photo(15, 9, &[8, 5, 8]);
+        let (encoded, ends) = encode_planes(&image);
+        assert_eq!(ends.len(), 21);
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let (ac, data) = (&container.ac, container.encoded_data);
+
+        let mut decoded = Image::new(15, 9, &image.depths()).unwrap();
+        assert_eq!(decode(ac, data, &ends, &mut decoded, 0).unwrap(), 21);
+        assert_eq!(decoded.planes, image.planes);
+
+        // sem os 3 planos de bits menos significativos, o canal de 5 bits vem inteiro
+        let mut preview = Image::new(15, 9, &image.depths()).unwrap();
+        assert_eq!(decode(ac, data, &ends, &mut preview, 3).unwrap(), 15);
+        assert_coarse(&preview, &image, &[5, 5, 5]);
+    }
+
+    // cortado depois de qualquer plano, o arquivo ainda fornece uma aproximação
+    #[test]
+    fn truncated_data_decodes_the_complete_planes() {
+        let image = photo(12, 10, &[8, 5]);
+        let (encoded, ends) = encode_planes(&image);
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let data = container.encoded_data;
+
+        // os 4 primeiros trechos são os 2 planos mais altos de cada canal; o quinto está incompleto
+        let mut decoded = Image::new(12, 10, &image.depths()).unwrap();
+        let cut = &data[..ends[4] as usize - 1];
+        assert_eq!(decode(&container.ac, cut, &ends, &mut decoded, 0).unwrap(), 4);
+        assert_coarse(&decoded, &image, &[2, 2]);
+
+        let mut decoded = Image::new(12, 10, &image.depths()).unwrap();
+        assert_eq!(decode(&container.ac, &[], &ends, &mut decoded, 0).unwrap(), 0);
+        assert_coarse(&decoded, &image, &[0, 0]);
+    }
+
+    #[test]
+    fn malformed_indexes_are_rejected() {
+        let image = photo(4, 4, &[4]);
+        let (encoded, ends) = encode_planes(&image);
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let (ac, data) = (&container.ac, container.encoded_data);
+        let mut decoded = Image::new(4, 4, &[4]).unwrap();
+        assert!(matches!(decode(ac, data, &ends, &mut decoded, 5), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(decode(ac, data, &ends[..3], &mut decoded, 0), Err(Error::InvalidContainer(_))));
+        assert!(matches!(decode(ac, data, &[ends[1], ends[0], ends[2], ends[3]], &mut decoded, 0), Err(Error::InvalidContainer(_))));
+    }
+}
@@ -0,0 +1,755 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::image::Image;
+use crate::Error;
+use crate::Result;
+
+pub const FILE_HEADER_LEN: usize = 14;
+
+pub const CORE_HEADER_LEN: u32 = 12;
+pub const INFO_HEADER_LEN: u32 = 40;
+pub const V4_HEADER_LEN: u32 = 108;
+pub const V5_HEADER_LEN: u32 = 124;
+
+pub const BI_RGB: u32 = 0;
+pub const BI_RLE8: u32 = 1;
+pub const BI_RLE4: u32 = 2;
+pub const BI_BITFIELDS: u32 = 3;
+pub const BI_ALPHABITFIELDS: u32 = 6;
+
+// cabeçalhos e disposição dos pixels de um arquivo BMP
+#[derive(Debug, Clone)]
+pub struct Bmp {
+    pub info_header_len: u32,
+    pub width: u32,
+    pub height: u32,
+    pub top_down: bool,
+    pub bits_per_pixel: u16,
+    pub compression: u32,
+    pub size_image: u32,
+    // máscaras dos canais de 16 e 32 bits por pixel, na ordem B, G, R, A
+    pub masks: Vec<u32>,
+    // cores da paleta na ordem B, G, R
+    pub palette: Vec<[u8; 3]>,
+    pub palette_offset: usize,
+    pub palette_entry_len: usize,
+    pub pixel_offset: usize,
+    pub row_size: usize,
+}
+
+impl Bmp {
+    // lê apenas os cabeçalhos e a paleta, isto é, os bytes anteriores à região de pixels
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < FILE_HEADER_LEN + CORE_HEADER_LEN as usize || &bytes[0..2] != b"BM" {
+            return Err(Error::InvalidImage("cabeçalho BMP ausente"));
+        }
+
+        let pixel_offset = read_u32(bytes, 10) as usize;
+        let info_header_len = read_u32(bytes, 14);
+        if !matches!(info_header_len, CORE_HEADER_LEN | INFO_HEADER_LEN | 52 | 56 | V4_HEADER_LEN | V5_HEADER_LEN) {
+            return Err(Error::UnsupportedImage("versão de cabeçalho BMP desconhecida"));
+        }
+        let info_end = FILE_HEADER_LEN + info_header_len as usize;
+        if bytes.len() < info_end || pixel_offset < info_end {
+            return Err(Error::InvalidImage("cabeçalho BMP incompleto"));
+        }
+
+        let (width, height, bits_per_pixel, compression, size_image, colors_used) = if info_header_len == CORE_HEADER_LEN {
+            (
+                read_u16(bytes, 18) as i32,
+                read_u16(bytes, 20) as i32,
+                read_u16(bytes, 24),
+                BI_RGB,
+                0,
+                0,
+            )
+        } else {
+            (
+                read_i32(bytes, 18),
+                read_i32(bytes, 22),
+                read_u16(bytes, 28),
+                read_u32(bytes, 30),
+                read_u32(bytes, 34),
+                read_u32(bytes, 46),
+            )
+        };
+
+        if width <= 0 || height == 0 || height == i32::MIN {
+            return Err(Error::InvalidImage("dimensões inválidas"));
+        }
+        let width = width as u32;
+        let top_down = height < 0;
+        let height = height.unsigned_abs();
+
+        let valid = match compression {
+            BI_RGB => matches!(bits_per_pixel, 1 | 4 | 8 | 16 | 24 | 32),
+            BI_RLE8 => bits_per_pixel == 8 && !top_down,
+            BI_RLE4 => bits_per_pixel == 4 && !top_down,
+            BI_BITFIELDS | BI_ALPHABITFIELDS => matches!(bits_per_pixel, 16 | 32),
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::UnsupportedImage("compressão ou profundidade de cor não suportada"));
+        }
+
+        // máscaras: dentro dos cabeçalhos maiores ou logo após o BITMAPINFOHEADER
+        let mut palette_offset = info_end;
+        let masks = match compression {
+            BI_BITFIELDS | BI_ALPHABITFIELDS => {
+                let count = if compression == BI_ALPHABITFIELDS { 4 } else { 3 };
+                let offset = if info_header_len == INFO_HEADER_LEN {
+                    palette_offset += count * 4;
+                    info_end
+                } else {
+                    FILE_HEADER_LEN + INFO_HEADER_LEN as usize
+                };
+                let count = if info_header_len >= 56 { 4 } else { count };
+                if palette_offset > pixel_offset || offset + count * 4 > bytes.len() {
+                    return Err(Error::InvalidImage("máscaras de cor ausentes"));
+                }
+                let rgba: Vec<u32> = (0..count).map(|i| read_u32(bytes, offset + i * 4)).collect();
+                let mut masks = vec![rgba[2], rgba[1], rgba[0]];
+                if count == 4 && rgba[3] != 0 {
+                    masks.push(rgba[3]);
+                }
+                masks
+            }
+            _ if bits_per_pixel == 16 => vec![0x001F, 0x03E0, 0x7C00],
+            _ if bits_per_pixel == 32 => vec![0x0000_00FF, 0x0000_FF00, 0x00FF_0000, 0xFF00_0000],
+            _ => Vec::new(),
+        };
+        validate_masks(&masks, bits_per_pixel)?;
+
+        // paleta: entradas de 3 bytes no cabeçalho antigo e de 4 bytes nos demais
+        let palette_entry_len = if info_header_len == CORE_HEADER_LEN { 3 } else { 4 };
+        let mut palette: Vec<[u8; 3]> = Vec::new();
+        if bits_per_pixel <= 8 {
+            let max_colors = 1usize << bits_per_pixel;
+            let mut count = if colors_used > 0 && (colors_used as usize) < max_colors {
+                colors_used as usize
+            } else {
+                max_colors
+            };
+            let available = (pixel_offset.min(bytes.len()) - palette_offset) / palette_entry_len;
+            count = count.min(available);
+            for i in 0..count {
+                let entry = palette_offset + i * palette_entry_len;
+                palette.push([bytes[entry], bytes[entry + 1], bytes[entry + 2]]);
+            }
+        }
+
+        let row_size = (width as usize)
+            .checked_mul(bits_per_pixel as usize)
+            .map(|bits| bits.div_ceil(32) * 4)
+            .ok_or(Error::InvalidImage("dimensões muito grandes"))?;
+        Image::checked_len(width as usize, height as usize)?;
+
+        Ok(Self {
+            info_header_len,
+            width,
+            height,
+            top_down,
+            bits_per_pixel,
+            compression,
+            size_image,
+            masks,
+            palette,
+            palette_offset,
+            palette_entry_len,
+            pixel_offset,
+            row_size,
+        })
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel as usize / 8
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self.compression, BI_RLE8 | BI_RLE4)
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.bits_per_pixel <= 8
+    }
+
+    // tamanho da região de pixels não comprimida
+    pub fn pixel_len(&self) -> usize {
+        self.row_size * self.height as usize
+    }
+
+    // profundidade de cada plano exposto: índices da paleta, bytes B, G, R ou canais das máscaras
+    pub fn plane_depths(&self) -> Vec<u8> {
+        match self.bits_per_pixel {
+            1 | 4 | 8 => vec![self.bits_per_pixel as u8],
+            24 => vec![8, 8, 8],
+            _ => self.masks.iter().map(|mask| mask.count_ones() as u8).collect(),
+        }
+    }
+
+    // converte a região de pixels em planos; retorna também o tamanho da região lida
+    pub fn read_image(&self, bytes: &[u8]) -> Result<(Image, usize)> {
+        let pixels = bytes.get(self.pixel_offset..).unwrap_or(&[]);
+        let len = self.pixel_len();
+        if !self.is_compressed() && pixels.len() < len {
+            return Err(Error::InvalidImage("dados de pixels fora do arquivo"));
+        }
+
+        let mut image = Image::new(self.width as usize, self.height as usize, &self.plane_depths())?;
+        if self.is_compressed() {
+            let len = self.read_rle(pixels, &mut image)?;
+            return Ok((image, len));
+        }
+
+        for row in 0..self.height as usize {
+            let y = self.row_to_y(row);
+            let data = &pixels[row * self.row_size..(row + 1) * self.row_size];
+            for x in 0..self.width as usize {
+                self.read_pixel(data, x, y, &mut image);
+            }
+        }
+        Ok((image, len))
+    }
+
+    // gera a região de pixels a partir dos planos, com preenchimento e bits sem uso zerados
+    pub fn write_pixels(&self, image: &Image) -> Result<Vec<u8>> {
+        if image.width != self.width as usize
+            || image.height != self.height as usize
+            || image.depths() != self.plane_depths()
+        {
+            return Err(Error::InvalidImage("planos incompatíveis com o cabeçalho BMP"));
+        }
+
+        if self.is_compressed() {
+            return Ok(self.write_rle(image));
+        }
+
+        let mut pixels = vec![0u8; self.pixel_len()];
+        for row in 0..self.height as usize {
+            let y = self.row_to_y(row);
+            let data = &mut pixels[row * self.row_size..(row + 1) * self.row_size];
+            for x in 0..self.width as usize {
+                self.write_pixel(data, x, y, image);
+            }
+        }
+        Ok(pixels)
+    }
+
+    // as linhas são gravadas de baixo para cima, exceto quando a altura é negativa
+    fn row_to_y(&self, row: usize) -> usize {
+        if self.top_down {
+            row
+        } else {
+            self.height as usize - 1 - row
+        }
+    }
+
+    fn read_pixel(&self, data: &[u8], x: usize, y: usize, image: &mut Image) {
+        match self.bits_per_pixel {
+            1 | 4 | 8 => {
+                let bits = self.bits_per_pixel as usize;
+                let bit = x * bits;
+                let shift = 8 - bits - bit % 8;
+                let index = (data[bit / 8] >> shift) & ((1u16 << bits) - 1) as u8;
+                image.set(0, x, y, index as u16);
+            }
+            24 => {
+                for channel in 0..3 {
+                    image.set(channel, x, y, data[x * 3 + channel] as u16);
+                }
+            }
+            16 => {
+                let value = read_u16(data, x * 2) as u32;
+                self.read_masked(value, x, y, image);
+            }
+            _ => {
+                let value = read_u32(data, x * 4);
+                self.read_masked(value, x, y, image);
+            }
+        }
+    }
+
+    fn read_masked(&self, value: u32, x: usize, y: usize, image: &mut Image) {
+        for (channel, &mask) in self.masks.iter().enumerate() {
+            image.set(channel, x, y, ((value & mask) >> mask.trailing_zeros()) as u16);
+        }
+    }
+
+    fn write_pixel(&self, data: &mut [u8], x: usize, y: usize, image: &Image) {
+        match self.bits_per_pixel {
+            1 | 4 | 8 => {
+                let bits = self.bits_per_pixel as usize;
+                let bit = x * bits;
+                let shift = 8 - bits - bit % 8;
+                data[bit / 8] |= (image.get(0, x, y) as u8) << shift;
+            }
+            24 => {
+                for channel in 0..3 {
+                    data[x * 3 + channel] = image.get(channel, x, y) as u8;
+                }
+            }
+            16 => {
+                let value = self.write_masked(x, y, image) as u16;
+                data[x * 2..x * 2 + 2].copy_from_slice(&value.to_le_bytes());
+            }
+            _ => {
+                let value = self.write_masked(x, y, image);
+                data[x * 4..x * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    fn write_masked(&self, x: usize, y: usize, image: &Image) -> u32 {
+        let mut value: u32 = 0;
+        for (channel, &mask) in self.masks.iter().enumerate() {
+            value |= ((image.get(channel, x, y) as u32) << mask.trailing_zeros()) & mask;
+        }
+        value
+    }
+
+    // BI_RLE8 e BI_RLE4: pares (quantidade, valor) e sequências de escape iniciadas por zero;
+    // pixels não definidos pelo fluxo ficam com o índice zero
+    fn read_rle(&self, data: &[u8], image: &mut Image) -> Result<usize> {
+        let four_bits = self.compression == BI_RLE4;
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let mut x: usize = 0;
+        let mut row: usize = 0;
+        let mut position: usize = 0;
+
+        let mut put = |x: usize, row: usize, value: u8| {
+            if x < width && row < height {
+                image.set(0, x, height - 1 - row, value as u16);
+            }
+        };
+
+        loop {
+            // alguns arquivos terminam sem o marcador de fim de imagem
+            let (count, value) = match data.get(position..position + 2) {
+                Some(pair) => (pair[0] as usize, pair[1]),
+                None if position >= data.len() => break,
+                None => return Err(Error::InvalidImage("fluxo RLE truncado")),
+            };
+            position += 2;
+
+            if count > 0 {
+                for i in 0..count {
+                    let pixel = match (four_bits, i % 2) {
+                        (false, _) => value,
+                        (true, 0) => value >> 4,
+                        (true, _) => value & 0x0F,
+                    };
+                    put(x, row, pixel);
+                    x += 1;
+                }
+                continue;
+            }
+
+            match value {
+                0 => {
+                    x = 0;
+                    row += 1;
+                }
+                1 => break,
+                2 => {
+                    let delta = match data.get(position..position + 2) {
+                        Some(delta) => delta,
+                        None => return Err(Error::InvalidImage("fluxo RLE truncado")),
+                    };
+                    x += delta[0] as usize;
+                    row += delta[1] as usize;
+                    position += 2;
+                }
+                count => {
+                    let count = count as usize;
+                    let len = if four_bits { count.div_ceil(2) } else { count };
+                    let literal = match data.get(position..position + len) {
+                        Some(literal) => literal,
+                        None => return Err(Error::InvalidImage("fluxo RLE truncado")),
+                    };
+                    for i in 0..count {
+                        let pixel = match (four_bits, i % 2) {
+                            (false, _) => literal[i],
+                            (true, 0) => literal[i / 2] >> 4,
+                            (true, _) => literal[i / 2] & 0x0F,
+                        };
+                        put(x, row, pixel);
+                        x += 1;
+                    }
+                    // sequências absolutas são alinhadas em 16 bits
+                    position += len + len % 2;
+                }
+            }
+        }
+
+        Ok(position.min(data.len()))
+    }
+
+    // codificação RLE canônica: repetições de dois ou mais pixels iguais, sequências absolutas
+    // para trechos de pelo menos três pixels distintos, fim de linha e fim de imagem
+    fn write_rle(&self, image: &Image) -> Vec<u8> {
+        let four_bits = self.compression == BI_RLE4;
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let mut output: Vec<u8> = Vec::new();
+
+        for row in 0..height {
+            let y = height - 1 - row;
+            let pixels: Vec<u8> = (0..width).map(|x| image.get(0, x, y) as u8).collect();
+            let mut x = 0;
+            while x < width {
+                let run = pixels[x..].iter().take(255).take_while(|&&p| p == pixels[x]).count();
+                if run >= 2 {
+                    output.push(run as u8);
+                    output.push(if four_bits { pixels[x] << 4 | pixels[x] } else { pixels[x] });
+                    x += run;
+                    continue;
+                }
+
+                let mut end = x + 1;
+                while end < width && end - x < 255 && (end + 1 >= width || pixels[end] != pixels[end + 1]) {
+                    end += 1;
+                }
+                let literal = &pixels[x..end];
+                if literal.len() < 3 {
+                    for &pixel in literal {
+                        output.push(1);
+                        output.push(if four_bits { pixel << 4 } else { pixel });
+                    }
+                } else {
+                    output.push(0);
+                    output.push(literal.len() as u8);
+                    let start = output.len();
+                    if four_bits {
+                        for pair in literal.chunks(2) {
+                            output.push(pair[0] << 4 | pair.get(1).copied().unwrap_or(0));
+                        }
+                    } else {
+                        output.extend_from_slice(literal);
+                    }
+                    if (output.len() - start) % 2 == 1 {
+                        output.push(0);
+                    }
+                }
+                x = end;
+            }
+            output.push(0);
+            output.push(if row + 1 == height { 1 } else { 0 });
+        }
+
+        output
+    }
+}
+
+// bytes de um BMP que não são amostras de pixels, suficientes para reconstruir o arquivo
+// original a partir dos planos
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BmpShell {
+    // cabeçalhos, máscaras, paleta e bytes até o início da região de pixels
+    pub header: Vec<u8>,
+    // região de pixels original, quando não é reproduzida a partir dos planos
+    pub pixel_data: Option<Vec<u8>>,
+    // bytes de preenchimento e bits sem uso diferentes de zero (posição na região, valor)
+    pub fixups: Vec<(u32, u8)>,
+    // bytes após a região de pixels
+    pub trailer: Vec<u8>,
+}
+
+pub fn split(bytes: &[u8]) -> Result<(BmpShell, Image)> {
+    let bmp = Bmp::parse(bytes)?;
+    let (image, len) = bmp.read_image(bytes)?;
+    let region = &bytes[bmp.pixel_offset..bmp.pixel_offset + len];
+
+    let generated = bmp.write_pixels(&image)?;
+    let mut pixel_data: Option<Vec<u8>> = None;
+    let mut fixups: Vec<(u32, u8)> = Vec::new();
+    if bmp.is_compressed() {
+        if generated != region {
+            pixel_data = Some(region.to_vec());
+        }
+    } else {
+        for (position, (&original, &generated)) in region.iter().zip(generated.iter()).enumerate() {
+            if original != generated {
+                fixups.push((position as u32, original));
+            }
+        }
+    }
+
+    let shell = BmpShell {
+        header: bytes[..bmp.pixel_offset].to_vec(),
+        pixel_data,
+        fixups,
+        trailer: bytes[bmp.pixel_offset + len..].to_vec(),
+    };
+    Ok((shell, image))
+}
+
+pub fn join(shell: &BmpShell, image: &Image) -> Result<Vec<u8>> {
+    let bmp = Bmp::parse(&shell.header)?;
+    let mut pixels = match &shell.pixel_data {
+        Some(pixel_data) => pixel_data.clone(),
+        None => bmp.write_pixels(image)?,
+    };
+    for &(position, value) in shell.fixups.iter() {
+        match pixels.get_mut(position as usize) {
+            Some(pixel) => *pixel = value,
+            None => return Err(Error::InvalidImage("correção fora da região de pixels")),
+        }
+    }
+
+    let mut output = Vec::with_capacity(shell.header.len() + pixels.len() + shell.trailer.len());
+    output.extend_from_slice(&shell.header);
+    output.extend_from_slice(&pixels);
+    output.extend_from_slice(&shell.trailer);
+    Ok(output)
+}
+
+// grava uma imagem menor, como um recorte, no mesmo formato de um BMP original: o cabeçalho,
+// com máscaras e paleta, é copiado com as dimensões e os tamanhos ajustados
+pub fn write_resized(header: &[u8], image: &Image) -> Result<Vec<u8>> {
+    let original = Bmp::parse(header)?;
+    let mut header = match header.get(..original.pixel_offset) {
+        Some(header) => header.to_vec(),
+        None => return Err(Error::InvalidImage("cabeçalho BMP incompleto")),
+    };
+    if original.info_header_len == CORE_HEADER_LEN {
+        let (width, height) = match (u16::try_from(image.width), u16::try_from(image.height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(Error::InvalidImage("dimensões muito grandes")),
+        };
+        header[18..20].copy_from_slice(&width.to_le_bytes());
+        header[20..22].copy_from_slice(&height.to_le_bytes());
+    } else {
+        let (width, height) = match (i32::try_from(image.width), i32::try_from(image.height)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => return Err(Error::InvalidImage("dimensões muito grandes")),
+        };
+        let height = if original.top_down { -height } else { height };
+        header[18..22].copy_from_slice(&width.to_le_bytes());
+        header[22..26].copy_from_slice(&height.to_le_bytes());
+    }
+
+    let bmp = Bmp::parse(&header)?;
+    let pixels = bmp.write_pixels(image)?;
+    let file_len = match u32::try_from(header.len() + pixels.len()) {
+        Ok(len) => len,
+        Err(_) => return Err(Error::InvalidImage("dimensões muito grandes")),
+    };
+    header[2..6].copy_from_slice(&file_len.to_le_bytes());
+    // o tamanho da região de pixels é opcional em BI_RGB, mas obrigatório em RLE
+    if bmp.info_header_len != CORE_HEADER_LEN && (bmp.size_image != 0 || bmp.is_compressed()) {
+        header[34..38].copy_from_slice(&(pixels.len() as u32).to_le_bytes());
+    }
+
+    let mut output = header;
+    output.extend_from_slice(&pixels);
+    Ok(output)
+}
+
+// grava um BMP simples (BITMAPINFOHEADER, 24 bits, ou 32 bits quando há alfa) a partir dos
+// planos B, G, R e A, com as amostras levadas a 8 bits; imagens com menos de três planos são
+// gravadas em tons de cinza a partir do primeiro
+pub fn write_rgb(image: &Image) -> Result<Vec<u8>> {
+    let bytes_per_pixel = if image.planes.len() >= 4 { 4 } else { 3 };
+    let row_size = (image.width * bytes_per_pixel).div_ceil(4) * 4;
+    let pixel_len = match row_size.checked_mul(image.height) {
+        Some(len) if len <= u32::MAX as usize - FILE_HEADER_LEN - INFO_HEADER_LEN as usize => len,
+        _ => return Err(Error::InvalidImage("dimensões muito grandes")),
+    };
+    let pixel_offset = FILE_HEADER_LEN + INFO_HEADER_LEN as usize;
+
+    let mut output = Vec::with_capacity(pixel_offset + pixel_len);
+    output.extend_from_slice(b"BM");
+    output.extend_from_slice(&((pixel_offset + pixel_len) as u32).to_le_bytes());
+    output.extend_from_slice(&0u32.to_le_bytes());
+    output.extend_from_slice(&(pixel_offset as u32).to_le_bytes());
+    output.extend_from_slice(&INFO_HEADER_LEN.to_le_bytes());
+    output.extend_from_slice(&(image.width as i32).to_le_bytes());
+    output.extend_from_slice(&(image.height as i32).to_le_bytes());
+    output.extend_from_slice(&1u16.to_le_bytes());
+    output.extend_from_slice(&(bytes_per_pixel as u16 * 8).to_le_bytes());
+    output.extend_from_slice(&BI_RGB.to_le_bytes());
+    output.extend_from_slice(&(pixel_len as u32).to_le_bytes());
+    output.extend_from_slice(&2835i32.to_le_bytes());
+    output.extend_from_slice(&2835i32.to_le_bytes());
+    output.extend_from_slice(&0u32.to_le_bytes());
+    output.extend_from_slice(&0u32.to_le_bytes());
+
+    let channels: Vec<usize> = if image.planes.len() >= 3 {
+        (0..bytes_per_pixel).collect()
+    } else {
+        vec![0; 3]
+    };
+    for y in (0..image.height).rev() {
+        let start = output.len();
+        for x in 0..image.width {
+            for &plane in channels.iter() {
+                let max = image.planes[plane].max_value() as u32;
+                let value = image.get(plane, x, y) as u32;
+                output.push(((value * 255 + max / 2) / max) as u8);
+            }
+        }
+        output.resize(start + row_size, 0);
+    }
+    Ok(output)
+}
+
+// máscaras devem ser não nulas, contíguas, disjuntas e caber no pixel
+fn validate_masks(masks: &[u32], bits_per_pixel: u16) -> Result<()> {
+    let mut used: u32 = 0;
+    for &mask in masks {
+        let shifted = mask.checked_shr(mask.trailing_zeros()).unwrap_or(0);
+        let contiguous = mask != 0 && shifted & shifted.wrapping_add(1) == 0;
+        let fits = bits_per_pixel == 32 || mask >> bits_per_pixel == 0;
+        if !contiguous || !fits || used & mask != 0 || mask.count_ones() > 16 {
+            return Err(Error::InvalidImage("máscaras de cor inválidas"));
+        }
+        used |= mask;
+    }
+    Ok(())
+}
+
+pub fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+pub fn read_i32(bytes: &[u8], offset: usize) -> i32 {
+    read_u32(bytes, offset) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        bilevel_bmp,
+        bmp,
+        bmp_header,
+    };
+
+    fn round_trip(bytes: &[u8]) -> (BmpShell, Image) {
+        let (shell, image) = split(bytes).unwrap();
+        assert_eq!(join(&shell, &image).unwrap(), bytes);
+        (shell, image)
+    }
+
+    #[test]
+    fn layout_is_read_from_the_headers() {
+        let bmp = Bmp::parse(&bmp(13, 7)).unwrap();
+        assert_eq!((bmp.width, bmp.height, bmp.top_down, bmp.bytes_per_pixel()), (13, 7, false, 3));
+        assert_eq!((bmp.pixel_offset, bmp.row_size, bmp.pixel_len()), (54, 40, 280));
+
+        let palette: Vec<[u8; 3]> = (0..=255).map(|index| [index, index, index]).collect();
+        let mut bytes = bmp_header(6, 4, 8, 8 * 4, &palette);
+        bytes[22..26].copy_from_slice(&(-4i32).to_le_bytes());
+        let bmp = Bmp::parse(&bytes).unwrap();
+        assert_eq!((bmp.height, bmp.top_down, bmp.pixel_offset, bmp.row_size), (4, true, 54 + 1024, 8));
+        assert_eq!((bmp.palette.len(), bmp.palette[7]), (256, [7, 7, 7]));
+    }
+
+    #[test]
+    fn rgb_and_bilevel_round_trip() {
+        let (shell, image) = round_trip(&bmp(13, 7));
+        assert_eq!(image.depths(), vec![8, 8, 8]);
+        assert!(shell.fixups.is_empty() && shell.pixel_data.is_none());
+
+        let (_, image) = round_trip(&bilevel_bmp(37, 20));
+        assert_eq!(image.depths(), vec![1]);
+        // o centro do círculo é preto e os cantos são brancos
+        assert_eq!((image.get(0, 18, 10), image.get(0, 0, 0)), (0, 1));
+    }
+
+    // bytes de preenchimento diferentes de zero e dados após os pixels são preservados
+    #[test]
+    fn padding_and_trailer_are_preserved() {
+        let mut bytes = bmp(5, 3);
+        let row_size = 16;
+        bytes[54 + row_size - 1] = 0xAB;
+        bytes.extend_from_slice(b"trailer");
+        let (shell, _) = round_trip(&bytes);
+        assert_eq!(shell.fixups, vec![(row_size as u32 - 1, 0xAB)]);
+        assert_eq!(shell.trailer, b"trailer");
+    }
+
+    #[test]
+    fn top_down_palette_round_trip() {
+        let palette: Vec<[u8; 3]> = (0..=255).map(|index| [index, index, index]).collect();
+        let mut bytes = bmp_header(6, 4, 8, 8 * 4, &palette);
+        bytes[22..26].copy_from_slice(&(-4i32).to_le_bytes());
+        for row in 0..4u8 {
+            bytes.extend((0..8).map(|x| row * 16 + x));
+        }
+        let (_, image) = round_trip(&bytes);
+        assert_eq!(image.get(0, 5, 0), 5);
+        assert_eq!(image.get(0, 0, 3), 48);
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let palette = [[0, 0, 0], [255, 255, 255], [255, 0, 0]];
+        let canonical = [4, 1, 0, 3, 0, 1, 2, 0, 0, 0, 2, 2, 5, 0, 0, 1];
+        let mut bytes = bmp_header(7, 2, 8, canonical.len(), &palette);
+        bytes[30..34].copy_from_slice(&BI_RLE8.to_le_bytes());
+        bytes.extend_from_slice(&canonical);
+        let (shell, image) = round_trip(&bytes);
+        assert!(shell.pixel_data.is_none());
+        assert_eq!((0..7).map(|x| image.get(0, x, 1)).collect::<Vec<_>>(), [1, 1, 1, 1, 0, 1, 2]);
+        assert_eq!((0..7).map(|x| image.get(0, x, 0)).collect::<Vec<_>>(), [2, 2, 0, 0, 0, 0, 0]);
+
+        // um fluxo que o codificador canônico não gera é guardado como está
+        let mut bytes = bmp_header(7, 2, 8, 8, &palette);
+        bytes[30..34].copy_from_slice(&BI_RLE8.to_le_bytes());
+        bytes.extend_from_slice(&[1, 1, 1, 1, 0, 0, 0, 1]);
+        let (shell, _) = round_trip(&bytes);
+        assert!(shell.pixel_data.is_some());
+    }
+
+    #[test]
+    fn rgb_copies_keep_the_samples() {
+        let (_, image) = split(&bmp(9, 5)).unwrap();
+        let rgb = write_rgb(&image).unwrap();
+        assert_eq!(split(&rgb).unwrap().1.planes, image.planes);
+        let (_, gray) = split(&bilevel_bmp(7, 3)).unwrap();
+        assert!(write_rgb(&gray).is_ok());
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert!(matches!(split(b"BM"), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(b"PK\x03\x04 not a bitmap at all"), Err(Error::InvalidImage(_))));
+
+        let bytes = bmp(4, 4);
+        assert!(matches!(split(&bytes[..40]), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(&bytes[..bytes.len() - 1]), Err(Error::InvalidImage(_))));
+
+        let mut unknown_header = bytes.clone();
+        unknown_header[14..18].copy_from_slice(&64u32.to_le_bytes());
+        assert!(matches!(split(&unknown_header), Err(Error::UnsupportedImage(_))));
+
+        let mut zero_width = bytes.clone();
+        zero_width[18..22].copy_from_slice(&0i32.to_le_bytes());
+        assert!(matches!(split(&zero_width), Err(Error::InvalidImage(_))));
+
+        let mut truncated_rle = bmp_header(4, 1, 8, 3, &[[0, 0, 0]]);
+        truncated_rle[30..34].copy_from_slice(&BI_RLE8.to_le_bytes());
+        truncated_rle.extend_from_slice(&[0, 5, 1]);
+        assert!(matches!(split(&truncated_rle), Err(Error::InvalidImage(_))));
+    }
+
+    #[test]
+    fn resized_copies_keep_the_format() {
+        let bytes = bmp(9, 9);
+        let (shell, image) = split(&bytes).unwrap();
+        let mut small = Image::new(3, 2, &image.depths()).unwrap();
+        small.set(1, 2, 1, 200);
+        let resized = write_resized(&shell.header, &small).unwrap();
+        let (_, read) = split(&resized).unwrap();
+        assert_eq!((read.width, read.height, read.get(1, 2, 1)), (3, 2, 200));
+    }
+}
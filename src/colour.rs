@@ -0,0 +1,141 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::image::Image;
+use crate::Error;
+use crate::Result;
+
+// transformação de cor reversível aplicada aos planos B, G e R antes da predição
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColourTransform {
+    #[default]
+    None,
+    YCoCgR,
+    Rct,
+}
+
+// os três primeiros planos (B, G, R) são substituídos por luminância e duas diferenças de
+// cor; as diferenças ocupam um bit a mais e são gravadas somadas a 2^profundidade
+pub fn transformed_depths(transform: ColourTransform, depths: &[u8]) -> Result<Vec<u8>> {
+    if transform == ColourTransform::None {
+        return Ok(depths.to_vec());
+    }
+    match depths {
+        [blue, green, red, ..] if blue == green && green == red && *blue < 16 => {
+            let mut transformed = depths.to_vec();
+            transformed[1] += 1;
+            transformed[2] += 1;
+            Ok(transformed)
+        }
+        _ => Err(Error::UnsupportedImage("a transformação de cor requer canais B, G e R de mesma profundidade, até 15 bits")),
+    }
+}
+
+pub fn forward(transform: ColourTransform, image: &Image) -> Result<Image> {
+    if transform == ColourTransform::None {
+        return Ok(image.clone());
+    }
+    let depths = transformed_depths(transform, &image.depths())?;
+    let mut output = Image::new(image.width, image.height, &depths)?;
+    for (plane, samples) in output.planes.iter_mut().zip(image.planes.iter()).skip(3) {
+        plane.samples.copy_from_slice(&samples.samples);
+    }
+
+    let offset = 1i32 << image.planes[0].depth;
+    for index in 0..image.planes[0].samples.len() {
+        let blue = image.planes[0].samples[index] as i32;
+        let green = image.planes[1].samples[index] as i32;
+        let red = image.planes[2].samples[index] as i32;
+        let (luma, first, second) = match transform {
+            ColourTransform::YCoCgR => {
+                let co = red - blue;
+                let t = blue + (co >> 1);
+                let cg = green - t;
+                (t + (cg >> 1), co, cg)
+            }
+            ColourTransform::Rct => ((red + 2 * green + blue) >> 2, blue - green, red - green),
+            ColourTransform::None => unreachable!(),
+        };
+        output.planes[0].samples[index] = luma as u16;
+        output.planes[1].samples[index] = (first + offset) as u16;
+        output.planes[2].samples[index] = (second + offset) as u16;
+    }
+    Ok(output)
+}
+
+// `depths` são as profundidades originais, gravadas no cabeçalho
+pub fn inverse(transform: ColourTransform, image: &Image, depths: &[u8]) -> Result<Image> {
+    if transformed_depths(transform, depths)? != image.depths() {
+        return Err(Error::InvalidImage("planos incompatíveis com a transformação de cor"));
+    }
+    if transform == ColourTransform::None {
+        return Ok(image.clone());
+    }
+    let mut output = Image::new(image.width, image.height, depths)?;
+    for (plane, samples) in output.planes.iter_mut().zip(image.planes.iter()).skip(3) {
+        plane.samples.copy_from_slice(&samples.samples);
+    }
+
+    // amostras decodificadas de um arquivo corrompido podem sair do intervalo válido
+    let offset = 1i32 << depths[0];
+    let max = offset - 1;
+    for index in 0..image.planes[0].samples.len() {
+        let luma = image.planes[0].samples[index] as i32;
+        let first = image.planes[1].samples[index] as i32 - offset;
+        let second = image.planes[2].samples[index] as i32 - offset;
+        let (blue, green, red) = match transform {
+            ColourTransform::YCoCgR => {
+                let t = luma - (second >> 1);
+                let green = second + t;
+                let blue = t - (first >> 1);
+                (blue, green, blue + first)
+            }
+            ColourTransform::Rct => {
+                let green = luma - ((first + second) >> 2);
+                (first + green, green, second + green)
+            }
+            ColourTransform::None => unreachable!(),
+        };
+        output.planes[0].samples[index] = blue.clamp(0, max) as u16;
+        output.planes[1].samples[index] = green.clamp(0, max) as u16;
+        output.planes[2].samples[index] = red.clamp(0, max) as u16;
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::photo;
+
+    // inclusive nos extremos do intervalo, onde as diferenças de cor ocupam o bit a mais
+    #[test]
+    fn transforms_are_reversible() {
+        for depths in [&[8, 8, 8][..], &[8, 8, 8, 8], &[15, 15, 15]] {
+            let mut image = photo(9, 7, depths);
+            let max = image.planes[0].max_value();
+            for (plane, value) in [(0, 0), (1, max), (2, 0)] {
+                image.set(plane, 0, 0, value);
+            }
+            for (plane, value) in [(0, max), (1, 0), (2, max)] {
+                image.set(plane, 1, 0, value);
+            }
+            for transform in [ColourTransform::None, ColourTransform::YCoCgR, ColourTransform::Rct] {
+                let transformed = forward(transform, &image).unwrap();
+                assert_eq!(transformed.depths(), transformed_depths(transform, depths).unwrap());
+                assert_eq!(inverse(transform, &transformed, depths).unwrap().planes, image.planes);
+            }
+        }
+    }
+
+    #[test]
+    fn incompatible_planes_are_rejected() {
+        for depths in [&[8, 8][..], &[8, 5, 8], &[16, 16, 16]] {
+            assert!(matches!(forward(ColourTransform::Rct, &photo(2, 2, depths)), Err(Error::UnsupportedImage(_))));
+        }
+        let transformed = forward(ColourTransform::YCoCgR, &photo(2, 2, &[8, 8, 8])).unwrap();
+        assert!(matches!(inverse(ColourTransform::YCoCgR, &transformed, &[7, 7, 7]), Err(Error::InvalidImage(_))));
+    }
+}
@@ -0,0 +1,1551 @@
+use bincode::Options;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::io::Write;
+
+use crate::audio;
+use crate::bilevel;
+use crate::bitplane;
+use crate::bmp;
+use crate::bmp::{
+    Bmp,
+    BmpShell,
+};
+use crate::colour;
+use crate::dct;
+use crate::decoder::ArithmeticDecoder;
+use crate::delta;
+use crate::delta::DeltaOptions;
+use crate::dpcm;
+use crate::dump;
+use crate::dump::Dump;
+use crate::encoder::{
+    ArithmeticEncoder,
+    EncodedSizes,
+};
+use crate::float;
+use crate::float::FloatOptions;
+use crate::histogram;
+use crate::image::Image;
+use crate::jpegls;
+use crate::limits::DecodeLimits;
+use crate::metadata::FileMetadata;
+use crate::model;
+use crate::netpbm;
+use crate::netpbm::Netpbm;
+use crate::palette;
+use crate::predictive;
+use crate::predictive::ImageOptions;
+use crate::progressive;
+use crate::sequence;
+use crate::tiles;
+use crate::tiles::{
+    Region,
+    Segments,
+};
+use crate::ArithmeticCoding;
+use crate::wav;
+use crate::wav::{
+    Wav,
+    WavShell,
+};
+use crate::wavelet;
+use crate::Error;
+use crate::Result;
+
+// identifica arquivos com cabeçalho; arquivos sem ele são lidos no formato antigo
+pub const MAGIC: &[u8; 5] = b"PIDAC";
+pub const VERSION: u8 = 1;
+
+// tamanho da região codificada (u64) seguido da quantidade de dígitos do último valor (u8)
+pub const TRAILER_LEN: usize = size_of::<u64>() + size_of::<u8>();
+
+// transformação aplicada aos dados antes da codificação aritmética
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    #[default]
+    Bytes,
+    Dpcm,
+    Image(ImageOptions),
+    // estilo JPEG-LS; NEAR é o maior erro absoluto permitido em cada amostra (0 sem perdas)
+    JpegLs { near: u16 },
+    // com perdas, estilo JPEG: YCbCr, DCT 8x8 e tabelas de quantização escaladas por `quality`
+    Dct { quality: u8 },
+    // sem perdas, com a wavelet 5/3 de LeGall em `levels` níveis
+    Wavelet { levels: u8 },
+    // sem perdas, em planos de bits do mais significativo para o menos significativo
+    BitPlanes,
+    // sem perdas, para imagens de 1 bit, com contextos de 16 vizinhos como no JBIG2
+    Bilevel,
+    // como o modo image, em blocos de `size` x `size` pixels codificados independentemente,
+    // o que permite decodificar apenas uma região
+    Tiled { size: u32, options: ImageOptions },
+    // sem perdas, em passadas que dobram a resolução, como no Adam7, para que o início do
+    // arquivo já forneça uma prévia do tamanho original
+    Progressive { levels: u8 },
+    // sem perdas, para sequências de quadros de mesmas dimensões: cada quadro é previsto pelo
+    // anterior, com um quadro-chave, previsto apenas pelos vizinhos, a cada `key_interval`
+    Sequence { key_interval: u32, motion: bool },
+    // sem perdas, para arquivos WAV PCM: cada canal, ou o meio e o lado em estéreo, é previsto
+    // em blocos por um preditor polinomial fixo ou por LPC
+    Wav,
+    // bytes lidos como elementos inteiros de mesma largura, substituídos pela diferença de
+    // primeira ou segunda ordem, com um modelo adaptativo por byte do elemento
+    Delta(DeltaOptions),
+    // séries de f32 ou f64: cada valor é previsto pelos anteriores, como no FPC, e o XOR dos
+    // padrões de bits é codificado com contextos
+    Float(FloatOptions),
+}
+
+// formato do arquivo de imagem lido nos modos de imagem
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImageFormat {
+    #[default]
+    Bmp,
+    // P5 e P6
+    Netpbm,
+}
+
+// dimensões dos planos e bytes do arquivo que não são amostras, gravados nos modos de imagem
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImageHeader {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub depths: Vec<u8>,
+    // nos arquivos Netpbm, `header` guarda o cabeçalho com os comentários
+    pub shell: BmpShell,
+    // mapa de bits dos valores usados em cada plano, quando o histograma é compactado
+    pub used_values: Vec<Vec<u8>>,
+    // no modo com blocos, a posição do trecho de cada bloco na região codificada
+    pub tile_offsets: Vec<u64>,
+    // nos modos bitplane e progressivo, a posição do fim do trecho de cada passada (um plano
+    // de bits de um canal, ou uma passada do entrelaçamento) na região codificada
+    pub pass_ends: Vec<u64>,
+    // nos modos bitplane e progressivo, a estrutura do codificador, que também fica no final
+    // do arquivo, para que um arquivo truncado possa ser decodificado
+    pub coder: Option<ArithmeticCoding>,
+    // no modo sequence, os bytes que não são amostras de cada quadro, ausentes quando iguais
+    // aos do primeiro, guardados em `shell`
+    pub frames: Vec<Option<BmpShell>>,
+    // no modo sequence, a posição do trecho de cada quadro-chave na região codificada
+    pub key_offsets: Vec<u64>,
+}
+
+// formato das amostras e bytes do arquivo que não são amostras, gravados no modo wav
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AudioHeader {
+    pub channels: u16,
+    pub bits: u16,
+    pub frames: u64,
+    pub shell: WavShell,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Header {
+    pub mode: Mode,
+    pub metadata: Option<FileMetadata>,
+    pub image: Option<ImageHeader>,
+    pub audio: Option<AudioHeader>,
+    // tamanho do arquivo original nos modos delta e float
+    pub data_len: Option<u64>,
+}
+
+// medidas da codificação que interessam a quem a pediu: os tamanhos gravados e a distorção
+// dos modos com perdas
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EncodeReport {
+    pub sizes: EncodedSizes,
+    // no modo jpegls, o maior erro absoluto entre as amostras originais e as reconstruídas
+    pub max_error: Option<u16>,
+    // no modo dct, o PSNR, em dB, da imagem que o decodificador reconstruirá
+    pub psnr: Option<f64>,
+}
+
+pub struct Container<'a> {
+    pub header: Header,
+    pub ac: ArithmeticCoding,
+    pub encoded_data: &'a [u8],
+    pub last_value_shifts: u32,
+}
+
+impl<'a> Container<'a> {
+    // separa as regiões de um arquivo `.ac` sem confiar em nenhum dos tamanhos gravados
+    pub fn parse(input: &'a [u8], limits: &DecodeLimits) -> Result<Self> {
+        let (header, input) = match input.strip_prefix(MAGIC) {
+            Some(rest) => read_header(rest, limits)?,
+            None => (Header::default(), input),
+        };
+
+        let (body, trailer) = match input.len().checked_sub(TRAILER_LEN) {
+            Some(position) => input.split_at(position),
+            None => return Err(Error::InvalidContainer("arquivo muito curto")),
+        };
+
+        let mut encoded_data_len_buffer: [u8; 8] = [0; 8];
+        encoded_data_len_buffer.copy_from_slice(&trailer[..size_of::<u64>()]);
+        let encoded_data_len = u64::from_le_bytes(encoded_data_len_buffer);
+        let last_value_shifts = trailer[size_of::<u64>()] as u32;
+
+        if encoded_data_len > body.len() as u64 {
+            return Err(Error::InvalidContainer("tamanho dos dados codificados inválido"));
+        }
+        let (encoded_data, model) = body.split_at(encoded_data_len as usize);
+
+        // cada entrada da tabela ocupa 9 bytes no arquivo e 16 bytes em memória
+        limits.check_model(model.len() as u64)?;
+        limits.check_memory((model.len() / 9 * size_of::<(u8, u64)>()) as u64)?;
+
+        let ac: ArithmeticCoding = deserialize_bounded(model)?;
+
+        Ok(Self {
+            header,
+            ac,
+            encoded_data,
+            last_value_shifts,
+        })
+    }
+
+    pub fn decode<W: Write>(self, limits: &DecodeLimits, mut output: W) -> Result<()> {
+        self.check_samples()?;
+        // cada bloco tem seu próprio decodificador
+        if let Mode::Tiled { size, options } = self.header.mode {
+            let header = image_header(&self.header)?;
+            check_image(header, limits, tiles_memory_len(header, size, &options)?)?;
+            let region = Region::new(0, 0, header.width as usize, header.height as usize);
+            let image = self.decode_tiles(&region)?;
+            output.write_all(&join_image(header, &image)?)?;
+            output.flush()?;
+            return Ok(());
+        }
+        if let Mode::Sequence { .. } = self.header.mode {
+            return Err(Error::UnsupportedImage("o modo sequence requer a escolha dos quadros"));
+        }
+        // cada passada também
+        if let Mode::BitPlanes | Mode::Progressive { .. } = self.header.mode {
+            let header = image_header(&self.header)?;
+            let (image, passes) = decode_passes(&self.header, &self.ac, self.encoded_data, limits, 0)?;
+            if passes < header.pass_ends.len() {
+                return Err(Error::InvalidContainer("dados codificados incompletos"));
+            }
+            output.write_all(&join_image(header, &image)?)?;
+            output.flush()?;
+            return Ok(());
+        }
+
+        let total = self.ac.symbols.total;
+        let encoded_len = self.encoded_data.len();
+
+        let mut decoder = ArithmeticDecoder::new(
+            self.ac,
+            self.encoded_data,
+            self.last_value_shifts,
+        )?;
+
+        match self.header.mode {
+            Mode::Bytes => {
+                // cada símbolo decodificado produz um byte de saída
+                limits.check_output(total)?;
+                decoder.decode(output)
+            }
+            Mode::Dpcm => {
+                limits.check_output(total)?;
+                // resíduos e imagem reconstruída ficam em memória ao mesmo tempo
+                limits.check_memory(total.saturating_mul(2))?;
+                let mut residuals: Vec<u8> = Vec::new();
+                decoder.decode(&mut residuals)?;
+                output.write_all(&dpcm::inverse(&residuals)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Image(options) => {
+                let header = image_header(&self.header)?;
+                let depths = colour::transformed_depths(options.transform, &header.depths)?;
+                let packed_depths = histogram::packed_depths(options.packed, &depths, &header.used_values)?;
+                let mut image = allocate_image(header, &packed_depths, limits, predictive::models_memory_len(&packed_depths, &options))?;
+                predictive::decode(&mut decoder, &mut image, &options)?;
+                let image = histogram::inverse(options.packed, &image, &header.used_values, &depths)?;
+                let image = palette::inverse(options.palette, image_palette(header)?.as_deref(), &image)?;
+                let image = colour::inverse(options.transform, &image, &header.depths)?;
+                output.write_all(&join_image(header, &image)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::JpegLs { near } => {
+                let header = image_header(&self.header)?;
+                let mut image = allocate_image(header, &header.depths, limits, jpegls::models_memory_len(&header.depths, near))?;
+                jpegls::decode(&mut decoder, &mut image, near)?;
+                output.write_all(&join_image(header, &image)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Dct { quality } => {
+                let header = image_header(&self.header)?;
+                let memory_len = dct::memory_len(header.width as usize, header.height as usize, header.depths.len());
+                let mut image = allocate_image(header, &header.depths, limits, memory_len)?;
+                dct::decode(&mut decoder, &mut image, quality)?;
+                output.write_all(&join_image(header, &image)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Wavelet { levels } => {
+                let header = image_header(&self.header)?;
+                let width = header.width as usize;
+                let height = header.height as usize;
+                check_image(header, limits, wavelet::memory_len(width, height, header.depths.len()))?;
+                let image = wavelet::decode(&mut decoder, width, height, &header.depths, levels, 0)?;
+                output.write_all(&join_image(header, &image)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Bilevel => {
+                let header = image_header(&self.header)?;
+                let mut image = allocate_image(header, &header.depths, limits, bilevel::memory_len())?;
+                bilevel::decode(&mut decoder, &mut image)?;
+                output.write_all(&join_image(header, &image)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Wav => {
+                let header = audio_header(&self.header)?;
+                let wav = validate_audio(header)?;
+                if header.frames > audio::max_frames(encoded_len, wav.channels) {
+                    return Err(Error::InvalidContainer("quantidade de quadros maior que a dos dados codificados"));
+                }
+                let data_len = header.frames.saturating_mul(wav.frame_len() as u64);
+                let output_len = (header.shell.header.len() as u64)
+                    .saturating_add(data_len)
+                    .saturating_add(header.shell.trailer.len() as u64);
+                limits.check_output(output_len)?;
+                // canais decodificados, amostras intercaladas e arquivo gravado
+                let memory_len = audio::memory_len(wav.channels, header.frames)
+                    .saturating_add(header.frames.saturating_mul(wav.channels as u64 * size_of::<i32>() as u64))
+                    .saturating_add(output_len);
+                limits.check_memory(memory_len)?;
+                let channels = audio::decode(&mut decoder, wav.channels, header.frames as usize, wav.bits)?;
+                output.write_all(&header.shell.header)?;
+                output.write_all(&wav.write_samples(&channels)?)?;
+                output.write_all(&header.shell.trailer)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Delta(options) => {
+                let len = data_len(&self.header)?;
+                if len > delta::max_len(encoded_len) {
+                    return Err(Error::InvalidContainer("tamanho dos dados maior que o dos dados codificados"));
+                }
+                limits.check_output(len)?;
+                // os bytes decodificados são usados na predição e gravados de uma vez
+                limits.check_memory(len.saturating_add(delta::memory_len(&options)))?;
+                output.write_all(&delta::decode(&mut decoder, len as usize, &options)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Float(options) => {
+                let len = data_len(&self.header)?;
+                options.validate()?;
+                if len > float::max_len(encoded_len, &options) {
+                    return Err(Error::InvalidContainer("tamanho dos dados maior que o dos dados codificados"));
+                }
+                limits.check_output(len)?;
+                limits.check_memory(len.saturating_add(float::memory_len(&options)))?;
+                output.write_all(&float::decode(&mut decoder, len as usize, &options)?)?;
+                output.flush()?;
+                Ok(())
+            }
+            Mode::Tiled { .. } | Mode::BitPlanes | Mode::Progressive { .. } | Mode::Sequence { .. } => unreachable!(),
+        }
+    }
+
+    // decodifica apenas os blocos que cruzam `region` e grava o recorte no formato do arquivo
+    // original, com um cabeçalho ajustado às dimensões do recorte
+    pub fn decode_region<W: Write>(self, limits: &DecodeLimits, region: Region, mut output: W) -> Result<()> {
+        let (size, options) = match self.header.mode {
+            Mode::Tiled { size, options } => (size, options),
+            _ => return Err(Error::UnsupportedImage("o recorte requer um arquivo do modo com blocos")),
+        };
+        self.check_samples()?;
+        let header = image_header(&self.header)?;
+        validate_image(header)?;
+        let inside = region.x.checked_add(region.width).is_some_and(|right| right <= header.width as usize)
+            && region.y.checked_add(region.height).is_some_and(|bottom| bottom <= header.height as usize);
+        if region.width == 0 || region.height == 0 || !inside {
+            return Err(Error::UnsupportedImage("recorte fora da imagem"));
+        }
+
+        // as amostras ocupam no arquivo no máximo 2 bytes, mais até 3 bytes de alinhamento por linha
+        let planes_len = Image::memory_len(region.width, region.height, header.depths.len());
+        let output_len = (header.shell.header.len() as u64)
+            .saturating_add(planes_len)
+            .saturating_add(3 * region.height as u64);
+        limits.check_output(output_len)?;
+        // planos decodificados, planos restaurados, arquivo gravado e memória dos blocos
+        let memory_len = planes_len
+            .saturating_mul(2)
+            .saturating_add(output_len)
+            .saturating_add(tiles_memory_len(header, size, &options)?);
+        limits.check_memory(memory_len)?;
+
+        let image = self.decode_tiles(&region)?;
+        let bytes = match header.format {
+            ImageFormat::Bmp => bmp::write_resized(&header.shell.header, &image)?,
+            ImageFormat::Netpbm => netpbm::write_resized(&header.shell.header, &image)?,
+        };
+        output.write_all(&bytes)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    // quantidade de quadros de um arquivo do modo sequence
+    pub fn frame_count(&self) -> Result<usize> {
+        match self.header.mode {
+            Mode::Sequence { .. } => Ok(image_header(&self.header)?.frames.len()),
+            _ => Err(Error::UnsupportedImage("a escolha de quadros requer um arquivo do modo sequence")),
+        }
+    }
+
+    // decodifica a partir do quadro-chave anterior a `first` e entrega a `write`, com seu índice,
+    // cada quadro de `first` a `last` no formato original
+    pub fn decode_frames<F: FnMut(usize, Vec<u8>) -> Result<()>>(&self, limits: &DecodeLimits, first: usize, last: usize, mut write: F) -> Result<()> {
+        let (key_interval, motion) = match self.header.mode {
+            Mode::Sequence { key_interval, motion } if key_interval > 0 => (key_interval as usize, motion),
+            Mode::Sequence { .. } => return Err(Error::InvalidContainer("intervalo entre quadros-chave inválido")),
+            _ => return Err(Error::UnsupportedImage("a escolha de quadros requer um arquivo do modo sequence")),
+        };
+        self.check_samples()?;
+        let header = image_header(&self.header)?;
+        if header.key_offsets.len() != header.frames.len().div_ceil(key_interval) {
+            return Err(Error::InvalidContainer("índice de quadros-chave inválido"));
+        }
+        if first > last || last >= header.frames.len() {
+            return Err(Error::UnsupportedImage("quadro inexistente"));
+        }
+        // quadro anterior, quadro atual e modelos
+        let models_len = sequence::Models::memory_len(&header.depths);
+        let frame_len = Image::memory_len(header.width as usize, header.height as usize, header.depths.len());
+        check_image(header, limits, models_len.saturating_add(frame_len))?;
+
+        let segments = Segments {
+            ac: &self.ac,
+            data: self.encoded_data,
+            offsets: &header.key_offsets,
+            last_value_shifts: self.last_value_shifts,
+        };
+        let mut decoder = segments.decoder(first / key_interval)?;
+        let mut models = sequence::Models::new(&header.depths, decoder.max_total())?;
+        let mut previous: Option<Image> = None;
+        for index in first / key_interval * key_interval..=last {
+            // cada quadro-chave começa um trecho novo, com modelos novos
+            if index % key_interval == 0 && previous.is_some() {
+                decoder = segments.decoder(index / key_interval)?;
+                models = sequence::Models::new(&header.depths, decoder.max_total())?;
+                previous = None;
+            }
+            let mut image = Image::new(header.width as usize, header.height as usize, &header.depths)?;
+            sequence::decode(&mut decoder, &mut models, &mut image, previous.as_ref(), motion)?;
+            if index >= first {
+                let frame = ImageHeader {
+                    format: header.format,
+                    width: header.width,
+                    height: header.height,
+                    depths: header.depths.clone(),
+                    shell: header.frames[index].as_ref().unwrap_or(&header.shell).clone(),
+                    ..ImageHeader::default()
+                };
+                validate_image(&frame)?;
+                write(index, join_image(&frame, &image)?)?;
+            }
+            previous = Some(image);
+        }
+        Ok(())
+    }
+
+    pub fn decode_frame<W: Write>(&self, limits: &DecodeLimits, index: usize, mut output: W) -> Result<()> {
+        self.decode_frames(limits, index, index, |_, bytes| {
+            output.write_all(&bytes)?;
+            output.flush()?;
+            Ok(())
+        })
+    }
+
+    // confere as dimensões gravadas com a quantidade de símbolos que os dados codificados podem
+    // conter, antes de qualquer alocação, mesmo sem limites: os modos de imagem codificam ao
+    // menos um símbolo por amostra ou, no modo dct, por bloco
+    fn check_samples(&self) -> Result<()> {
+        let header = match &self.header.image {
+            Some(header) => header,
+            None => return Ok(()),
+        };
+        let (width, height, planes) = (header.width as usize, header.height as usize, header.depths.len());
+        let symbols = match self.header.mode {
+            Mode::Dct { .. } => dct::blocks(width, height, planes),
+            _ => (width as u64).saturating_mul(height as u64).saturating_mul(planes as u64),
+        };
+        if symbols > model::max_symbols(self.encoded_data.len(), 2) {
+            return Err(Error::InvalidContainer("dimensões maiores que as dos dados codificados"));
+        }
+        Ok(())
+    }
+
+    // planos de `region` no modo com blocos, com as transformações do modo image desfeitas
+    fn decode_tiles(&self, region: &Region) -> Result<Image> {
+        let (size, options) = match self.header.mode {
+            Mode::Tiled { size, options } => (size, options),
+            _ => return Err(Error::InvalidContainer("modo sem blocos")),
+        };
+        let header = image_header(&self.header)?;
+        let depths = colour::transformed_depths(options.transform, &header.depths)?;
+        let packed_depths = histogram::packed_depths(options.packed, &depths, &header.used_values)?;
+        let segments = Segments {
+            ac: &self.ac,
+            data: self.encoded_data,
+            offsets: &header.tile_offsets,
+            last_value_shifts: self.last_value_shifts,
+        };
+        let image = tiles::decode(&segments, header.width as usize, header.height as usize, &packed_depths, size, &options, region)?;
+        let image = histogram::inverse(options.packed, &image, &header.used_values, &depths)?;
+        let image = palette::inverse(options.palette, image_palette(header)?.as_deref(), &image)?;
+        colour::inverse(options.transform, &image, &header.depths)
+    }
+
+    // lê apenas o início dos dados: no modo wavelet, sem os `skip` níveis mais finos, gravando
+    // a banda LL, com 1/2^skip das dimensões originais, como um BMP simples; no modo de planos
+    // de bits, sem os `skip` planos menos significativos, gravando uma aproximação do original
+    pub fn decode_preview<W: Write>(self, limits: &DecodeLimits, skip: u8, mut output: W) -> Result<()> {
+        if !matches!(self.header.mode, Mode::Wavelet { .. } | Mode::BitPlanes) {
+            return Err(Error::UnsupportedImage("a prévia requer um arquivo do modo wavelet ou bitplane"));
+        }
+        self.check_samples()?;
+        let header = image_header(&self.header)?;
+        if image_palette(header)?.is_some() {
+            return Err(Error::UnsupportedImage("a prévia requer imagem sem paleta"));
+        }
+
+        let preview = match self.header.mode {
+            Mode::Wavelet { levels } => {
+                let dimensions = wavelet::dimensions(header.width as usize, header.height as usize, levels.min(skip));
+                let (width, height) = dimensions[dimensions.len() - 1];
+                check_image(header, limits, wavelet::memory_len(width, height, header.depths.len()))?;
+                let mut decoder = ArithmeticDecoder::new(
+                    self.ac,
+                    self.encoded_data,
+                    self.last_value_shifts,
+                )?;
+                let image = wavelet::decode(&mut decoder, header.width as usize, header.height as usize, &header.depths, levels, skip)?;
+                bmp::write_rgb(&image)?
+            }
+            _ => {
+                let (image, passes) = decode_passes(&self.header, &self.ac, self.encoded_data, limits, skip)?;
+                join_passes(header, &image, passes)?
+            }
+        };
+        output.write_all(&preview)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+fn image_header(header: &Header) -> Result<&ImageHeader> {
+    match &header.image {
+        Some(header) => Ok(header),
+        None => Err(Error::InvalidContainer("cabeçalho de imagem ausente")),
+    }
+}
+
+fn data_len(header: &Header) -> Result<u64> {
+    match header.data_len {
+        Some(len) => Ok(len),
+        None => Err(Error::InvalidContainer("tamanho dos dados ausente")),
+    }
+}
+
+fn audio_header(header: &Header) -> Result<&AudioHeader> {
+    match &header.audio {
+        Some(header) => Ok(header),
+        None => Err(Error::InvalidContainer("cabeçalho de áudio ausente")),
+    }
+}
+
+// confere os chunks guardados, que devem terminar no início das amostras, com o formato gravado
+fn validate_audio(header: &AudioHeader) -> Result<Wav> {
+    let wav = Wav::parse(&header.shell.header)?;
+    if wav.data_offset != header.shell.header.len()
+        || wav.channels != header.channels as usize
+        || wav.bits != header.bits
+    {
+        return Err(Error::InvalidContainer("cabeçalho de áudio inválido"));
+    }
+    Ok(wav)
+}
+
+// paleta das imagens indexadas; arquivos Netpbm não têm paleta
+fn image_palette(header: &ImageHeader) -> Result<Option<Vec<[u8; 3]>>> {
+    match header.format {
+        ImageFormat::Bmp => {
+            let bmp = Bmp::parse(&header.shell.header)?;
+            Ok(bmp.is_indexed().then_some(bmp.palette))
+        }
+        ImageFormat::Netpbm => Ok(None),
+    }
+}
+
+// memória de um bloco decodificado e dos seus modelos, recriados a cada bloco
+fn tiles_memory_len(header: &ImageHeader, size: u32, options: &ImageOptions) -> Result<u64> {
+    let depths = colour::transformed_depths(options.transform, &header.depths)?;
+    let packed_depths = histogram::packed_depths(options.packed, &depths, &header.used_values)?;
+    let width = (size as usize).min(header.width as usize);
+    let height = (size as usize).min(header.height as usize);
+    Ok(Image::memory_len(width, height, depths.len()).saturating_add(predictive::models_memory_len(&packed_depths, options)))
+}
+
+// reconstrói o arquivo de imagem original a partir dos planos
+fn join_image(header: &ImageHeader, image: &Image) -> Result<Vec<u8>> {
+    match header.format {
+        ImageFormat::Bmp => bmp::join(&header.shell, image),
+        ImageFormat::Netpbm => netpbm::join(&header.shell, image),
+    }
+}
+
+// uma aproximação, sem todas as passadas, não corresponde ao fluxo RLE original guardado no
+// cabeçalho, então as amostras são gravadas com a compressão do módulo
+fn join_passes(header: &ImageHeader, image: &Image, passes: usize) -> Result<Vec<u8>> {
+    if passes == header.pass_ends.len() {
+        return join_image(header, image);
+    }
+    let header = ImageHeader {
+        shell: BmpShell {
+            pixel_data: None,
+            ..header.shell.clone()
+        },
+        ..header.clone()
+    };
+    join_image(&header, image)
+}
+
+// confere os limites antes de alocar os planos descritos no cabeçalho; `depths` são as
+// profundidades dos planos codificados e `models_len` a memória usada pelos modelos do modo
+fn allocate_image(header: &ImageHeader, depths: &[u8], limits: &DecodeLimits, models_len: u64) -> Result<Image> {
+    check_image(header, limits, models_len)?;
+    Image::new(header.width as usize, header.height as usize, depths)
+}
+
+// valida o cabeçalho de imagem e confere os limites; `extra_len` é a memória usada pelo modo
+// além dos planos e do arquivo reconstruído
+fn check_image(header: &ImageHeader, limits: &DecodeLimits, extra_len: u64) -> Result<()> {
+    let pixel_len = validate_image(header)?;
+    let width = header.width as usize;
+    let height = header.height as usize;
+    let output_len = (header.shell.header.len() as u64)
+        .saturating_add(header.shell.trailer.len() as u64)
+        .saturating_add(pixel_len);
+    limits.check_output(output_len)?;
+    // planos codificados, planos originais, arquivo reconstruído e memória do modo
+    let memory_len = Image::memory_len(width, height, header.depths.len())
+        .saturating_mul(2)
+        .saturating_add(output_len)
+        .saturating_add(extra_len);
+    limits.check_memory(memory_len)
+}
+
+// confere o cabeçalho do arquivo de imagem guardado com as dimensões e profundidades
+// gravadas; retorna o tamanho da região de pixels
+fn validate_image(header: &ImageHeader) -> Result<u64> {
+    let (width, height, depths, pixel_len) = match header.format {
+        ImageFormat::Bmp => {
+            let bmp = Bmp::parse(&header.shell.header)?;
+            let pixel_len = (bmp.row_size as u64).saturating_mul(bmp.height as u64);
+            (bmp.width, bmp.height, bmp.plane_depths(), pixel_len)
+        }
+        ImageFormat::Netpbm => {
+            let netpbm = Netpbm::parse(&header.shell.header)?;
+            (netpbm.width, netpbm.height, netpbm.plane_depths(), netpbm.pixel_len() as u64)
+        }
+    };
+    if width != header.width || height != header.height || depths != header.depths {
+        return Err(Error::InvalidContainer("cabeçalho de imagem inválido"));
+    }
+    Ok(header.shell.pixel_data.as_ref().map_or(pixel_len, |pixel_data| pixel_data.len() as u64))
+}
+
+// separa os planos de um BMP ou Netpbm; um fluxo RLE que a compressão canônica não reproduz
+// a partir dos planos vai como está no cabeçalho, e os planos são codificados da mesma forma,
+// para o recorte e a escolha de quadros
+pub fn split_image(input: &[u8]) -> Result<(ImageHeader, Image)> {
+    let (format, (shell, image)) = if netpbm::is_netpbm(input) {
+        (ImageFormat::Netpbm, netpbm::split(input)?)
+    } else {
+        (ImageFormat::Bmp, bmp::split(input)?)
+    };
+    let header = ImageHeader {
+        format,
+        width: image.width as u32,
+        height: image.height as u32,
+        depths: image.depths(),
+        shell,
+        used_values: Vec::new(),
+        tile_offsets: Vec::new(),
+        pass_ends: Vec::new(),
+        coder: None,
+        frames: Vec::new(),
+        key_offsets: Vec::new(),
+    };
+    Ok((header, image))
+}
+
+pub fn encode<W: Write>(input: &[u8], header: &Header, low: u32, high: u32, mut output: W) -> Result<EncodeReport> {
+    if let Mode::Sequence { .. } = header.mode {
+        return encode_sequence(&[input], header, low, high, output);
+    }
+    if let Mode::Wav = header.mode {
+        let sizes = encode_wav(input, header, low, high, output)?;
+        return Ok(EncodeReport {
+            sizes,
+            ..EncodeReport::default()
+        });
+    }
+    // os modos delta e float leem o arquivo como uma sequência de elementos, com modelos
+    // adaptativos, e gravam o tamanho dele no cabeçalho
+    if matches!(header.mode, Mode::Delta(_) | Mode::Float(_)) {
+        let header = Header {
+            data_len: Some(input.len() as u64),
+            ..header.clone()
+        };
+        write_header(&mut output, &header)?;
+        let mut encoder = ArithmeticEncoder::new(low, high, output)?;
+        match header.mode {
+            Mode::Delta(options) => delta::encode(&mut encoder, input, &options)?,
+            Mode::Float(options) => float::encode(&mut encoder, input, &options)?,
+            _ => unreachable!(),
+        }
+        let sizes = encoder.finish()?;
+        return Ok(EncodeReport {
+            sizes,
+            ..EncodeReport::default()
+        });
+    }
+    if !matches!(header.mode, Mode::Bytes | Mode::Dpcm) {
+        return encode_image(input, header, low, high, output);
+    }
+
+    write_header(&mut output, header)?;
+
+    let transformed;
+    let data = match header.mode {
+        Mode::Dpcm => {
+            transformed = dpcm::forward(input)?;
+            &transformed
+        }
+        _ => input,
+    };
+
+    let mut encoder = ArithmeticEncoder::new(low, high, output)?;
+    encoder.verify_file_len(data.len() as u64)?;
+    let sizes = encoder.encode(data)?;
+    Ok(EncodeReport {
+        sizes,
+        ..EncodeReport::default()
+    })
+}
+
+// os canais são codificados e os demais bytes do WAV, inclusive os outros chunks, vão no
+// cabeçalho
+fn encode_wav<W: Write>(input: &[u8], header: &Header, low: u32, high: u32, mut output: W) -> Result<EncodedSizes> {
+    let (wav, shell, channels) = wav::split(input)?;
+    let header = Header {
+        audio: Some(AudioHeader {
+            channels: wav.channels as u16,
+            bits: wav.bits,
+            frames: channels[0].len() as u64,
+            shell,
+        }),
+        ..header.clone()
+    };
+    write_header(&mut output, &header)?;
+
+    let mut encoder = ArithmeticEncoder::new(low, high, output)?;
+    audio::encode(&mut encoder, &channels, wav.bits)?;
+    encoder.finish()
+}
+
+// modos que codificam os planos do BMP; os demais bytes vão no cabeçalho
+fn encode_image<W: Write>(input: &[u8], header: &Header, low: u32, high: u32, mut output: W) -> Result<EncodeReport> {
+    let (mut image_header, image) = split_image(input)?;
+    // um erro no índice da paleta pode trocar a cor inteira do pixel
+    let palette = image_palette(&image_header)?;
+    let indexed = palette.is_some();
+    match header.mode {
+        Mode::JpegLs { near } if near > 0 && indexed => {
+            return Err(Error::UnsupportedImage("NEAR maior que zero requer imagem sem paleta"));
+        }
+        Mode::Dct { .. } if indexed => {
+            return Err(Error::UnsupportedImage("o modo dct requer canais de 8 bits sem paleta"));
+        }
+        Mode::Bilevel => bilevel::validate(&image_header.depths)?,
+        _ => {}
+    }
+    // no modo image, as transformações são aplicadas antes de gravar o cabeçalho, que guarda
+    // os valores usados em cada plano quando o histograma é compactado
+    let image = match header.mode {
+        Mode::Image(options) | Mode::Tiled { options, .. } => transform_image(&options, &mut image_header, palette.as_deref(), &image)?,
+        _ => image,
+    };
+    // o índice dos blocos ou das passadas só é conhecido depois da codificação, então a região
+    // codificada é montada em memória e gravada após o cabeçalho
+    if matches!(header.mode, Mode::Tiled { .. } | Mode::BitPlanes | Mode::Progressive { .. }) {
+        let mut data: Vec<u8> = Vec::new();
+        let mut encoder = ArithmeticEncoder::new(low, high, &mut data)?;
+        match header.mode {
+            Mode::Tiled { size, options } => {
+                image_header.tile_offsets = tiles::encode(&mut encoder, &image, size, &options)?;
+            }
+            Mode::BitPlanes => {
+                image_header.pass_ends = bitplane::encode(&mut encoder, &image)?;
+                image_header.coder = Some(ArithmeticCoding::new(low, high)?);
+            }
+            Mode::Progressive { levels } => {
+                image_header.pass_ends = progressive::encode(&mut encoder, &image, levels)?;
+                image_header.coder = Some(ArithmeticCoding::new(low, high)?);
+            }
+            _ => unreachable!(),
+        }
+        let sizes = encoder.finish()?;
+        let header = Header {
+            image: Some(image_header),
+            ..header.clone()
+        };
+        write_header(&mut output, &header)?;
+        output.write_all(&data)?;
+        output.flush()?;
+        return Ok(EncodeReport {
+            sizes,
+            ..EncodeReport::default()
+        });
+    }
+    let header = Header {
+        image: Some(image_header),
+        ..header.clone()
+    };
+    write_header(&mut output, &header)?;
+
+    let mut encoder = ArithmeticEncoder::new(low, high, output)?;
+    let mut report = EncodeReport::default();
+    match header.mode {
+        Mode::Image(options) => predictive::encode(&mut encoder, &image, &options)?,
+        Mode::JpegLs { near } => report.max_error = Some(jpegls::encode(&mut encoder, &image, near)?),
+        Mode::Dct { quality } => report.psnr = Some(dct::encode(&mut encoder, &image, quality)?),
+        Mode::Wavelet { levels } => wavelet::encode(&mut encoder, &image, levels)?,
+        Mode::Bilevel => bilevel::encode(&mut encoder, &image)?,
+        Mode::Bytes | Mode::Dpcm | Mode::Tiled { .. } | Mode::BitPlanes | Mode::Progressive { .. } | Mode::Sequence { .. } | Mode::Wav | Mode::Delta(_) | Mode::Float(_) => unreachable!(),
+    }
+    report.sizes = encoder.finish()?;
+    Ok(report)
+}
+
+fn transform_image(options: &ImageOptions, image_header: &mut ImageHeader, palette: Option<&[[u8; 3]]>, image: &Image) -> Result<Image> {
+    let image = colour::forward(options.transform, image)?;
+    let image = palette::forward(options.palette, palette, &image)?;
+    if options.packed {
+        image_header.used_values = histogram::used_values(&image);
+    }
+    histogram::forward(options.packed, &image, &image_header.used_values)
+}
+
+// estágios intermediários do modo image: os planos transformados, os resíduos da predição e o
+// custo de cada pixel, medido com os mesmos modelos e a mesma precisão da codificação
+pub fn dump(input: &[u8], header: &Header, low: u32, high: u32) -> Result<Dump> {
+    let options = match header.mode {
+        Mode::Image(options) => options,
+        _ => return Err(Error::UnsupportedImage("a exportação dos estágios requer o modo image")),
+    };
+    let (mut image_header, image) = split_image(input)?;
+    let palette = image_palette(&image_header)?;
+    let max_bits: u32 = image_header.depths.iter().map(|&depth| depth as u32).sum();
+    let image = transform_image(&options, &mut image_header, palette.as_deref(), &image)?;
+
+    let ac = ArithmeticCoding::new(low, high)?;
+    let (residuals, costs) = predictive::costs(&image, &options, ac.half_bit() as u64 - 1)?;
+    Ok(Dump {
+        transformed: bmp::write_rgb(&image)?,
+        residuals: bmp::write_rgb(&dump::residual_image(&residuals)?)?,
+        costs: bmp::write_rgb(&dump::heat_map(&costs, image.width, image.height, max_bits as f64)?)?,
+        bits: costs.iter().sum(),
+        pixels: costs.len(),
+    })
+}
+
+// codifica os quadros, na ordem, em um único arquivo; todos devem ter o formato, as dimensões e
+// as profundidades do primeiro
+pub fn encode_sequence<W: Write>(frames: &[&[u8]], header: &Header, low: u32, high: u32, mut output: W) -> Result<EncodeReport> {
+    let (key_interval, motion) = match header.mode {
+        Mode::Sequence { key_interval, motion } if key_interval > 0 => (key_interval, motion),
+        _ => return Err(Error::UnsupportedImage("intervalo entre quadros-chave inválido")),
+    };
+    let (first, frames) = match frames.split_first() {
+        Some(frames) => frames,
+        None => return Err(Error::UnsupportedImage("sequência sem quadros")),
+    };
+    let (mut image_header, image) = split_image(first)?;
+
+    // o índice dos quadros-chave só é conhecido depois da codificação, como no modo com blocos
+    let mut data: Vec<u8> = Vec::new();
+    let mut encoder = ArithmeticEncoder::new(low, high, &mut data)?;
+    let depths = image.depths();
+    let mut models = sequence::Models::new(&depths, encoder.max_total())?;
+    sequence::encode(&mut encoder, &mut models, &image, None, motion)?;
+    image_header.frames.push(None);
+    image_header.key_offsets.push(0);
+
+    let mut previous = image;
+    for (index, frame) in frames.iter().enumerate() {
+        let (frame_header, image) = split_image(frame)?;
+        if frame_header.format != image_header.format
+            || frame_header.width != image_header.width
+            || frame_header.height != image_header.height
+            || frame_header.depths != image_header.depths
+        {
+            return Err(Error::UnsupportedImage("quadros com formato ou dimensões diferentes"));
+        }
+        image_header.frames.push((frame_header.shell != image_header.shell).then_some(frame_header.shell));
+
+        if (index as u64 + 1).is_multiple_of(key_interval as u64) {
+            image_header.key_offsets.push(encoder.restart()?);
+            models = sequence::Models::new(&depths, encoder.max_total())?;
+            sequence::encode(&mut encoder, &mut models, &image, None, motion)?;
+        } else {
+            sequence::encode(&mut encoder, &mut models, &image, Some(&previous), motion)?;
+        }
+        previous = image;
+    }
+    let sizes = encoder.finish()?;
+
+    let header = Header {
+        image: Some(image_header),
+        ..header.clone()
+    };
+    write_header(&mut output, &header)?;
+    output.write_all(&data)?;
+    output.flush()?;
+    Ok(EncodeReport {
+        sizes,
+        ..EncodeReport::default()
+    })
+}
+
+pub fn write_header<W: Write>(output: &mut W, header: &Header) -> Result<()> {
+    let buffer = bincode::serialize(header)?;
+    output.write_all(MAGIC)?;
+    output.write_all(&[VERSION])?;
+    output.write_all(&(buffer.len() as u32).to_le_bytes())?;
+    output.write_all(&buffer)?;
+    Ok(())
+}
+
+fn read_header<'a>(input: &'a [u8], limits: &DecodeLimits) -> Result<(Header, &'a [u8])> {
+    let (version, input) = match input.split_first() {
+        Some((&version, rest)) => (version, rest),
+        None => return Err(Error::InvalidContainer("cabeçalho incompleto")),
+    };
+    if version != VERSION {
+        return Err(Error::InvalidContainer("versão de arquivo não suportada"));
+    }
+
+    let (header_len, input) = match input.split_first_chunk::<4>() {
+        Some((header_len, rest)) => (u32::from_le_bytes(*header_len) as usize, rest),
+        None => return Err(Error::InvalidContainer("cabeçalho incompleto")),
+    };
+    if header_len > input.len() {
+        return Err(Error::InvalidContainer("tamanho do cabeçalho inválido"));
+    }
+    limits.check_model(header_len as u64)?;
+    limits.check_memory(header_len as u64)?;
+
+    let (header, input) = input.split_at(header_len);
+    Ok((deserialize_bounded(header)?, input))
+}
+
+// o limite impede que um tamanho forjado provoque alocações maiores que o próprio trecho lido
+fn deserialize_bounded<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T> {
+    Ok(bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(bytes.len() as u64)
+        .deserialize(bytes)?)
+}
+
+pub fn decode<W: Write>(input: &[u8], limits: &DecodeLimits, output: W) -> Result<()> {
+    Container::parse(input, limits)?.decode(limits, output)
+}
+
+// decodifica as passadas completas de um arquivo do modo bitplane ou progressivo, que pode estar
+// truncado, e grava uma imagem do tamanho original no formato original: no modo bitplane, os
+// bits dos planos que faltam recebem o ponto médio do intervalo possível, e no progressivo, os
+// pixels das passadas que faltam repetem os vizinhos decodificados. Retorna as passadas
+// decodificadas e o total
+pub fn decode_partial<W: Write>(input: &[u8], limits: &DecodeLimits, mut output: W) -> Result<(usize, usize)> {
+    let (header, data) = match input.strip_prefix(MAGIC) {
+        Some(rest) => read_header(rest, limits)?,
+        None => return Err(Error::InvalidContainer("cabeçalho ausente")),
+    };
+    if !matches!(header.mode, Mode::BitPlanes | Mode::Progressive { .. }) {
+        return Err(Error::UnsupportedImage("a decodificação parcial requer um arquivo do modo bitplane ou progressive"));
+    }
+    let ac = match &image_header(&header)?.coder {
+        Some(ac) => ac,
+        None => return Err(Error::InvalidContainer("estrutura do codificador ausente")),
+    };
+    let (image, passes) = decode_passes(&header, ac, data, limits, 0)?;
+    let header = image_header(&header)?;
+    output.write_all(&join_passes(header, &image, passes)?)?;
+    output.flush()?;
+    Ok((passes, header.pass_ends.len()))
+}
+
+// planos dos modos bitplane e progressivo, a partir das passadas inteiras em `data`, sem os
+// `skip` planos de bits menos significativos no modo bitplane; retorna também quantas passadas
+// foram decodificadas
+fn decode_passes(header: &Header, ac: &ArithmeticCoding, data: &[u8], limits: &DecodeLimits, skip: u8) -> Result<(Image, usize)> {
+    let image_header = image_header(header)?;
+    let depths = &image_header.depths;
+    match header.mode {
+        Mode::BitPlanes => {
+            let mut image = allocate_image(image_header, depths, limits, bitplane::memory_len(depths.len()))?;
+            let passes = bitplane::decode(ac, data, &image_header.pass_ends, &mut image, skip)?;
+            Ok((image, passes))
+        }
+        Mode::Progressive { levels } => {
+            let mut image = allocate_image(image_header, depths, limits, progressive::memory_len(depths))?;
+            let passes = progressive::decode(ac, data, &image_header.pass_ends, &mut image, levels)?;
+            Ok((image, passes))
+        }
+        _ => Err(Error::InvalidContainer("modo sem passadas")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::ByteOrder;
+    use crate::colour::ColourTransform;
+    use crate::palette::PaletteOrder;
+    use crate::predictive::Predictor;
+    use crate::encoder::ArithmeticEncoder;
+    use crate::limits::Limit;
+    use crate::testing::{
+        bilevel_bmp,
+        bmp,
+        bmp_header,
+        wav,
+        HIGH,
+        LOW,
+    };
+
+    fn encode_with(input: &[u8], mode: Mode) -> Vec<u8> {
+        let header = Header {
+            mode,
+            ..Header::default()
+        };
+        let mut output = Vec::new();
+        encode(input, &header, LOW, HIGH, &mut output).unwrap();
+        output
+    }
+
+    fn decode_default(input: &[u8]) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        decode(input, &DecodeLimits::new(), &mut output)?;
+        Ok(output)
+    }
+
+    // regrava o cabeçalho de um arquivo codificado depois de alterá-lo
+    fn forge(encoded: &[u8], edit: impl FnOnce(&mut Header)) -> Vec<u8> {
+        let rest = &encoded[MAGIC.len()..];
+        let (mut header, body) = read_header(rest, &DecodeLimits::new()).unwrap();
+        edit(&mut header);
+        let mut forged = Vec::new();
+        write_header(&mut forged, &header).unwrap();
+        forged.extend_from_slice(body);
+        forged
+    }
+
+    fn parse(encoded: &[u8]) -> Container<'_> {
+        Container::parse(encoded, &DecodeLimits::new()).unwrap()
+    }
+
+    #[test]
+    fn bytes_and_dpcm_round_trip() {
+        let text = b"abracadabra ".repeat(100);
+        for (input, mode) in [(&text[..], Mode::Bytes), (b"", Mode::Bytes), (&bmp(10, 10)[..], Mode::Dpcm)] {
+            let encoded = encode_with(input, mode);
+            assert_eq!(decode_default(&encoded).unwrap(), input);
+        }
+        let header = Header {
+            mode: Mode::Dpcm,
+            ..Header::default()
+        };
+        assert!(matches!(encode(b"abracadabra", &header, LOW, HIGH, Vec::new()), Err(Error::InvalidImage(_))));
+    }
+
+    #[test]
+    fn image_mode_round_trip() {
+        let options = [
+            ImageOptions::default(),
+            ImageOptions::new(true, ColourTransform::None, Predictor::Med, false, PaletteOrder::None, false),
+            ImageOptions::new(true, ColourTransform::None, Predictor::Png, false, PaletteOrder::None, false),
+            ImageOptions::new(true, ColourTransform::None, Predictor::Png, true, PaletteOrder::None, false),
+            ImageOptions::new(false, ColourTransform::None, Predictor::Med, true, PaletteOrder::None, true),
+        ];
+        for options in options {
+            for input in [bmp(13, 9), bilevel_bmp(40, 30)] {
+                let encoded = encode_with(&input, Mode::Image(options));
+                assert_eq!(decode_default(&encoded).unwrap(), input);
+            }
+        }
+        for transform in [ColourTransform::YCoCgR, ColourTransform::Rct] {
+            let input = bmp(13, 9);
+            let encoded = encode_with(&input, Mode::Image(ImageOptions::new(true, transform, Predictor::Med, false, PaletteOrder::None, false)));
+            assert_eq!(decode_default(&encoded).unwrap(), input);
+            let header = Header {
+                mode: Mode::Image(ImageOptions::new(false, transform, Predictor::Med, false, PaletteOrder::None, false)),
+                ..Header::default()
+            };
+            assert!(matches!(encode(&bilevel_bmp(8, 8), &header, LOW, HIGH, Vec::new()), Err(Error::UnsupportedImage(_))));
+        }
+        for order in [PaletteOrder::Luminance, PaletteOrder::Tour] {
+            let input = bilevel_bmp(40, 30);
+            let encoded = encode_with(&input, Mode::Image(ImageOptions::new(false, ColourTransform::None, Predictor::Med, false, order, false)));
+            assert_eq!(decode_default(&encoded).unwrap(), input);
+            let header = Header {
+                mode: Mode::Image(ImageOptions::new(false, ColourTransform::None, Predictor::Med, false, order, false)),
+                ..Header::default()
+            };
+            assert!(matches!(encode(&bmp(8, 8), &header, LOW, HIGH, Vec::new()), Err(Error::UnsupportedImage(_))));
+        }
+        let header = Header {
+            mode: Mode::Image(ImageOptions::default()),
+            ..Header::default()
+        };
+        assert!(matches!(encode(b"abracadabra", &header, LOW, HIGH, Vec::new()), Err(Error::InvalidImage(_))));
+    }
+
+    // um fluxo RLE que a compressão do módulo bmp não reproduz vai como está no cabeçalho
+    #[test]
+    fn non_canonical_rle_round_trip() {
+        let mut input = bmp_header(7, 2, 8, 8, &[[0, 0, 0], [255, 255, 255]]);
+        input[30..34].copy_from_slice(&bmp::BI_RLE8.to_le_bytes());
+        input.extend_from_slice(&[1, 1, 1, 1, 0, 0, 0, 1]);
+        for mode in [Mode::Image(ImageOptions::default()), Mode::Tiled { size: 4, options: ImageOptions::default() }] {
+            let encoded = encode_with(&input, mode);
+            assert!(parse(&encoded).header.image.unwrap().shell.pixel_data.is_some());
+            assert_eq!(decode_default(&encoded).unwrap(), input, "{:?}", mode);
+        }
+
+        let encoded = encode_with(&input, Mode::Tiled { size: 4, options: ImageOptions::default() });
+        let mut region = Vec::new();
+        parse(&encoded).decode_region(&DecodeLimits::new(), Region::new(0, 1, 2, 1), &mut region).unwrap();
+        let (shell, image) = bmp::split(&region).unwrap();
+        assert!(shell.pixel_data.is_none());
+        assert_eq!((image.width, image.height, image.get(0, 1, 0)), (2, 1, 1));
+
+        // sem todos os planos de bits, a aproximação também usa a compressão do módulo
+        let encoded = encode_with(&input, Mode::BitPlanes);
+        let header_len = MAGIC.len() + 1 + 4 + bmp::read_u32(&encoded, MAGIC.len() + 1) as usize;
+        let ends = parse(&encoded).header.image.unwrap().pass_ends;
+        let mut partial = Vec::new();
+        assert_eq!(decode_partial(&encoded[..header_len + ends[6] as usize], &DecodeLimits::new(), &mut partial).unwrap(), (7, 8));
+        let (shell, image) = bmp::split(&partial).unwrap();
+        assert!(shell.pixel_data.is_none());
+        assert_eq!((image.width, image.height, image.get(0, 1, 0)), (7, 2, 1));
+    }
+
+    #[test]
+    fn tiled_round_trip_and_regions() {
+        let options = ImageOptions::new(true, ColourTransform::YCoCgR, Predictor::Png, true, PaletteOrder::None, true);
+        for input in [bmp(20, 12), bilevel_bmp(40, 30)] {
+            let encoded = encode_with(&input, Mode::Tiled { size: 8, options: ImageOptions::default() });
+            assert_eq!(decode_default(&encoded).unwrap(), input);
+        }
+        let input = bmp(20, 12);
+        assert_eq!(decode_default(&encode_with(&input, Mode::Tiled { size: 8, options })).unwrap(), input);
+
+        let encoded = encode_with(&input, Mode::Tiled { size: 8, options: ImageOptions::default() });
+        let mut region = Vec::new();
+        parse(&encoded).decode_region(&DecodeLimits::new(), Region::new(5, 3, 10, 6), &mut region).unwrap();
+        let (_, image) = bmp::split(&region).unwrap();
+        let (_, original) = bmp::split(&input).unwrap();
+        assert_eq!(image.planes, tiles::crop(&original, &Region::new(5, 3, 10, 6)).unwrap().planes);
+        let outside = parse(&encoded).decode_region(&DecodeLimits::new(), Region::new(15, 0, 6, 1), Vec::new());
+        assert!(matches!(outside, Err(Error::UnsupportedImage(_))));
+
+        let header = Header {
+            mode: Mode::Tiled { size: 0, options: ImageOptions::default() },
+            ..Header::default()
+        };
+        assert!(matches!(encode(&input, &header, LOW, HIGH, Vec::new()), Err(Error::UnsupportedImage(_))));
+        let encoded = encode_with(&input, Mode::Image(ImageOptions::default()));
+        let region = parse(&encoded).decode_region(&DecodeLimits::new(), Region::new(0, 0, 1, 1), Vec::new());
+        assert!(matches!(region, Err(Error::UnsupportedImage(_))));
+    }
+
+    #[test]
+    fn image_forged_dimensions_are_rejected() {
+        for mode in [Mode::Image(ImageOptions::default()), Mode::Dct { quality: 75 }] {
+            let encoded = encode_with(&bmp(8, 8), mode);
+            let forged = forge(&encoded, |header| {
+                let image = header.image.as_mut().unwrap();
+                image.width = 1 << 16;
+                image.height = 1 << 15;
+                image.shell.header[18..22].copy_from_slice(&image.width.to_le_bytes());
+                image.shell.header[22..26].copy_from_slice(&image.height.to_le_bytes());
+            });
+            let error = decode_default(&forged).unwrap_err();
+            assert!(matches!(error, Error::InvalidContainer("dimensões maiores que as dos dados codificados")));
+        }
+
+        let encoded = encode_with(&bmp(8, 8), Mode::Image(ImageOptions::default()));
+        let forged = forge(&encoded, |header| header.image.as_mut().unwrap().width = 9);
+        assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer("cabeçalho de imagem inválido"))));
+        let forged = forge(&encoded, |header| header.image = None);
+        assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer(_))));
+    }
+
+    #[test]
+    fn jpegls_and_dct_round_trip() {
+        let input = bmp(16, 16);
+        assert_eq!(decode_default(&encode_with(&input, Mode::JpegLs { near: 0 })).unwrap(), input);
+        assert_eq!(decode_default(&encode_with(&bilevel_bmp(40, 30), Mode::JpegLs { near: 0 })).unwrap(), bilevel_bmp(40, 30));
+        let decoded = decode_default(&encode_with(&input, Mode::JpegLs { near: 2 })).unwrap();
+        assert_eq!(decoded.len(), input.len());
+        assert!(decoded.iter().zip(input.iter()).all(|(&decoded, &input)| decoded.abs_diff(input) <= 2));
+
+        let decoded = decode_default(&encode_with(&input, Mode::Dct { quality: 90 })).unwrap();
+        assert_eq!(decoded[..54], input[..54]);
+        assert_eq!(decoded.len(), input.len());
+
+        let header = Header {
+            mode: Mode::JpegLs { near: 1 },
+            ..Header::default()
+        };
+        assert!(matches!(encode(&bilevel_bmp(8, 8), &header, LOW, HIGH, Vec::new()), Err(Error::UnsupportedImage(_))));
+    }
+
+    #[test]
+    fn encode_reports_the_distortion() {
+        let header = Header {
+            mode: Mode::JpegLs { near: 3 },
+            ..Header::default()
+        };
+        let report = encode(&bmp(16, 16), &header, LOW, HIGH, Vec::new()).unwrap();
+        assert!(report.max_error.is_some_and(|max_error| max_error <= 3));
+        let mut encoded = Vec::new();
+        let report = encode(&bmp(16, 16), &Header::default(), LOW, HIGH, &mut encoded).unwrap();
+        let data_len = parse(&encoded).encoded_data.len() as u64;
+        assert_eq!((report.sizes.data_len, report.max_error, report.psnr), (data_len, None, None));
+        let header = Header {
+            mode: Mode::Dct { quality: 90 },
+            ..Header::default()
+        };
+        let report = encode(&bmp(16, 16), &header, LOW, HIGH, Vec::new()).unwrap();
+        assert!(report.psnr.is_some_and(|psnr| psnr > 30.0) && report.max_error.is_none());
+    }
+
+    #[test]
+    fn netpbm_round_trip() {
+        let options = ImageOptions::new(true, ColourTransform::YCoCgR, Predictor::Png, true, PaletteOrder::None, true);
+        let modes = [
+            Mode::Image(ImageOptions::default()),
+            Mode::Image(options),
+            Mode::JpegLs { near: 0 },
+            Mode::Wavelet { levels: 3 },
+            Mode::BitPlanes,
+            Mode::Tiled { size: 8, options },
+            Mode::Progressive { levels: 2 },
+        ];
+        let mut netpbm = b"P6\n# comentario\n13 9\n1000\n".to_vec();
+        netpbm.extend((0..13 * 9 * 3).flat_map(|index| (index as u16 * 7 % 1001).to_be_bytes()));
+        for mode in modes {
+            let encoded = encode_with(&netpbm, mode);
+            assert_eq!(decode_default(&encoded).unwrap(), netpbm, "{:?}", mode);
+        }
+
+        // a prévia dos planos de bits mantém o formato
+        let encoded = encode_with(&netpbm, Mode::BitPlanes);
+        let mut preview = Vec::new();
+        parse(&encoded).decode_preview(&DecodeLimits::new(), 4, &mut preview).unwrap();
+        assert_eq!((preview.len(), &preview[..2]), (netpbm.len(), &b"P6"[..]));
+    }
+
+    #[test]
+    fn bilevel_round_trip() {
+        let input = bilevel_bmp(40, 30);
+        assert_eq!(decode_default(&encode_with(&input, Mode::Bilevel)).unwrap(), input);
+        let header = Header {
+            mode: Mode::Bilevel,
+            ..Header::default()
+        };
+        assert!(matches!(encode(&bmp(8, 8), &header, LOW, HIGH, Vec::new()), Err(Error::UnsupportedImage(_))));
+    }
+
+    #[test]
+    fn wavelet_round_trip_and_previews() {
+        let input = bmp(20, 12);
+        let encoded = encode_with(&input, Mode::Wavelet { levels: 2 });
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+
+        let mut preview = Vec::new();
+        parse(&encoded).decode_preview(&DecodeLimits::new(), 1, &mut preview).unwrap();
+        let (_, image) = bmp::split(&preview).unwrap();
+        assert_eq!((image.width, image.height), (10, 6));
+
+        let encoded = encode_with(&input, Mode::BitPlanes);
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+        let mut preview = Vec::new();
+        parse(&encoded).decode_preview(&DecodeLimits::new(), 2, &mut preview).unwrap();
+        assert_eq!(preview.len(), input.len());
+        let preview = parse(&encoded).decode_preview(&DecodeLimits::new(), 9, Vec::new());
+        assert!(matches!(preview, Err(Error::UnsupportedImage(_))));
+
+        let encoded = encode_with(&input, Mode::Image(ImageOptions::default()));
+        let preview = parse(&encoded).decode_preview(&DecodeLimits::new(), 1, Vec::new());
+        assert!(matches!(preview, Err(Error::UnsupportedImage(_))));
+        assert!(matches!(decode_partial(&encoded, &DecodeLimits::new(), Vec::new()), Err(Error::UnsupportedImage(_))));
+    }
+
+    // um arquivo progressivo truncado ainda fornece as passadas completas
+    #[test]
+    fn truncated_progressive_files_are_decoded_partially() {
+        let input = bmp(24, 16);
+        let encoded = encode_with(&input, Mode::Progressive { levels: 2 });
+        assert_eq!(decode_partial(&encoded, &DecodeLimits::new(), Vec::new()).unwrap(), (5, 5));
+
+        let header_len = MAGIC.len() + 1 + 4 + bmp::read_u32(&encoded, MAGIC.len() + 1) as usize;
+        let ends = parse(&encoded).header.image.unwrap().pass_ends;
+        let truncated = &encoded[..header_len + ends[1] as usize + 3];
+        let mut output = Vec::new();
+        assert_eq!(decode_partial(truncated, &DecodeLimits::new(), &mut output).unwrap(), (2, 5));
+        assert_eq!(output.len(), input.len());
+        assert!(decode_default(truncated).is_err());
+
+        let forged = forge(&encoded, |header| header.image.as_mut().unwrap().pass_ends.pop().map(|_| ()).unwrap());
+        assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer(_))));
+    }
+
+    // cortado depois de qualquer plano, um arquivo do modo bitplane ainda fornece uma aproximação
+    #[test]
+    fn truncated_bitplane_files_are_decoded_partially() {
+        let input = bmp(20, 12);
+        let encoded = encode_with(&input, Mode::BitPlanes);
+        assert_eq!(decode_partial(&encoded, &DecodeLimits::new(), Vec::new()).unwrap(), (24, 24));
+
+        let header_len = MAGIC.len() + 1 + 4 + bmp::read_u32(&encoded, MAGIC.len() + 1) as usize;
+        let ends = parse(&encoded).header.image.unwrap().pass_ends;
+        let truncated = &encoded[..header_len + ends[8] as usize + 1];
+        let mut output = Vec::new();
+        assert_eq!(decode_partial(truncated, &DecodeLimits::new(), &mut output).unwrap(), (9, 24));
+        assert!(decode_default(truncated).is_err());
+
+        // três planos de bits do primeiro canal e os bits que faltam no ponto médio
+        let (_, image) = bmp::split(&output).unwrap();
+        let (_, original) = bmp::split(&input).unwrap();
+        for (&sample, &expected) in image.planes[0].samples.iter().zip(original.planes[0].samples.iter()) {
+            assert_eq!(sample, (expected & 0xE0) | 0x10);
+        }
+    }
+
+    #[test]
+    fn sequence_round_trip() {
+        let frames: Vec<Vec<u8>> = (0..5).map(|index| {
+            let mut frame = bmp(16, 16);
+            frame[54 + index * 3] ^= 0xFF;
+            frame
+        }).collect();
+        let references: Vec<&[u8]> = frames.iter().map(Vec::as_slice).collect();
+        let header = Header {
+            mode: Mode::Sequence { key_interval: 2, motion: true },
+            ..Header::default()
+        };
+        let mut encoded = Vec::new();
+        encode_sequence(&references, &header, LOW, HIGH, &mut encoded).unwrap();
+        let container = parse(&encoded);
+        assert_eq!(container.frame_count().unwrap(), 5);
+        let mut decoded = Vec::new();
+        container.decode_frames(&DecodeLimits::new(), 1, 4, |index, bytes| {
+            decoded.push((index, bytes));
+            Ok(())
+        }).unwrap();
+        assert_eq!(decoded, (1..5).map(|index| (index, frames[index].clone())).collect::<Vec<_>>());
+        assert!(matches!(decode_default(&encoded), Err(Error::UnsupportedImage(_))));
+
+        let forged = forge(&encoded, |header| header.mode = Mode::Sequence { key_interval: 0, motion: true });
+        assert!(matches!(parse(&forged).decode_frame(&DecodeLimits::new(), 0, Vec::new()), Err(Error::InvalidContainer(_))));
+        let forged = forge(&encoded, |header| header.image.as_mut().unwrap().key_offsets.push(0));
+        assert!(matches!(parse(&forged).decode_frame(&DecodeLimits::new(), 0, Vec::new()), Err(Error::InvalidContainer(_))));
+        assert!(matches!(parse(&encoded).decode_frame(&DecodeLimits::new(), 5, Vec::new()), Err(Error::UnsupportedImage(_))));
+    }
+
+    #[test]
+    fn dump_exports_the_image_stages() {
+        let input = bmp(13, 9);
+        let header = Header {
+            mode: Mode::Image(ImageOptions::default()),
+            ..Header::default()
+        };
+        let stages = dump(&input, &header, LOW, HIGH).unwrap();
+        let (_, original) = bmp::split(&input).unwrap();
+        assert_eq!(bmp::split(&stages.transformed).unwrap().1.planes, original.planes);
+        for stage in [&stages.residuals, &stages.costs] {
+            let (_, image) = bmp::split(stage).unwrap();
+            assert_eq!((image.width, image.height), (13, 9));
+        }
+        // o custo medido não passa do tamanho dos dados codificados
+        let encoded = encode_with(&input, Mode::Image(ImageOptions::default()));
+        assert_eq!(stages.pixels, 13 * 9);
+        assert!(stages.bits > 0.0 && stages.bits <= (encoded.len() * 8) as f64);
+
+        let header = Header {
+            mode: Mode::JpegLs { near: 0 },
+            ..Header::default()
+        };
+        assert!(matches!(dump(&input, &header, LOW, HIGH), Err(Error::UnsupportedImage(_))));
+    }
+
+    #[test]
+    fn wav_round_trip() {
+        for channels in [1, 2] {
+            let input = wav(channels, 5000);
+            let encoded = encode_with(&input, Mode::Wav);
+            assert!(encoded.len() < input.len());
+            assert_eq!(decode_default(&encoded).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn wav_forged_frames_are_rejected() {
+        let encoded = encode_with(&wav(2, 1000), Mode::Wav);
+        let forged = forge(&encoded, |header| header.audio.as_mut().unwrap().frames = 1 << 40);
+        assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer(_))));
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let input: Vec<u8> = (0..20000u32).flat_map(|index| (index * 3 + index % 7).to_be_bytes()).chain([1, 2, 3]).collect();
+        let encoded = encode_with(&input, Mode::Delta(DeltaOptions::new(4, 1, 2, ByteOrder::Big)));
+        assert!(encoded.len() < input.len() / 10);
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn delta_forged_len_is_rejected() {
+        let encoded = encode_with(&[7; 1000], Mode::Delta(DeltaOptions::default()));
+        for len in [1 << 40, 1 << 55, u64::MAX] {
+            let forged = forge(&encoded, |header| header.data_len = Some(len));
+            assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer(_))));
+        }
+    }
+
+    // o limite de tamanho não pode rejeitar um arquivo legítimo, mesmo com a maior compressão
+    #[test]
+    fn delta_constant_data_is_accepted() {
+        let input = vec![0; 1 << 20];
+        let encoded = encode_with(&input, Mode::Delta(DeltaOptions::new(1, 1, 0, ByteOrder::Little)));
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn float_round_trip() {
+        let values = (0..5000).map(|index| (index as f64 * 0.01).sin() * 20.0).chain([f64::NAN, -0.0, f64::INFINITY, f64::MIN_POSITIVE / 4.0]);
+        let mut input: Vec<u8> = values.flat_map(f64::to_le_bytes).collect();
+        input.extend_from_slice(&f64::from_bits(0x7FF0_0000_0000_0001).to_le_bytes());
+        input.push(9);
+        let encoded = encode_with(&input, Mode::Float(FloatOptions::default()));
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+
+        let input: Vec<u8> = (0..3000).flat_map(|index| (index as f32 * 0.5).to_be_bytes()).collect();
+        let encoded = encode_with(&input, Mode::Float(FloatOptions::new(4, ByteOrder::Big)));
+        assert!(encoded.len() < input.len() / 2);
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn float_forged_len_is_rejected() {
+        let input: Vec<u8> = (0..100).flat_map(|index| (index as f64).to_le_bytes()).collect();
+        let encoded = encode_with(&input, Mode::Float(FloatOptions::default()));
+        for len in [1 << 40, isize::MAX as u64 + 1, u64::MAX] {
+            let forged = forge(&encoded, |header| header.data_len = Some(len));
+            assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer(_))));
+        }
+        let forged = forge(&encoded, |header| header.mode = Mode::Float(FloatOptions::new(3, ByteOrder::Little)));
+        assert!(matches!(decode_default(&forged), Err(Error::InvalidContainer(_))));
+    }
+
+    #[test]
+    fn float_constant_data_is_accepted() {
+        let input: Vec<u8> = [1.5f32; 1 << 18].iter().flat_map(|value| value.to_le_bytes()).collect();
+        let encoded = encode_with(&input, Mode::Float(FloatOptions::new(4, ByteOrder::Little)));
+        assert_eq!(decode_default(&encoded).unwrap(), input);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let header = Header {
+            metadata: Some(FileMetadata {
+                name: String::from("abracadabra.txt"),
+                mode: 0o600,
+                modified: Some((1700000000, 5)),
+            }),
+            ..Header::default()
+        };
+        let mut encoded = Vec::new();
+        write_header(&mut encoded, &header).unwrap();
+        ArithmeticEncoder::new(LOW, HIGH, &mut encoded).unwrap().encode(b"abracadabra").unwrap();
+
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let metadata = container.header.metadata.clone().unwrap();
+        assert_eq!((metadata.name.as_str(), metadata.mode, metadata.modified), ("abracadabra.txt", 0o600, Some((1700000000, 5))));
+        assert_eq!(decode_default(&encoded).unwrap(), b"abracadabra");
+
+        // arquivos sem cabeçalho continuam sendo aceitos
+        let mut plain = Vec::new();
+        ArithmeticEncoder::new(LOW, HIGH, &mut plain).unwrap().encode(b"abracadabra").unwrap();
+        assert!(Container::parse(&plain, &DecodeLimits::new()).unwrap().header.metadata.is_none());
+
+        let mut version = encoded.clone();
+        version[MAGIC.len()] = VERSION + 1;
+        assert!(matches!(decode_default(&version), Err(Error::InvalidContainer(_))));
+        let mut header_len = encoded.clone();
+        header_len[MAGIC.len() + 1..MAGIC.len() + 5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(decode_default(&header_len), Err(Error::InvalidContainer(_))));
+    }
+
+    #[test]
+    fn malformed_containers_are_rejected() {
+        let mut encoded = Vec::new();
+        ArithmeticEncoder::new(LOW, HIGH, &mut encoded).unwrap().encode(b"abracadabra").unwrap();
+        assert!(matches!(decode_default(&encoded[..TRAILER_LEN - 1]), Err(Error::InvalidContainer(_))));
+
+        let len = encoded.len();
+        let mut trailer = encoded.clone();
+        trailer[len - TRAILER_LEN..len - 1].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(decode_default(&trailer), Err(Error::InvalidContainer(_))));
+
+        // a quantidade de entradas da tabela de símbolos vem depois de low, high e precisão
+        let table = Container::parse(&encoded, &DecodeLimits::new()).unwrap().encoded_data.len() + 12;
+        let mut forged = encoded.clone();
+        forged[table..table + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(decode_default(&forged), Err(Error::Serialization(_))));
+    }
+
+    #[test]
+    fn limits_are_checked_before_decoding() {
+        let mut encoded = Vec::new();
+        ArithmeticEncoder::new(LOW, HIGH, &mut encoded).unwrap().encode(&[7; 1000]).unwrap();
+        let limits = |max_output_bytes, max_model_size| DecodeLimits {
+            max_output_bytes,
+            max_model_size,
+            max_memory: None,
+        };
+        assert!(matches!(decode(&encoded, &limits(Some(999), None), Vec::new()), Err(Error::LimitExceeded(Limit::Output, 999))));
+        assert!(matches!(decode(&encoded, &limits(None, Some(8)), Vec::new()), Err(Error::LimitExceeded(Limit::Model, 8))));
+        assert_eq!(decode_default(&encoded).unwrap(), [7; 1000]);
+    }
+}
@@ -0,0 +1,418 @@
+use std::f32::consts::PI;
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Error;
+use crate::Result;
+
+pub const DEFAULT_QUALITY: u8 = 75;
+
+const BLOCK: usize = 8;
+const BLOCK_LEN: usize = BLOCK * BLOCK;
+
+// categorias (quantidade de bits) dos coeficientes e símbolos corrida/categoria dos AC
+const CATEGORIES: usize = 16;
+const RUN_LEVELS: usize = 256;
+const END_OF_BLOCK: u32 = 0x00;
+const ZERO_RUN: u32 = 0xF0;
+
+// tabelas de quantização do anexo K do JPEG, para luminância e crominância
+const LUMA_TABLE: [u16; BLOCK_LEN] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+const CHROMA_TABLE: [u16; BLOCK_LEN] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+// posição, na ordem das linhas, de cada coeficiente da varredura em zigue-zague
+const ZIGZAG: [usize; BLOCK_LEN] = [
+    0, 1, 8, 16, 9, 2, 3, 10,
+    17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// modelos de uma classe de componente (luminância ou crominância)
+#[derive(Clone)]
+struct Models {
+    dc: AdaptiveModel,
+    ac: AdaptiveModel,
+}
+
+// tabelas de quantização e cossenos compartilhados por todos os blocos
+struct Transform {
+    tables: [[f32; BLOCK_LEN]; 2],
+    cosines: [[f32; BLOCK]; BLOCK],
+}
+
+impl Transform {
+    fn new(quality: u8) -> Result<Self> {
+        if quality == 0 || quality > 100 {
+            return Err(Error::UnsupportedImage("qualidade deve estar entre 1 e 100"));
+        }
+        // escala do libjpeg: 50 mantém as tabelas do anexo K
+        let scale = if quality < 50 {
+            5000 / quality as u32
+        } else {
+            200 - 2 * quality as u32
+        };
+        let scaled = |table: &[u16; BLOCK_LEN]| {
+            let mut output = [0f32; BLOCK_LEN];
+            for (value, &base) in output.iter_mut().zip(table.iter()) {
+                *value = ((base as u32 * scale + 50) / 100).clamp(1, 255) as f32;
+            }
+            output
+        };
+
+        let mut cosines = [[0f32; BLOCK]; BLOCK];
+        for (u, row) in cosines.iter_mut().enumerate() {
+            let factor = if u == 0 { (0.5f32).sqrt() } else { 1.0 };
+            for (x, value) in row.iter_mut().enumerate() {
+                *value = factor * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+            }
+        }
+
+        Ok(Self {
+            tables: [scaled(&LUMA_TABLE), scaled(&CHROMA_TABLE)],
+            cosines,
+        })
+    }
+
+    // DCT direta e quantização
+    fn forward(&self, samples: &[f32; BLOCK_LEN], table: usize, output: &mut [i32; BLOCK_LEN]) {
+        let mut rows = [0f32; BLOCK_LEN];
+        for y in 0..BLOCK {
+            for u in 0..BLOCK {
+                rows[y * BLOCK + u] = (0..BLOCK).map(|x| samples[y * BLOCK + x] * self.cosines[u][x]).sum();
+            }
+        }
+        for v in 0..BLOCK {
+            for u in 0..BLOCK {
+                let coefficient: f32 = (0..BLOCK).map(|y| rows[y * BLOCK + u] * self.cosines[v][y]).sum();
+                output[v * BLOCK + u] = (coefficient / self.tables[table][v * BLOCK + u]).round() as i32;
+            }
+        }
+    }
+
+    // reconstrução dos coeficientes e DCT inversa
+    fn inverse(&self, coefficients: &[i32; BLOCK_LEN], table: usize, output: &mut [f32; BLOCK_LEN]) {
+        let mut columns = [0f32; BLOCK_LEN];
+        for y in 0..BLOCK {
+            for u in 0..BLOCK {
+                columns[y * BLOCK + u] = (0..BLOCK)
+                    .map(|v| coefficients[v * BLOCK + u] as f32 * self.tables[table][v * BLOCK + u] * self.cosines[v][y])
+                    .sum();
+            }
+        }
+        for y in 0..BLOCK {
+            for x in 0..BLOCK {
+                output[y * BLOCK + x] = (0..BLOCK).map(|u| columns[y * BLOCK + u] * self.cosines[u][x]).sum();
+            }
+        }
+    }
+}
+
+// memória usada pelos componentes em ponto flutuante e pelos modelos
+pub fn memory_len(width: usize, height: usize, planes: usize) -> u64 {
+    (width as u64)
+        .saturating_mul(height as u64)
+        .saturating_mul(planes as u64)
+        .saturating_mul(2 * size_of::<f32>() as u64)
+        .saturating_add(2 * AdaptiveModel::memory_len(CATEGORIES + RUN_LEVELS))
+}
+
+// quantidade de blocos codificados, cada um com ao menos a categoria do DC
+pub fn blocks(width: usize, height: usize, planes: usize) -> u64 {
+    (width.div_ceil(BLOCK) as u64)
+        .saturating_mul(height.div_ceil(BLOCK) as u64)
+        .saturating_mul(planes as u64)
+}
+
+// codifica a imagem; retorna o PSNR, em dB, da imagem que o decodificador reconstruirá
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image, quality: u8) -> Result<f64> {
+    let components = to_components(image)?;
+    let mut reconstructed = vec![vec![0f32; image.width * image.height]; image.planes.len()];
+    code_components(encoder, &Transform::new(quality)?, image.width, image.height, Some(&components), &mut reconstructed)?;
+
+    let output = from_components(image, &reconstructed)?;
+    Ok(psnr(image, &output))
+}
+
+// reconstrói os planos de `image`, que já deve ter as dimensões e profundidades gravadas
+pub fn decode(decoder: &mut ArithmeticDecoder, image: &mut Image, quality: u8) -> Result<()> {
+    let transform = Transform::new(quality)?;
+    validate(image)?;
+    let mut reconstructed = vec![vec![0f32; image.width * image.height]; image.planes.len()];
+    code_components(decoder, &transform, image.width, image.height, None, &mut reconstructed)?;
+    *image = from_components(image, &reconstructed)?;
+    Ok(())
+}
+
+// percorre os blocos de cada componente nos dois sentidos; `source` existe apenas na
+// codificação, e `output` recebe os componentes reconstruídos
+fn code_components<C: Coder>(
+    coder: &mut C,
+    transform: &Transform,
+    width: usize,
+    height: usize,
+    source: Option<&[Vec<f32>]>,
+    output: &mut [Vec<f32>],
+) -> Result<()> {
+    let max_total = coder.max_total();
+    let models = Models {
+        dc: AdaptiveModel::new(CATEGORIES, max_total)?,
+        ac: AdaptiveModel::new(RUN_LEVELS, max_total)?,
+    };
+    let mut models = [models.clone(), models];
+    let mut bits = AdaptiveModel::new(2, max_total)?;
+    let colour = output.len() >= 3;
+
+    for (component, reconstructed) in output.iter_mut().enumerate() {
+        // luminância e canais extras usam a primeira tabela, crominância a segunda
+        let table = if colour && (component == 1 || component == 2) { 1 } else { 0 };
+        let mut previous_dc = 0;
+        for block_y in (0..height).step_by(BLOCK) {
+            for block_x in (0..width).step_by(BLOCK) {
+                let mut coefficients = [0i32; BLOCK_LEN];
+                if let Some(source) = source {
+                    // blocos incompletos repetem a última linha e a última coluna
+                    let mut samples = [0f32; BLOCK_LEN];
+                    for y in 0..BLOCK {
+                        for x in 0..BLOCK {
+                            let index = (block_y + y).min(height - 1) * width + (block_x + x).min(width - 1);
+                            samples[y * BLOCK + x] = source[component][index] - 128.0;
+                        }
+                    }
+                    transform.forward(&samples, table, &mut coefficients);
+                }
+
+                code_block(coder, &mut models[table], &mut bits, &mut coefficients, &mut previous_dc)?;
+
+                let mut samples = [0f32; BLOCK_LEN];
+                transform.inverse(&coefficients, table, &mut samples);
+                for y in 0..BLOCK.min(height - block_y) {
+                    for x in 0..BLOCK.min(width - block_x) {
+                        reconstructed[(block_y + y) * width + block_x + x] = samples[y * BLOCK + x] + 128.0;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// DC como diferença do bloco anterior (categoria e bits) e AC como pares corrida/categoria
+// seguidos dos bits do valor, na ordem em zigue-zague
+fn code_block<C: Coder>(
+    coder: &mut C,
+    models: &mut Models,
+    bits: &mut AdaptiveModel,
+    coefficients: &mut [i32; BLOCK_LEN],
+    previous_dc: &mut i32,
+) -> Result<()> {
+    let difference = code_value(coder, &mut models.dc, bits, coefficients[0] - *previous_dc)?;
+    coefficients[0] = *previous_dc + difference;
+    *previous_dc = coefficients[0];
+
+    let mut index = 1;
+    while index < BLOCK_LEN {
+        // no codificador, o símbolo é calculado a partir dos coeficientes restantes
+        let next = (index..BLOCK_LEN).find(|&next| coefficients[ZIGZAG[next]] != 0);
+        let (run, value) = match next {
+            Some(next) if next - index >= 16 => (15, 0),
+            Some(next) => ((next - index) as u32, coefficients[ZIGZAG[next]]),
+            None => (0, 0),
+        };
+
+        let symbol = coder.code(&mut models.ac, (run << 4) | category(value))?;
+        let run = (symbol >> 4) as usize;
+        let size = symbol & 0x0F;
+        if size == 0 {
+            if symbol == END_OF_BLOCK {
+                break;
+            }
+            if symbol != ZERO_RUN || index + 16 > BLOCK_LEN {
+                return Err(Error::InvalidContainer("dados codificados corrompidos"));
+            }
+            index += 16;
+            continue;
+        }
+
+        index += run;
+        if index >= BLOCK_LEN {
+            return Err(Error::InvalidContainer("dados codificados corrompidos"));
+        }
+        coefficients[ZIGZAG[index]] = code_bits(coder, bits, size, value)?;
+        index += 1;
+    }
+
+    // no decodificador, os coeficientes não lidos são zero
+    for &position in ZIGZAG[index.min(BLOCK_LEN)..].iter() {
+        coefficients[position] = 0;
+    }
+    Ok(())
+}
+
+// categoria seguida dos bits do valor
+fn code_value<C: Coder>(coder: &mut C, model: &mut AdaptiveModel, bits: &mut AdaptiveModel, value: i32) -> Result<i32> {
+    let size = coder.code(model, category(value))?;
+    if size == 0 {
+        return Ok(0);
+    }
+    code_bits(coder, bits, size, value)
+}
+
+// como no JPEG, valores negativos são gravados como valor + 2^categoria - 1
+fn code_bits<C: Coder>(coder: &mut C, bits: &mut AdaptiveModel, size: u32, value: i32) -> Result<i32> {
+    let pattern = if value < 0 { value + (1 << size) - 1 } else { value } as u32;
+    let mut output: u32 = 0;
+    for shift in (0..size).rev() {
+        let bit = coder.code(bits, (pattern >> shift) & 1)?;
+        output = (output << 1) | bit;
+    }
+    let output = output as i32;
+    if output < 1 << (size - 1) {
+        Ok(output - (1 << size) + 1)
+    } else {
+        Ok(output)
+    }
+}
+
+fn category(value: i32) -> u32 {
+    (u32::BITS - value.unsigned_abs().leading_zeros()).min(CATEGORIES as u32 - 1)
+}
+
+// converte B, G e R em Y, Cb e Cr (JFIF); os demais planos são mantidos
+fn to_components(image: &Image) -> Result<Vec<Vec<f32>>> {
+    validate(image)?;
+    let mut components: Vec<Vec<f32>> = image
+        .planes
+        .iter()
+        .map(|plane| plane.samples.iter().map(|&sample| sample as f32).collect())
+        .collect();
+    if let [first, second, third, ..] = components.as_mut_slice() {
+        for ((first, second), third) in first.iter_mut().zip(second.iter_mut()).zip(third.iter_mut()) {
+            let (blue, green, red) = (*first, *second, *third);
+            *first = 0.299 * red + 0.587 * green + 0.114 * blue;
+            *second = -0.168736 * red - 0.331264 * green + 0.5 * blue + 128.0;
+            *third = 0.5 * red - 0.418688 * green - 0.081312 * blue + 128.0;
+        }
+    }
+    Ok(components)
+}
+
+fn validate(image: &Image) -> Result<()> {
+    if image.planes.iter().any(|plane| plane.depth != 8) {
+        return Err(Error::UnsupportedImage("o modo dct requer canais de 8 bits sem paleta"));
+    }
+    Ok(())
+}
+
+fn from_components(image: &Image, components: &[Vec<f32>]) -> Result<Image> {
+    let mut output = Image::new(image.width, image.height, &image.depths())?;
+    let sample = |value: f32| value.round().clamp(0.0, 255.0) as u16;
+    for index in 0..image.width * image.height {
+        if components.len() >= 3 {
+            let luma = components[0][index];
+            let blue = components[1][index] - 128.0;
+            let red = components[2][index] - 128.0;
+            output.planes[0].samples[index] = sample(luma + 1.772 * blue);
+            output.planes[1].samples[index] = sample(luma - 0.344136 * blue - 0.714136 * red);
+            output.planes[2].samples[index] = sample(luma + 1.402 * red);
+        }
+        let first = if components.len() >= 3 { 3 } else { 0 };
+        for (plane, component) in output.planes.iter_mut().zip(components.iter()).skip(first) {
+            plane.samples[index] = sample(component[index]);
+        }
+    }
+    Ok(output)
+}
+
+// relação sinal-ruído de pico entre duas imagens de 8 bits, em dB
+pub fn psnr(original: &Image, output: &Image) -> f64 {
+    let mut squared_error: f64 = 0.0;
+    let mut count: u64 = 0;
+    for (plane, other) in original.planes.iter().zip(output.planes.iter()) {
+        for (&sample, &other) in plane.samples.iter().zip(other.samples.iter()) {
+            let difference = sample as f64 - other as f64;
+            squared_error += difference * difference;
+            count += 1;
+        }
+    }
+    if squared_error == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (255.0 * 255.0 / (squared_error / count as f64)).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        photo,
+    };
+
+    fn round_trip(image: &Image, quality: u8) -> (Image, f64, usize) {
+        let mut expected = 0.0;
+        let encoded = encode(|encoder| {
+            expected = super::encode(encoder, image, quality)?;
+            Ok(())
+        });
+        let mut decoded = Image::new(image.width, image.height, &image.depths()).unwrap();
+        decode(&encoded, |decoder| super::decode(decoder, &mut decoded, quality)).unwrap();
+        (decoded, expected, encoded.len())
+    }
+
+    // o PSNR informado na codificação é o da imagem decodificada, e cresce com a qualidade
+    #[test]
+    fn lossy_round_trip() {
+        let image = photo(21, 13, &[8, 8, 8]);
+        let (decoded, expected, low_len) = round_trip(&image, 20);
+        assert_eq!(psnr(&image, &decoded), expected);
+        assert!(expected > 25.0);
+        let (decoded, expected, high_len) = round_trip(&image, 95);
+        assert_eq!(psnr(&image, &decoded), expected);
+        assert!(high_len > low_len);
+
+        let gray = photo(8, 8, &[8]);
+        let (decoded, expected, _) = round_trip(&gray, 100);
+        assert_eq!(psnr(&gray, &decoded), expected);
+        assert_eq!(psnr(&gray, &gray), f64::INFINITY);
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let image = photo(8, 8, &[8]);
+        let mut encoder = ArithmeticEncoder::new(0, (1 << 30) - 1, Vec::new()).unwrap();
+        assert!(matches!(super::encode(&mut encoder, &image, 0), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(super::encode(&mut encoder, &image, 101), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(super::encode(&mut encoder, &photo(8, 8, &[4]), 50), Err(Error::UnsupportedImage(_))));
+        assert_eq!(blocks(17, 8, 3), 9);
+    }
+}
@@ -1,48 +1,55 @@
 use debug_print::debug_print;
-use std::io::Read;
-use std::io::Seek;
 use std::io::Write;
-use std::fs::File;
 
-use arithmetic_coding::ArithmeticCoding;
+use crate::model::{
+    Coder,
+    Model,
+};
+use crate::ArithmeticCoding;
+use crate::Error;
+use crate::Result;
 
-pub struct ArithmeticDecoder {
+pub struct ArithmeticDecoder<'a> {
     ac: ArithmeticCoding,
     code: u32,
+    input: &'a [u8],
     value: u32,
     value_count: u64,
     value_shifts: u32,
     last_value_shifts: u32,
-    output: File,
 }
 
-impl ArithmeticDecoder {
-    pub fn new(ac: ArithmeticCoding, value_count: u64, last_value_shifts: u32, output: File) -> Self {
+impl<'a> ArithmeticDecoder<'a> {
+    pub fn new(ac: ArithmeticCoding, input: &'a [u8], last_value_shifts: u32) -> Result<Self> {
+        ac.validate()?;
+        if !input.len().is_multiple_of(size_of::<u32>()) || last_value_shifts > 32 {
+            return Err(Error::InvalidContainer("região de dados codificados inválida"));
+        }
         let code = 0;
         let value = 0;
+        let value_count = (input.len() / size_of::<u32>()) as u64;
         let value_shifts = 0;
-        Self {
+        let mut decoder = Self {
             ac,
             code,
+            input,
             value,
             value_count,
             value_shifts,
             last_value_shifts,
-            output,
+        };
+
+        for _ in 0..decoder.ac.precision {
+            let bit = decoder.get_bit_from_value();
+            decoder.code = (decoder.code << 1) | bit;
         }
+
+        debug_print!("\t{:012b}\n", decoder.code);
+        Ok(decoder)
     }
-    
-    pub fn decode(&mut self, input: &mut File) {
-        input.seek(std::io::SeekFrom::Start(0)).unwrap();
-        self.read_value_from_file(input);
 
-        for _ in 0..self.ac.precision {
-            let bit = self.get_bit_from_value(input);
-            self.code = (self.code << 1) | bit;
-        }
+    pub fn decode<W: Write>(&mut self, mut output: W) -> Result<()> {
 
-        debug_print!("\t{:012b}\n", self.code);
-        
         debug_print!("\t\t|\t{:012b}\t{:012b}\t|",
             self.ac.low,
             self.ac.high,
@@ -52,47 +59,77 @@ impl ArithmeticDecoder {
         let mut count: u64 = 0;
 
         while count < total {
-            let low = self.ac.low;
-            let high = self.ac.high;
-
-            let range = (high - low + 1) as u64;
-            let offset = (self.code - low) as u64;
-            let value = ((offset + 1) * (total + 1)) / range;
+            let value = self.decode_value(total)?;
+            let symbol = self.ac.symbols.get_symbol_by_value(value)?;
 
-            let symbol = self.ac.symbols.get_symbol_by_value(value);
-
-            self.write_decoded_symbol(symbol as u8);
-            self.update(symbol as u8, input);
+            output.write_all(&[symbol])?;
+            self.update(symbol)?;
 
             count += 1;
         }
 
+        output.flush()?;
+
         debug_print!("\n\n");
+        Ok(())
+    }
+
+    // decodifica um símbolo com qualquer modelo, atualizando-o como o codificador fez
+    pub fn decode_symbol<M: Model + ?Sized>(&mut self, model: &mut M) -> Result<u32> {
+        let total = model.total();
+        let value = self.decode_value(total)?;
+        let symbol = model.symbol_by_value(value)?;
+        let (
+            low_of_symbol,
+            high_of_symbol,
+        ) = model.low_and_high(symbol)?;
+
+        self.decode_interval(low_of_symbol, high_of_symbol, total)?;
+        model.update(symbol);
+        Ok(symbol)
     }
 
-    fn update(&mut self, symbol: u8, input: &mut File) {
+    // maior total de frequências que um modelo pode ter na precisão do arquivo
+    pub fn max_total(&self) -> u64 {
+        self.ac.half_bit() as u64 - 1
+    }
+
+    // posição do código dentro do intervalo atual, na escala das frequências do modelo
+    fn decode_value(&self, total: u64) -> Result<u64> {
+        let low = self.ac.low;
+        let high = self.ac.high;
+
+        if self.code < low || self.code > high {
+            return Err(Error::InvalidContainer("dados codificados corrompidos"));
+        }
+
+        let range = (high - low) as u64 + 1;
+        let offset = (self.code - low) as u64;
+        Ok(((offset + 1) * (total + 1) - 1) / range)
+    }
+
+    fn update(&mut self, symbol: u8) -> Result<()> {
         let (
             low_of_symbol,
             high_of_symbol,
-        ) = self.ac.symbols.get_low_and_high(symbol);
-    
-        let range = (self.ac.high - self.ac.low + 1) as u64;
-        let total = self.ac.symbols.total + 1;
-        let old_low = self.ac.low;
-        
-        self.ac.low = old_low + ((low_of_symbol * range) / total) as u32;
-        self.ac.high = old_low + ((high_of_symbol * range) / total) as u32 - 1;
-
-        debug_print!("\n\t{}\t|\t{:012b}\t{:012b}\t|",
-            symbol,
+        ) = self.ac.symbols.get_low_and_high(symbol)?;
+
+        let total = self.ac.symbols.total;
+        self.decode_interval(low_of_symbol, high_of_symbol, total)
+    }
+
+    fn decode_interval(&mut self, low_of_symbol: u64, high_of_symbol: u64, total: u64) -> Result<()> {
+        self.ac.narrow(low_of_symbol, high_of_symbol, total)?;
+
+        debug_print!("\n\t{}\t{}\t|\t{:012b}\t{:012b}\t|",
+            low_of_symbol,
+            high_of_symbol,
             self.ac.low,
             self.ac.high,
         );
-    
-        self.ac.verify_low_and_high();
 
         while ((self.ac.low ^ self.ac.high) & self.ac.full_bit()) == 0 {
-            self.shift(input);
+            self.shift();
 
             self.ac.low = (self.ac.low << 1) & self.ac.full_mask();
             self.ac.high = ((self.ac.high << 1) & self.ac.full_mask()) | 1;
@@ -104,11 +141,11 @@ impl ArithmeticDecoder {
 
             debug_print!("\t{:012b}", self.code);
 
-            self.ac.verify_low_and_high();
+            self.ac.verify_low_and_high()?;
         }
-        
+
         while (self.ac.low & !self.ac.high & self.ac.half_bit()) != 0 {
-            self.underflow(input);
+            self.underflow();
 
             self.ac.low = (self.ac.low << 1) & self.ac.half_mask();
             self.ac.high = self.ac.full_bit() | ((self.ac.high << 1) & self.ac.half_mask()) | 1;
@@ -120,64 +157,98 @@ impl ArithmeticDecoder {
 
             debug_print!("\t{:012b}", self.code);
 
-            self.ac.verify_low_and_high();
+            self.ac.verify_low_and_high()?;
         }
+
+        Ok(())
     }
-    
-    fn shift(&mut self, input: &mut File) {
-        let bit = self.get_bit_from_value(input);
+
+    fn shift(&mut self) {
+        let bit = self.get_bit_from_value();
         self.code = ((self.code << 1) & self.ac.full_mask()) | bit;
     }
 
-    fn underflow(&mut self, input: &mut File) {
-        let bit = self.get_bit_from_value(input);
+    fn underflow(&mut self) {
+        let bit = self.get_bit_from_value();
         self.code = (self.code & self.ac.full_bit()) | ((self.code << 1) & self.ac.half_mask()) | bit;
     }
 
-    fn get_bit_from_value(&mut self, input: &mut File) -> u32 {
-        let bit: u32;
-        if self.value_shifts == 0 {
-            bit = self.value;
-            self.read_value_from_file(input);
-        } else {
-            bit = self.value >> self.value_shifts;
-            self.value &= u32::MAX >> 32 - self.value_shifts;
-            self.value_shifts -= 1;
+    // após o fim da região codificada, os bits lidos são zeros
+    fn get_bit_from_value(&mut self) -> u32 {
+        while self.value_shifts == 0 {
+            self.read_value_from_input();
         }
-        bit
+        self.value_shifts -= 1;
+        (self.value >> self.value_shifts) & 1
     }
 
-    fn read_value_from_file(&mut self, input: &mut File) {
-        let mut value_buffer: [u8; 4] = [0,0,0,0];
-    
-        if let Err(e) = input.read_exact(&mut value_buffer) {
-            eprintln!("\nErro ao ler os dados codificados: {}\n", e);
-            std::process::exit(1);
-        };
-        if self.value_count > 0 {
-            self.value_count -= 1;
+    fn read_value_from_input(&mut self) {
+        match self.input.split_first_chunk::<4>() {
+            Some((value_buffer, rest)) => {
+                self.input = rest;
+                self.value_count -= 1;
+                self.value = u32::from_le_bytes(*value_buffer);
+                self.value_shifts = if self.is_last_value() {
+                    self.last_value_shifts
+                } else {
+                    32
+                };
+            }
+            None => {
+                self.value = 0;
+                self.value_shifts = 32;
+            }
         }
-
-        self.value = u32::from_le_bytes(value_buffer);
-        self.value_shifts = if self.is_last_value() {
-            self.last_value_shifts - 1
-        } else {
-            31
-        };
     }
 
     fn is_last_value(&self) -> bool {
-        if self.value_count == 0 {
-            true
-        } else {
-            false
-        }
+        self.value_count == 0
+    }
+}
+
+impl Coder for ArithmeticDecoder<'_> {
+    fn code<M: Model + ?Sized>(&mut self, model: &mut M, _symbol: u32) -> Result<u32> {
+        self.decode_symbol(model)
     }
 
-    fn write_decoded_symbol(&mut self, symbol: u8) {
-        if let Err(e) = self.output.write(&[symbol]) {
-            eprintln!("\nNão foi possível gravar no arquivo de saída: {}\n", e);
-            std::process::exit(1);
+    fn max_total(&self) -> u64 {
+        self.max_total()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::encoder::ArithmeticEncoder;
+    use crate::limits::DecodeLimits;
+    use crate::testing::{
+        HIGH,
+        LOW,
+    };
+
+    #[test]
+    fn malformed_regions_are_rejected() {
+        let ac = ArithmeticCoding::new(LOW, HIGH).unwrap();
+        assert!(matches!(ArithmeticDecoder::new(ac.clone(), &[0; 5], 32), Err(Error::InvalidContainer(_))));
+        assert!(matches!(ArithmeticDecoder::new(ac.clone(), &[0; 4], 33), Err(Error::InvalidContainer(_))));
+        let mut forged = ac;
+        forged.precision = 40;
+        assert!(matches!(ArithmeticDecoder::new(forged, &[0; 4], 32), Err(Error::LowPrecision)));
+    }
+
+    // depois do fim da região codificada, o decodificador lê zeros em vez de entrar em pânico
+    #[test]
+    fn truncated_regions_decode_without_panicking() {
+        let input: Vec<u8> = (0..4000u32).map(|index| (index * 7 % 256) as u8).collect();
+        let mut encoded = Vec::new();
+        ArithmeticEncoder::new(LOW, HIGH, &mut encoded).unwrap().encode(&input).unwrap();
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let data = container.encoded_data;
+        let mut decoder = ArithmeticDecoder::new(container.ac, &data[..data.len() / 8 * 4], 32).unwrap();
+        let mut decoded = Vec::new();
+        if decoder.decode(&mut decoded).is_ok() {
+            assert_ne!(decoded, input);
         }
     }
-}
\ No newline at end of file
+}
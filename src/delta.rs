@@ -0,0 +1,211 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::model;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Error;
+use crate::Result;
+
+pub const MAX_ORDER: u8 = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeltaOptions {
+    // bytes de cada elemento: 1, 2, 4 ou 8
+    pub width: u8,
+    // distância, em elementos, até o elemento anterior usado na predição; em dados intercalados,
+    // como amostras estéreo, é a quantidade de canais
+    pub stride: u32,
+    // 0 (sem predição), 1 (diferença para o anterior) ou 2 (diferença da diferença)
+    pub order: u8,
+    pub byte_order: ByteOrder,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self::new(2, 1, 1, ByteOrder::Little)
+    }
+}
+
+impl DeltaOptions {
+    pub fn new(width: u8, stride: u32, order: u8, byte_order: ByteOrder) -> Self {
+        Self {
+            width,
+            stride,
+            order,
+            byte_order,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !matches!(self.width, 1 | 2 | 4 | 8) || self.stride == 0 || self.order > MAX_ORDER {
+            return Err(Error::InvalidContainer("parâmetros do filtro delta inválidos"));
+        }
+        Ok(())
+    }
+
+    fn mask(&self) -> u64 {
+        u64::MAX >> (64 - 8 * self.width as u32)
+    }
+}
+
+// um modelo por byte do resíduo, do menos para o mais significativo, e um para os bytes finais
+// que não completam um elemento
+pub fn memory_len(options: &DeltaOptions) -> u64 {
+    AdaptiveModel::memory_len(256).saturating_mul(options.width as u64 + 1)
+}
+
+// maior tamanho de arquivo que `len` bytes codificados podem conter: cada byte é um símbolo de
+// um modelo de 256 símbolos
+pub fn max_len(len: usize) -> u64 {
+    model::max_symbols(len, 256)
+}
+
+// cada elemento é substituído pela diferença (módulo 2^(8 * largura)) para a previsão feita com
+// os elementos anteriores, e os bytes da diferença, do menos para o mais significativo, são
+// codificados com o modelo da sua posição
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, input: &[u8], options: &DeltaOptions) -> Result<()> {
+    let mut coded = vec![0u8; input.len()];
+    code_bytes(encoder, &mut coded, Some(input), options)
+}
+
+pub fn decode(decoder: &mut ArithmeticDecoder, len: usize, options: &DeltaOptions) -> Result<Vec<u8>> {
+    let mut output = vec![0u8; len];
+    code_bytes(decoder, &mut output, None, options)?;
+    Ok(output)
+}
+
+fn code_bytes<C: Coder>(coder: &mut C, data: &mut [u8], source: Option<&[u8]>, options: &DeltaOptions) -> Result<()> {
+    options.validate()?;
+    let width = options.width as usize;
+    let stride = options.stride as usize;
+    let mask = options.mask();
+    let mut lanes = vec![AdaptiveModel::new(256, coder.max_total())?; width];
+    let mut tail = AdaptiveModel::new(256, coder.max_total())?;
+
+    let elements = data.len() / width;
+    for index in 0..elements {
+        let previous = |distance: usize| match index.checked_sub(distance.saturating_mul(stride)) {
+            Some(position) => read_element(data, position, width, options.byte_order),
+            None => 0,
+        };
+        let predicted = match options.order {
+            0 => 0,
+            1 => previous(1),
+            _ => previous(1).wrapping_mul(2).wrapping_sub(previous(2)),
+        };
+        let residual = source.map_or(0, |source| read_element(source, index, width, options.byte_order).wrapping_sub(predicted) & mask);
+        // com sinal, 0, -1, 1, -2, ... viram 0, 1, 2, 3, ..., de modo que resíduos pequenos têm
+        // os bytes altos zerados, e não 0xFF quando negativos
+        let sign = if residual >> (8 * width - 1) & 1 == 1 { mask } else { 0 };
+        let mapped = ((residual << 1) ^ sign) & mask;
+        let mut decoded = 0;
+        for (lane, model) in lanes.iter_mut().enumerate() {
+            let byte = coder.code(model, ((mapped >> (8 * lane)) & 0xFF) as u32)? as u64;
+            decoded |= byte << (8 * lane);
+        }
+        let residual = (decoded >> 1) ^ 0u64.wrapping_sub(decoded & 1);
+        write_element(data, index, width, options.byte_order, residual.wrapping_add(predicted) & mask);
+    }
+    for position in elements * width..data.len() {
+        let byte = source.map_or(0, |source| source[position]);
+        data[position] = coder.code(&mut tail, byte as u32)? as u8;
+    }
+    Ok(())
+}
+
+// elemento `index`, com `width` bytes, lido na ordem de bytes informada
+pub fn read_element(data: &[u8], index: usize, width: usize, byte_order: ByteOrder) -> u64 {
+    let mut bytes = [0u8; 8];
+    let element = &data[index * width..(index + 1) * width];
+    match byte_order {
+        ByteOrder::Little => {
+            bytes[..width].copy_from_slice(element);
+            u64::from_le_bytes(bytes)
+        }
+        ByteOrder::Big => {
+            bytes[8 - width..].copy_from_slice(element);
+            u64::from_be_bytes(bytes)
+        }
+    }
+}
+
+pub fn write_element(data: &mut [u8], index: usize, width: usize, byte_order: ByteOrder, value: u64) {
+    let element = &mut data[index * width..(index + 1) * width];
+    match byte_order {
+        ByteOrder::Little => element.copy_from_slice(&value.to_le_bytes()[..width]),
+        ByteOrder::Big => element.copy_from_slice(&value.to_be_bytes()[8 - width..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        Rng,
+    };
+
+    fn round_trip(input: &[u8], options: &DeltaOptions) -> usize {
+        let encoded = encode(|encoder| super::encode(encoder, input, options));
+        let decoded = decode(&encoded, |decoder| super::decode(decoder, input.len(), options)).unwrap();
+        assert_eq!(decoded, input);
+        encoded.len()
+    }
+
+    #[test]
+    fn every_width_and_order_round_trips() {
+        let mut rng = Rng(9);
+        // rampa de inteiros com ruído e um byte final que não completa um elemento
+        let values: Vec<u64> = (0..2000u64).map(|index| index * 1000 + rng.below(50)).collect();
+        for width in [1u8, 2, 4, 8] {
+            for byte_order in [ByteOrder::Little, ByteOrder::Big] {
+                let mut input = vec![0u8; values.len() * width as usize];
+                for (index, &value) in values.iter().enumerate() {
+                    write_element(&mut input, index, width as usize, byte_order, value);
+                }
+                input.push(0x5A);
+                for order in 0..=MAX_ORDER {
+                    round_trip(&input, &DeltaOptions::new(width, 1, order, byte_order));
+                }
+                round_trip(&input, &DeltaOptions::new(width, 3, 2, byte_order));
+            }
+        }
+        round_trip(&[], &DeltaOptions::default());
+    }
+
+    #[test]
+    fn prediction_shrinks_ramps() {
+        let mut input = vec![0u8; 8000];
+        for index in 0..4000 {
+            write_element(&mut input, index, 2, ByteOrder::Little, (index * 7) as u64);
+        }
+        let plain = round_trip(&input, &DeltaOptions::new(2, 1, 0, ByteOrder::Little));
+        let first = round_trip(&input, &DeltaOptions::new(2, 1, 1, ByteOrder::Little));
+        assert!(first * 10 < plain);
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        for options in [DeltaOptions::new(3, 1, 1, ByteOrder::Little), DeltaOptions::new(2, 0, 1, ByteOrder::Little), DeltaOptions::new(2, 1, 3, ByteOrder::Big)] {
+            assert!(matches!(options.validate(), Err(Error::InvalidContainer(_))));
+            let mut encoder = ArithmeticEncoder::new(0, (1 << 30) - 1, Vec::new()).unwrap();
+            assert!(matches!(super::encode(&mut encoder, b"data", &options), Err(Error::InvalidContainer(_))));
+        }
+    }
+}
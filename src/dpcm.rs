@@ -0,0 +1,108 @@
+use crate::bmp::{
+    Bmp,
+    BI_RGB,
+};
+use crate::Error;
+use crate::Result;
+
+// preditor MED (LOCO-I): escolhe entre a, b e a + b - c conforme a presença de bordas
+pub fn med(a: u8, b: u8, c: u8) -> u8 {
+    let (min, max) = if a < b { (a, b) } else { (b, a) };
+    if c >= max {
+        min
+    } else if c <= min {
+        max
+    } else {
+        (a as i16 + b as i16 - c as i16) as u8
+    }
+}
+
+// substitui cada amostra da região de pixels pelo resíduo da predição (módulo 256);
+// cabeçalho, paleta, preenchimento das linhas e bytes finais permanecem inalterados
+pub fn forward(input: &[u8]) -> Result<Vec<u8>> {
+    let bmp = parse(input)?;
+    let mut output = input.to_vec();
+    for_each_sample(&bmp, |position, a, b, c| {
+        let predicted = predict(input, a, b, c);
+        output[position] = input[position].wrapping_sub(predicted);
+    });
+    Ok(output)
+}
+
+// o cabeçalho não é alterado pela transformação direta, então é lido do próprio resíduo
+pub fn inverse(input: &[u8]) -> Result<Vec<u8>> {
+    let bmp = parse(input)?;
+    let mut output = input.to_vec();
+    for_each_sample(&bmp, |position, a, b, c| {
+        let predicted = predict(&output, a, b, c);
+        output[position] = input[position].wrapping_add(predicted);
+    });
+    Ok(output)
+}
+
+// a predição é feita sobre os bytes, então só se aplica a amostras de 8 bits não comprimidas
+fn parse(input: &[u8]) -> Result<Bmp> {
+    let bmp = Bmp::parse(input)?;
+    if bmp.compression != BI_RGB || !matches!(bmp.bits_per_pixel, 8 | 24 | 32) {
+        return Err(Error::UnsupportedImage("o modo dpcm requer BMP não comprimido de 8, 24 ou 32 bits"));
+    }
+    if bmp.pixel_offset as u64 + bmp.pixel_len() as u64 > input.len() as u64 {
+        return Err(Error::InvalidImage("dados de pixels fora do arquivo"));
+    }
+    Ok(bmp)
+}
+
+fn predict(samples: &[u8], a: Option<usize>, b: Option<usize>, c: Option<usize>) -> u8 {
+    match (a, b, c) {
+        (Some(a), Some(b), Some(c)) => med(samples[a], samples[b], samples[c]),
+        (Some(a), None, _) => samples[a],
+        (None, Some(b), _) => samples[b],
+        _ => 0,
+    }
+}
+
+// percorre as amostras na ordem em que estão gravadas, informando as posições dos
+// vizinhos à esquerda (a), acima (b) e acima à esquerda (c) do mesmo canal
+fn for_each_sample<F: FnMut(usize, Option<usize>, Option<usize>, Option<usize>)>(bmp: &Bmp, mut f: F) {
+    let bytes_per_pixel = bmp.bytes_per_pixel();
+    for y in 0..bmp.height as usize {
+        let row = bmp.pixel_offset + y * bmp.row_size;
+        for x in 0..bmp.width as usize {
+            for channel in 0..bytes_per_pixel {
+                let position = row + x * bytes_per_pixel + channel;
+                let a = (x > 0).then(|| position - bytes_per_pixel);
+                let b = (y > 0).then(|| position - bmp.row_size);
+                let c = (x > 0 && y > 0).then(|| position - bmp.row_size - bytes_per_pixel);
+                f(position, a, b, c);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        bilevel_bmp,
+        bmp,
+    };
+
+    #[test]
+    fn residuals_round_trip() {
+        let input = bmp(11, 6);
+        let residuals = forward(&input).unwrap();
+        assert_eq!(residuals[..54], input[..54]);
+        // o gradiente suave deixa resíduos pequenos
+        assert!(residuals[54 + 3 * 12..].iter().filter(|&&r| !(8..=248).contains(&r)).count() > 100);
+        assert_eq!(inverse(&residuals).unwrap(), input);
+        assert_eq!((med(10, 20, 25), med(10, 20, 5), med(10, 20, 15)), (10, 20, 15));
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        assert!(matches!(forward(&bilevel_bmp(8, 8)), Err(Error::UnsupportedImage(_))));
+        let input = bmp(4, 4);
+        assert!(matches!(forward(&input[..60]), Err(Error::InvalidImage(_))));
+        assert!(matches!(inverse(b"not a bitmap"), Err(Error::InvalidImage(_))));
+    }
+}
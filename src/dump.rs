@@ -0,0 +1,50 @@
+use crate::image::Image;
+use crate::Result;
+
+// estágios intermediários da codificação no modo image, já gravados como BMP
+pub struct Dump {
+    // planos depois das transformações de cor, da paleta e do histograma
+    pub transformed: Vec<u8>,
+    // resíduos da predição em tons de cinza
+    pub residuals: Vec<u8>,
+    // mapa de calor do custo de cada pixel
+    pub costs: Vec<u8>,
+    // custo total em bits, sem o cabeçalho
+    pub bits: f64,
+    pub pixels: usize,
+}
+
+// resíduos com sinal levados a 8 bits em torno de 128, de modo que o cinza médio indica uma
+// predição exata e os tons mais claros ou mais escuros, erros para cima ou para baixo
+pub fn residual_image(residuals: &Image) -> Result<Image> {
+    let mut image = Image::new(residuals.width, residuals.height, &vec![8; residuals.planes.len()])?;
+    for plane in 0..residuals.planes.len() {
+        let depth = residuals.planes[plane].depth as i32;
+        let half = 1i32 << (depth - 1);
+        for y in 0..residuals.height {
+            for x in 0..residuals.width {
+                let residual = residuals.get(plane, x, y) as i32;
+                let signed = if residual >= half { residual - 2 * half } else { residual };
+                let scaled = if depth > 8 { signed >> (depth - 8) } else { signed << (8 - depth) };
+                image.set(plane, x, y, (128 + scaled).clamp(0, 255) as u16);
+            }
+        }
+    }
+    Ok(image)
+}
+
+// custo de cada pixel de 0 (preto) até `max_bits` ou mais (branco), passando pelo vermelho e
+// pelo amarelo; os planos estão na ordem do BMP (azul, verde e vermelho)
+pub fn heat_map(costs: &[f64], width: usize, height: usize, max_bits: f64) -> Result<Image> {
+    let mut image = Image::new(width, height, &[8, 8, 8])?;
+    for y in 0..height {
+        for x in 0..width {
+            let level = (costs[y * width + x] / max_bits).clamp(0.0, 1.0) * 3.0;
+            for (plane, start) in [(0, 2.0), (1, 1.0), (2, 0.0)] {
+                let value = (level - start).clamp(0.0, 1.0) * 255.0;
+                image.set(plane, x, y, value.round() as u16);
+            }
+        }
+    }
+    Ok(image)
+}
@@ -1,110 +1,143 @@
 use bincode::serialize_into;
 use debug_print::debug_print;
-use std::io::BufReader;
-use std::io::Read;
-use std::io::Seek;
 use std::io::Write;
-use std::fs::File;
 
-use arithmetic_coding::ArithmeticCoding;
+use crate::model::{
+    Coder,
+    Model,
+};
+use crate::ArithmeticCoding;
+use crate::Result;
 
-pub struct ArithmeticEncoder {
+// tamanhos gravados no final da codificação, informados a quem a pediu
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncodedSizes {
+    // região codificada
+    pub data_len: u64,
+    // estrutura de dados, com a tabela de símbolos
+    pub table_len: u64,
+}
+
+pub struct ArithmeticEncoder<W: Write> {
     ac: ArithmeticCoding,
     initial_low: u32,
     initial_high: u32,
     value: u32,
     value_shifts: u32,
     underflow_count: u32,
-    output: File,
+    written: u64,
+    output: W,
 }
 
-impl ArithmeticEncoder {
-    pub fn new(low: u32, high: u32, output: File) -> Self {
-        let ac = ArithmeticCoding::new(low, high);
-        let initial_low = low;
-        let initial_high = high;
+impl<W: Write> ArithmeticEncoder<W> {
+    pub fn new(low: u32, high: u32, output: W) -> Result<Self> {
+        let ac = ArithmeticCoding::new(low, high)?;
+        let initial_low = ac.low;
+        let initial_high = ac.high;
         let value = 0;
         let value_shifts = 0;
         let underflow_count = 0;
-        Self {
+        let written = 0;
+        Ok(Self {
             ac,
             initial_low,
             initial_high,
             value,
             value_shifts,
             underflow_count,
+            written,
             output,
-        }
+        })
     }
-    
-    pub fn encode(&mut self, input: &mut File) {
-        input.seek(std::io::SeekFrom::Start(0)).unwrap();
+
+    pub fn encode(&mut self, input: &[u8]) -> Result<EncodedSizes> {
         self.generate_table(input);
-        input.seek(std::io::SeekFrom::Start(0)).unwrap();
 
         debug_print!("\n\t\t|\t{:012b}\t{:012b}\t|",
             self.ac.low,
             self.ac.high,
         );
-        
-        let reader = BufReader::new(input);
-        for byte in reader.bytes() {
-            match byte {
-                Ok(byte) => {
-                    self.update(byte);
-                }
-                Err(e) => {
-                    eprintln!("\nErro ao ler o arquivo de entrada: {}\n", e);
-                    std::process::exit(1);
-                }
-            }
+
+        for &byte in input {
+            self.update(byte)?;
         }
-        
-        self.finish();
-        self.add_bit_to_value(1); // gambiarra inexplicável
+
+        let sizes = self.finish()?;
 
         debug_print!("\n\n");
+        Ok(sizes)
     }
 
-    fn generate_table(&mut self, input: &mut File) {
-        let reader = BufReader::new(input);
-        for byte in reader.bytes() {
-            match byte {
-                Ok(byte) => {
-                    self.ac.symbols.add_symbol(byte);
-                }
-                Err(e) => {
-                    eprintln!("\nErro ao ler o arquivo de entrada: {}\n", e);
-                    std::process::exit(1);
-                }
-            }
+    // codifica um símbolo com qualquer modelo; o modelo é atualizado em seguida, da mesma
+    // forma que o decodificador fará
+    pub fn encode_symbol<M: Model + ?Sized>(&mut self, model: &mut M, symbol: u32) -> Result<()> {
+        let (
+            low_of_symbol,
+            high_of_symbol,
+        ) = model.low_and_high(symbol)?;
+
+        self.encode_interval(low_of_symbol, high_of_symbol, model.total())?;
+        model.update(symbol);
+        Ok(())
+    }
+
+    // maior total de frequências que um modelo pode ter na precisão escolhida
+    pub fn max_total(&self) -> u64 {
+        self.ac.half_bit() as u64 - 1
+    }
+
+    // emite os bits finais e grava a estrutura de dados e o tamanho da região codificada;
+    // retorna os tamanhos gravados
+    pub fn finish(&mut self) -> Result<EncodedSizes> {
+        self.emit_final_bits()?;
+        self.write_trailer()
+    }
+
+    // encerra o trecho atual, completando a última palavra de 32 bits com zeros, e recomeça o
+    // intervalo como em um novo arquivo; retorna a posição, na região codificada, do próximo
+    // trecho, que pode ser decodificado independentemente dos anteriores
+    pub fn restart(&mut self) -> Result<u64> {
+        self.emit_final_bits()?;
+        if self.value_shifts > 0 {
+            self.value <<= 32 - self.value_shifts;
+            self.write_value_to_file()?;
+        }
+        self.value = 0;
+        self.value_shifts = 0;
+        self.ac.low = self.initial_low;
+        self.ac.high = self.initial_high;
+        Ok(self.written)
+    }
+
+    fn generate_table(&mut self, input: &[u8]) {
+        for &byte in input {
+            self.ac.symbols.add_symbol(byte);
         }
         self.ac.symbols.calculate_accumulated_frequency();
     }
 
-    fn update(&mut self, symbol: u8) {
+    fn update(&mut self, symbol: u8) -> Result<()> {
         let (
             low_of_symbol,
             high_of_symbol,
-        ) = self.ac.symbols.get_low_and_high(symbol);
-    
-        let range = (self.ac.high - self.ac.low + 1) as u64;
-		let total = self.ac.symbols.total + 1;
-        let old_low = self.ac.low;
-        
-        self.ac.low = old_low + ((low_of_symbol * range) / total) as u32;
-        self.ac.high = old_low + ((high_of_symbol * range) / total) as u32 - 1;
-
-        debug_print!("\n\t{}\t|\t{:012b}\t{:012b}\t|",
-            symbol,
+        ) = self.ac.symbols.get_low_and_high(symbol)?;
+
+        let total = self.ac.symbols.total;
+        self.encode_interval(low_of_symbol, high_of_symbol, total)
+    }
+
+    fn encode_interval(&mut self, low_of_symbol: u64, high_of_symbol: u64, total: u64) -> Result<()> {
+        self.ac.narrow(low_of_symbol, high_of_symbol, total)?;
+
+        debug_print!("\n\t{}\t{}\t|\t{:012b}\t{:012b}\t|",
+            low_of_symbol,
+            high_of_symbol,
             self.ac.low,
             self.ac.high,
         );
-    
-        self.ac.verify_low_and_high();
 
         while ((self.ac.low ^ self.ac.high) & self.ac.full_bit()) == 0 {
-            self.shift();
+            self.shift()?;
 
             self.ac.low = (self.ac.low << 1) & self.ac.full_mask();
             self.ac.high = ((self.ac.high << 1) & self.ac.full_mask()) | 1;
@@ -114,9 +147,9 @@ impl ArithmeticEncoder {
                 self.ac.high,
             );
 
-            self.ac.verify_low_and_high();
+            self.ac.verify_low_and_high()?;
         }
-        
+
         while (self.ac.low & !self.ac.high & self.ac.half_bit()) != 0 {
             self.underflow();
 
@@ -128,19 +161,25 @@ impl ArithmeticEncoder {
                 self.ac.high,
             );
 
-            self.ac.verify_low_and_high();
+            self.ac.verify_low_and_high()?;
         }
+
+        Ok(())
     }
-    
-    fn shift(&mut self) {
+
+    fn shift(&mut self) -> Result<()> {
         let bit = self.ac.low >> (self.ac.precision - 1);
-        self.add_bit_to_value(bit);
+        self.emit_bit(bit)
+    }
+
+    fn emit_bit(&mut self, bit: u32) -> Result<()> {
+        self.add_bit_to_value(bit)?;
 
         debug_print!("\t{}", bit);
-        
+
         for _ in 0..self.underflow_count {
             let underflow_bit = bit ^ 1;
-            self.add_bit_to_value(underflow_bit);
+            self.add_bit_to_value(underflow_bit)?;
             debug_print!("\n\t\t|\t{:012b}\t{:012b}\t|\t{}",
                 self.ac.low,
                 self.ac.high,
@@ -149,80 +188,168 @@ impl ArithmeticEncoder {
         }
 
         self.underflow_count = 0;
+        Ok(())
     }
 
     fn underflow(&mut self) {
         self.underflow_count += 1;
     }
 
-    fn add_bit_to_value(&mut self, bit: u32) {
+    // emite os bits que identificam o intervalo final; o decodificador completa o
+    // restante com zeros
+    fn emit_final_bits(&mut self) -> Result<()> {
+        self.underflow_count += 1;
+        if self.ac.low < self.ac.half_bit() {
+            self.emit_bit(0)
+        } else {
+            self.emit_bit(1)
+        }
+    }
+
+    fn add_bit_to_value(&mut self, bit: u32) -> Result<()> {
         if self.value_shifts < 32 {
             self.value = (self.value << 1) | bit;
             self.value_shifts += 1;
         } else {
-            self.write_value_to_file();
+            self.write_value_to_file()?;
             self.value = bit;
             self.value_shifts = 1;
         }
+        Ok(())
     }
 
-    fn finish(&mut self) {
-        self.write_value_to_file();
+    fn write_trailer(&mut self) -> Result<EncodedSizes> {
+        self.write_value_to_file()?;
 
         // obtém tamanho da região codificada do arquivo
-        let encoded_data_len = match self.output.metadata() {
-            Ok(metadata) => metadata.len(),
-            Err(e) => {
-                eprintln!("\nErro ao obter metadados do arquivo de saída: {}\n", e);
-                std::process::exit(1);
-            }
-        };
-        println!("\nTamanho dos dados codificados: {} bytes.", encoded_data_len);
+        let encoded_data_len = self.written;
 
         // grava estrutura de dados no arquivo de saída
         let mut ac = self.ac.clone();
         ac.low = self.initial_low;
         ac.high = self.initial_high;
-        if let Err(e) = serialize_into(&mut self.output, &ac) {
-            eprintln!("\nErro ao gravar no arquivo de saída: {}\n", e);
-            std::process::exit(1);
-        };
+        let mut buffer: Vec<u8> = Vec::new();
+        serialize_into(&mut buffer, &ac)?;
+        self.output.write_all(&buffer)?;
 
         // obtém tamanho da estrutura de dados
-        let symbols_table_len = match self.output.metadata() {
-            Ok(metadata) => metadata.len() - encoded_data_len,
-            Err(e) => {
-                eprintln!("\nErro ao obter metadados do arquivo de saída: {}\n", e);
-                std::process::exit(1);
-            }
-        };
-        println!("Tamanho da tabela de símbolos: {} bytes.\n", symbols_table_len);
+        let symbols_table_len = buffer.len() as u64;
 
         // grava tamanho da região codificada no arquivo de saída
-        if let Err(e) = self.output.write_all(&encoded_data_len.to_le_bytes()) {
-            eprintln!("\nErro ao gravar no arquivo de saída: {}\n", e);
-            std::process::exit(1);
-        };
+        self.output.write_all(&encoded_data_len.to_le_bytes())?;
 
         // grava quantidade de dígitos validos do último byte no arquivo de saída
-        if let Err(e) = self.output.write_all(&(self.value_shifts as u8).to_le_bytes()) {
-            eprintln!("\nErro ao gravar no arquivo de saída: {}\n", e);
-            std::process::exit(1);
-        };
+        self.output.write_all(&(self.value_shifts as u8).to_le_bytes())?;
+
+        self.output.flush()?;
+        Ok(EncodedSizes {
+            data_len: encoded_data_len,
+            table_len: symbols_table_len,
+        })
     }
 
-    fn write_value_to_file(&mut self) {
+    fn write_value_to_file(&mut self) -> Result<()> {
         let value_buffer = self.value.to_le_bytes();
-        if let Err(e) = self.output.write_all(&value_buffer) {
-            eprintln!("\nErro ao gravar no arquivo de saída: {}\n", e);
-            std::process::exit(1);
-        };
+        self.output.write_all(&value_buffer)?;
+        self.written += value_buffer.len() as u64;
+        Ok(())
     }
 
-    pub fn verify_file_len(&self, file_len: u64) {
+    pub fn verify_file_len(&self, file_len: u64) -> Result<()> {
         if file_len >= self.ac.half_bit() as u64 {
-            print!("\nO arquivo é muito grande para a precisão escolhida!\n\n");
-            std::process::exit(1);
+            return Err(crate::Error::FileTooLarge);
         };
+        Ok(())
+    }
+}
+
+impl<W: Write> Coder for ArithmeticEncoder<W> {
+    fn code<M: Model + ?Sized>(&mut self, model: &mut M, symbol: u32) -> Result<u32> {
+        self.encode_symbol(model, symbol)?;
+        Ok(symbol)
+    }
+
+    fn max_total(&self) -> u64 {
+        self.max_total()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container;
+    use crate::decoder::ArithmeticDecoder;
+    use crate::limits::DecodeLimits;
+    use crate::model::AdaptiveModel;
+    use crate::testing::{
+        decode,
+        encode,
+        Rng,
+        HIGH,
+        LOW,
+    };
+
+    #[test]
+    fn static_table_round_trip() {
+        let text = b"a tabela de frequencias e gravada depois dos dados codificados".repeat(20);
+        for input in [&text[..], b"", b"x", b"xxxxxxxx"] {
+            let mut encoded = Vec::new();
+            let sizes = ArithmeticEncoder::new(LOW, HIGH, &mut encoded).unwrap().encode(input).unwrap();
+            assert_eq!(sizes.data_len + sizes.table_len + 9, encoded.len() as u64);
+            let mut decoded = Vec::new();
+            container::decode(&encoded, &DecodeLimits::new(), &mut decoded).unwrap();
+            assert_eq!(decoded, input);
+        }
+    }
+
+    #[test]
+    fn adaptive_round_trip_with_low_precision() {
+        let mut rng = Rng(3);
+        let symbols: Vec<u32> = (0..5000).map(|_| rng.below(5) as u32).collect();
+        for (low, high) in [(0, 1023), (100, 70000), (LOW, HIGH), (0, u32::MAX >> 1)] {
+            let mut encoded = Vec::new();
+            let mut encoder = ArithmeticEncoder::new(low, high, &mut encoded).unwrap();
+            let mut model = AdaptiveModel::new(5, encoder.max_total()).unwrap();
+            for &symbol in &symbols {
+                encoder.encode_symbol(&mut model, symbol).unwrap();
+            }
+            encoder.finish().unwrap();
+            let decoded = decode(&encoded, |decoder| {
+                let mut model = AdaptiveModel::new(5, decoder.max_total())?;
+                symbols.iter().map(|_| decoder.decode_symbol(&mut model)).collect::<crate::Result<Vec<_>>>()
+            });
+            assert_eq!(decoded.unwrap(), symbols);
+        }
+    }
+
+    // cada trecho recomeçado é decodificado sem os anteriores
+    #[test]
+    fn restarted_segments_are_independent() {
+        let mut offsets = vec![0];
+        let encoded = encode(|encoder| {
+            for segment in 0..3u32 {
+                let mut model = AdaptiveModel::new(4, encoder.max_total())?;
+                for index in 0..1000 {
+                    encoder.encode_symbol(&mut model, (index + segment) % 4)?;
+                }
+                if segment < 2 {
+                    offsets.push(encoder.restart()?);
+                }
+            }
+            Ok(())
+        });
+        let container = container::Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let mut decoder = ArithmeticDecoder::new(container.ac.clone(), &container.encoded_data[offsets[2] as usize..], container.last_value_shifts).unwrap();
+        let mut model = AdaptiveModel::new(4, decoder.max_total()).unwrap();
+        for index in 0..1000 {
+            assert_eq!(decoder.decode_symbol(&mut model).unwrap(), (index + 2) % 4);
+        }
+    }
+
+    #[test]
+    fn files_larger_than_the_precision_are_rejected() {
+        let encoder = ArithmeticEncoder::new(0, 1023, Vec::new()).unwrap();
+        assert!(encoder.verify_file_len(255).is_ok());
+        assert!(matches!(encoder.verify_file_len(256), Err(crate::Error::FileTooLarge)));
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+use std::io;
+
+use crate::limits::Limit;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Serialization(bincode::Error),
+    InvalidContainer(&'static str),
+    InvalidInterval,
+    LowPrecision,
+    FileTooLarge,
+    SymbolNotFound,
+    LimitExceeded(Limit, u64),
+    InvalidImage(&'static str),
+    UnsupportedImage(&'static str),
+    InvalidAudio(&'static str),
+    UnsupportedAudio(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Erro de entrada/saída: {}", e),
+            Error::Serialization(e) => write!(f, "Erro ao ler a estrutura de dados: {}", e),
+            Error::InvalidContainer(reason) => write!(f, "Arquivo codificado inválido: {}", reason),
+            Error::InvalidInterval => write!(f, "Low maior que high!"),
+            Error::LowPrecision => write!(f, "Precisão muito baixa!"),
+            Error::FileTooLarge => write!(f, "O arquivo é muito grande para a precisão escolhida!"),
+            Error::SymbolNotFound => write!(f, "Símbolo não encontrado!"),
+            Error::LimitExceeded(limit, max) => write!(f, "Limite de {} excedido ({} bytes)", limit, max),
+            Error::InvalidImage(reason) => write!(f, "Imagem inválida: {}", reason),
+            Error::UnsupportedImage(reason) => write!(f, "Imagem não suportada: {}", reason),
+            Error::InvalidAudio(reason) => write!(f, "Áudio inválido: {}", reason),
+            Error::UnsupportedAudio(reason) => write!(f, "Áudio não suportado: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Serialization(e)
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::delta::{
+    read_element,
+    write_element,
+    ByteOrder,
+};
+use crate::encoder::ArithmeticEncoder;
+use crate::model;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Error;
+use crate::Result;
+
+// tabelas dos preditores FCM e DFCM, com 2^16 entradas, como no FPC
+const TABLE_BITS: u32 = 16;
+const TABLE_MASK: usize = (1 << TABLE_BITS) - 1;
+
+// valor anterior (como no Gorilla), FCM e DFCM
+const PREDICTORS: usize = 3;
+// bits significativos entre o primeiro e o último bit 1 do XOR, codificados em grupos de até 8
+const CHUNK_BITS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FloatOptions {
+    // 4 (f32) ou 8 (f64)
+    pub width: u8,
+    pub byte_order: ByteOrder,
+}
+
+impl Default for FloatOptions {
+    fn default() -> Self {
+        Self::new(8, ByteOrder::Little)
+    }
+}
+
+impl FloatOptions {
+    pub fn new(width: u8, byte_order: ByteOrder) -> Self {
+        Self {
+            width,
+            byte_order,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !matches!(self.width, 4 | 8) {
+            return Err(Error::InvalidContainer("largura de ponto flutuante inválida"));
+        }
+        Ok(())
+    }
+
+    fn bits(&self) -> u32 {
+        8 * self.width as u32
+    }
+}
+
+// previsões do próximo padrão de bits a partir dos anteriores: o FCM guarda o valor que seguiu
+// o último contexto (hash dos valores recentes) e o DFCM, a diferença que seguiu o último
+// contexto de diferenças, somada ao valor anterior
+struct Predictors {
+    fcm: Vec<u64>,
+    dfcm: Vec<u64>,
+    fcm_hash: usize,
+    dfcm_hash: usize,
+    last: u64,
+    bits: u32,
+    mask: u64,
+}
+
+impl Predictors {
+    fn new(bits: u32) -> Self {
+        Self {
+            fcm: vec![0; 1 << TABLE_BITS],
+            dfcm: vec![0; 1 << TABLE_BITS],
+            fcm_hash: 0,
+            dfcm_hash: 0,
+            last: 0,
+            bits,
+            mask: u64::MAX >> (64 - bits),
+        }
+    }
+
+    fn predictions(&self) -> [u64; PREDICTORS] {
+        [
+            self.last,
+            self.fcm[self.fcm_hash],
+            self.dfcm[self.dfcm_hash].wrapping_add(self.last) & self.mask,
+        ]
+    }
+
+    // os hashes usam os bits altos (sinal, expoente e início da mantissa) dos valores e das
+    // diferenças
+    fn update(&mut self, value: u64) {
+        self.fcm[self.fcm_hash] = value;
+        self.fcm_hash = ((self.fcm_hash << 6) ^ (value >> (self.bits - 16)) as usize) & TABLE_MASK;
+        let delta = value.wrapping_sub(self.last) & self.mask;
+        self.dfcm[self.dfcm_hash] = delta;
+        self.dfcm_hash = ((self.dfcm_hash << 2) ^ (delta >> (self.bits - 24)) as usize) & TABLE_MASK;
+        self.last = value;
+    }
+}
+
+// o preditor usado é escolhido pelo anterior; as quantidades de zeros à esquerda e à direita
+// do XOR, pelas do valor anterior, em faixas de 4 bits
+struct Models {
+    predictor: Vec<AdaptiveModel>,
+    leading: Vec<AdaptiveModel>,
+    trailing: Vec<AdaptiveModel>,
+    // primeiro grupo de bits significativos e demais grupos, por quantidade de bits
+    first: Vec<AdaptiveModel>,
+    chunks: Vec<AdaptiveModel>,
+    tail: AdaptiveModel,
+}
+
+impl Models {
+    fn new(bits: u32, max_total: u64) -> Result<Self> {
+        let contexts = (bits / 4 + 1) as usize;
+        let chunks = || (1..=CHUNK_BITS).map(|len| AdaptiveModel::new(1 << len, max_total)).collect::<Result<Vec<_>>>();
+        Ok(Self {
+            predictor: vec![AdaptiveModel::new(PREDICTORS, max_total)?; PREDICTORS],
+            leading: vec![AdaptiveModel::new(bits as usize + 1, max_total)?; contexts],
+            trailing: vec![AdaptiveModel::new(bits as usize, max_total)?; contexts],
+            first: chunks()?,
+            chunks: chunks()?,
+            tail: AdaptiveModel::new(256, max_total)?,
+        })
+    }
+
+    fn memory_len(bits: u32) -> u64 {
+        let contexts = (bits / 4 + 1) as u64;
+        let chunks: u64 = (1..=CHUNK_BITS).map(|len| AdaptiveModel::memory_len(1 << len)).sum();
+        PREDICTORS as u64 * AdaptiveModel::memory_len(PREDICTORS)
+            + contexts * AdaptiveModel::memory_len(bits as usize + 1)
+            + contexts * AdaptiveModel::memory_len(bits as usize)
+            + 2 * chunks
+            + AdaptiveModel::memory_len(256)
+    }
+}
+
+// memória usada pelas tabelas dos preditores e pelos modelos
+pub fn memory_len(options: &FloatOptions) -> u64 {
+    2 * (size_of::<u64>() << TABLE_BITS) as u64 + Models::memory_len(options.bits())
+}
+
+// maior tamanho de arquivo que `len` bytes codificados podem conter: cada valor codifica ao
+// menos a quantidade de zeros à esquerda do XOR, e os bytes finais não completam um valor
+pub fn max_len(len: usize, options: &FloatOptions) -> u64 {
+    let width = options.width as u64;
+    model::max_symbols(len, options.bits() as usize + 1)
+        .saturating_mul(width)
+        .saturating_add(width - 1)
+}
+
+// cada valor é previsto pelo preditor cujo padrão de bits mais se aproxima dele, e o XOR entre
+// o valor e a previsão é codificado; os padrões são tratados como inteiros, então qualquer
+// valor, inclusive NaN com carga útil, é reproduzido bit a bit
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, input: &[u8], options: &FloatOptions) -> Result<()> {
+    let mut coded = vec![0u8; input.len()];
+    code_values(encoder, &mut coded, Some(input), options)
+}
+
+pub fn decode(decoder: &mut ArithmeticDecoder, len: usize, options: &FloatOptions) -> Result<Vec<u8>> {
+    let mut output = vec![0u8; len];
+    code_values(decoder, &mut output, None, options)?;
+    Ok(output)
+}
+
+fn code_values<C: Coder>(coder: &mut C, data: &mut [u8], source: Option<&[u8]>, options: &FloatOptions) -> Result<()> {
+    options.validate()?;
+    let width = options.width as usize;
+    let bits = options.bits();
+    let mut predictors = Predictors::new(bits);
+    let mut models = Models::new(bits, coder.max_total())?;
+    let (mut last_predictor, mut last_leading, mut last_trailing) = (0, 0, 0);
+
+    let values = data.len() / width;
+    for index in 0..values {
+        let predictions = predictors.predictions();
+        let value = source.map(|source| read_element(source, index, width, options.byte_order));
+        // o menor XOR é o que tem mais zeros à esquerda; em caso de empate, vale o primeiro
+        let predictor = value.map_or(0, |value| (0..PREDICTORS).min_by_key(|&predictor| predictions[predictor] ^ value).unwrap_or(0));
+        let predictor = coder.code(&mut models.predictor[last_predictor], predictor as u32)? as usize;
+        let prediction = predictions[predictor];
+        let xor = value.map_or(0, |value| value ^ prediction);
+
+        let leading = coder.code(&mut models.leading[last_leading as usize / 4], xor.leading_zeros() - (64 - bits))?;
+        let xor = match bits.checked_sub(leading + 1) {
+            // todos os bits são zero
+            None => 0,
+            Some(top) => {
+                let trailing = xor.trailing_zeros().min(top);
+                let trailing = coder.code(&mut models.trailing[last_trailing as usize / 4], trailing)?.min(top);
+                last_trailing = trailing;
+                // os bits entre o primeiro e o último bit 1, que não são repetidos
+                let mut decoded = (1 << top) | (1 << trailing);
+                let mut rest = top.saturating_sub(trailing + 1);
+                let mut chunk_models = &mut models.first;
+                while rest > 0 {
+                    let len = rest.min(CHUNK_BITS);
+                    rest -= len;
+                    let shift = trailing + 1 + rest;
+                    let chunk = coder.code(&mut chunk_models[len as usize - 1], ((xor >> shift) & ((1 << len) - 1)) as u32)? as u64;
+                    decoded |= chunk << shift;
+                    chunk_models = &mut models.chunks;
+                }
+                decoded
+            }
+        };
+        last_predictor = predictor;
+        last_leading = leading.min(bits);
+
+        let value = prediction ^ xor;
+        write_element(data, index, width, options.byte_order, value);
+        predictors.update(value);
+    }
+    for position in values * width..data.len() {
+        let byte = source.map_or(0, |source| source[position]);
+        data[position] = coder.code(&mut models.tail, byte as u32)? as u8;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+    };
+
+    fn round_trip(input: &[u8], options: &FloatOptions) -> usize {
+        let encoded = encode(|encoder| super::encode(encoder, input, options));
+        let decoded = decode(&encoded, |decoder| super::decode(decoder, input.len(), options)).unwrap();
+        assert_eq!(decoded, input);
+        encoded.len()
+    }
+
+    // uma série suave comprime bem abaixo dos 8 bytes por valor
+    #[test]
+    fn smooth_series_round_trip() {
+        let values: Vec<f64> = (0..4000).map(|index| 20.0 + (index as f64 * 0.01).sin()).collect();
+        let mut input: Vec<u8> = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        let len = round_trip(&input, &FloatOptions::default());
+        assert!(len < input.len() * 3 / 4);
+
+        input.extend_from_slice(&[1, 2, 3]);
+        round_trip(&input, &FloatOptions::default());
+        let single: Vec<u8> = values.iter().flat_map(|&value| (value as f32).to_be_bytes()).collect();
+        round_trip(&single, &FloatOptions::new(4, ByteOrder::Big));
+        round_trip(&[], &FloatOptions::new(4, ByteOrder::Little));
+    }
+
+    #[test]
+    fn invalid_widths_are_rejected() {
+        for width in [0, 2, 3, 16] {
+            let options = FloatOptions::new(width, ByteOrder::Little);
+            let mut encoder = ArithmeticEncoder::new(0, (1 << 30) - 1, Vec::new()).unwrap();
+            assert!(matches!(super::encode(&mut encoder, b"12345678", &options), Err(Error::InvalidContainer(_))));
+        }
+    }
+}
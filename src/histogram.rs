@@ -0,0 +1,125 @@
+use crate::image::Image;
+use crate::Error;
+use crate::Result;
+
+// compactação do histograma: os valores usados em cada plano são levados para o intervalo
+// 0..k, na mesma ordem, e o plano passa a ter a menor profundidade que comporta k valores.
+// Os valores usados são gravados como um mapa de bits por plano
+
+// mapa de bits dos valores presentes em cada plano, do bit menos significativo do primeiro byte
+pub fn used_values(image: &Image) -> Vec<Vec<u8>> {
+    image
+        .planes
+        .iter()
+        .map(|plane| {
+            let mut used = vec![0u8; (1usize << plane.depth).div_ceil(8)];
+            for &sample in plane.samples.iter() {
+                used[sample as usize / 8] |= 1 << (sample % 8);
+            }
+            used
+        })
+        .collect()
+}
+
+// profundidades dos planos compactados
+pub fn packed_depths(packed: bool, depths: &[u8], used: &[Vec<u8>]) -> Result<Vec<u8>> {
+    if !packed {
+        return Ok(depths.to_vec());
+    }
+    values(depths, used)?
+        .iter()
+        .map(|values| match values.len() {
+            0 => Err(Error::InvalidContainer("histograma dos planos inválido")),
+            len => Ok((usize::BITS - (len - 1).leading_zeros()).max(1) as u8),
+        })
+        .collect()
+}
+
+pub fn forward(packed: bool, image: &Image, used: &[Vec<u8>]) -> Result<Image> {
+    if !packed {
+        return Ok(image.clone());
+    }
+    let depths = image.depths();
+    let mut output = Image::new(image.width, image.height, &packed_depths(packed, &depths, used)?)?;
+    for ((plane, source), values) in output.planes.iter_mut().zip(image.planes.iter()).zip(values(&depths, used)?) {
+        let mut positions = vec![0u16; 1 << source.depth];
+        for (position, &value) in values.iter().enumerate() {
+            positions[value as usize] = position as u16;
+        }
+        for (sample, &value) in plane.samples.iter_mut().zip(source.samples.iter()) {
+            *sample = positions[value as usize];
+        }
+    }
+    Ok(output)
+}
+
+// `depths` são as profundidades dos planos antes da compactação
+pub fn inverse(packed: bool, image: &Image, used: &[Vec<u8>], depths: &[u8]) -> Result<Image> {
+    if packed_depths(packed, depths, used)? != image.depths() {
+        return Err(Error::InvalidImage("planos incompatíveis com o histograma gravado"));
+    }
+    if !packed {
+        return Ok(image.clone());
+    }
+    let mut output = Image::new(image.width, image.height, depths)?;
+    for ((plane, source), values) in output.planes.iter_mut().zip(image.planes.iter()).zip(values(depths, used)?) {
+        // posições decodificadas de um arquivo corrompido podem passar do último valor usado
+        let last = values.len() - 1;
+        for (sample, &position) in plane.samples.iter_mut().zip(source.samples.iter()) {
+            *sample = values[(position as usize).min(last)];
+        }
+    }
+    Ok(output)
+}
+
+// valores usados de cada plano, em ordem crescente
+fn values(depths: &[u8], used: &[Vec<u8>]) -> Result<Vec<Vec<u16>>> {
+    if depths.len() != used.len()
+        || depths.iter().zip(used.iter()).any(|(&depth, used)| used.len() != (1usize << depth).div_ceil(8))
+    {
+        return Err(Error::InvalidContainer("histograma dos planos inválido"));
+    }
+    Ok(depths
+        .iter()
+        .zip(used.iter())
+        .map(|(&depth, used)| (0..1u32 << depth).filter(|&value| used[value as usize / 8] & (1 << (value % 8)) != 0).map(|value| value as u16).collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packing_round_trips() {
+        let mut image = Image::new(3, 2, &[8, 12]).unwrap();
+        for (index, value) in [0u16, 17, 17, 200, 255, 200].into_iter().enumerate() {
+            image.planes[0].samples[index] = value;
+            image.planes[1].samples[index] = value * 16;
+        }
+        let used = used_values(&image);
+        assert_eq!(used[0].len(), 32);
+        assert_eq!(packed_depths(true, &image.depths(), &used).unwrap(), vec![2, 2]);
+        assert_eq!(packed_depths(false, &image.depths(), &used).unwrap(), vec![8, 12]);
+
+        let packed = forward(true, &image, &used).unwrap();
+        assert_eq!(packed.planes[0].samples, vec![0, 1, 1, 2, 3, 2]);
+        assert_eq!(inverse(true, &packed, &used, &image.depths()).unwrap().planes, image.planes);
+
+        // um plano constante passa a ter 1 bit
+        let constant = Image::new(2, 2, &[8]).unwrap();
+        assert_eq!(packed_depths(true, &[8], &used_values(&constant)).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn malformed_histograms_are_rejected() {
+        let image = Image::new(2, 2, &[8]).unwrap();
+        let used = used_values(&image);
+        assert!(matches!(packed_depths(true, &[8], &[vec![0; 32]]), Err(Error::InvalidContainer(_))));
+        assert!(matches!(packed_depths(true, &[8], &[vec![1; 31]]), Err(Error::InvalidContainer(_))));
+        assert!(matches!(packed_depths(true, &[8, 8], &used), Err(Error::InvalidContainer(_))));
+        let packed = forward(true, &image, &used).unwrap();
+        assert!(matches!(inverse(true, &packed, &used, &[4]), Err(Error::InvalidContainer(_))));
+        assert!(matches!(inverse(false, &packed, &used, &[8]), Err(Error::InvalidImage(_))));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::Error;
+use crate::Result;
+
+// amostras de um canal, linha a linha, de cima para baixo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plane {
+    pub depth: u8,
+    pub samples: Vec<u16>,
+}
+
+impl Plane {
+    pub fn max_value(&self) -> u16 {
+        ((1u32 << self.depth) - 1) as u16
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub planes: Vec<Plane>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, depths: &[u8]) -> Result<Self> {
+        let len = Self::checked_len(width, height)?;
+        if depths.iter().any(|&depth| depth == 0 || depth > 16) {
+            return Err(Error::UnsupportedImage("profundidade de amostra não suportada"));
+        }
+        let planes = depths
+            .iter()
+            .map(|&depth| Plane {
+                depth,
+                samples: vec![0; len],
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            planes,
+        })
+    }
+
+    // quantidade de amostras de um plano, sem estouro para dimensões forjadas
+    pub fn checked_len(width: usize, height: usize) -> Result<usize> {
+        match width.checked_mul(height) {
+            Some(len) if len <= u32::MAX as usize => Ok(len),
+            _ => Err(Error::InvalidImage("dimensões muito grandes")),
+        }
+    }
+
+    // memória ocupada pelas amostras de uma imagem com estas dimensões
+    pub fn memory_len(width: usize, height: usize, planes: usize) -> u64 {
+        (width as u64)
+            .saturating_mul(height as u64)
+            .saturating_mul(planes as u64)
+            .saturating_mul(size_of::<u16>() as u64)
+    }
+
+    pub fn get(&self, plane: usize, x: usize, y: usize) -> u16 {
+        self.planes[plane].samples[y * self.width + x]
+    }
+
+    pub fn set(&mut self, plane: usize, x: usize, y: usize, value: u16) {
+        self.planes[plane].samples[y * self.width + x] = value;
+    }
+
+    pub fn depths(&self) -> Vec<u8> {
+        self.planes.iter().map(|plane| plane.depth).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn planes_are_stored_row_by_row() {
+        let mut image = Image::new(3, 2, &[8, 1, 16]).unwrap();
+        image.set(1, 2, 1, 1);
+        assert_eq!(image.planes[1].samples, [0, 0, 0, 0, 0, 1]);
+        assert_eq!(image.get(1, 2, 1), 1);
+        assert_eq!(image.depths(), vec![8, 1, 16]);
+        assert_eq!(image.planes.iter().map(Plane::max_value).collect::<Vec<_>>(), [255, 1, 65535]);
+        assert_eq!(Image::memory_len(3, 2, 3), 36);
+    }
+
+    #[test]
+    fn invalid_dimensions_are_rejected() {
+        assert!(matches!(Image::new(4, 4, &[0]), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(Image::new(4, 4, &[17]), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(Image::checked_len(1 << 20, 1 << 20), Err(Error::InvalidImage(_))));
+        assert!(matches!(Image::checked_len(usize::MAX, 2), Err(Error::InvalidImage(_))));
+        assert_eq!(Image::memory_len(usize::MAX, usize::MAX, 3), u64::MAX);
+    }
+}
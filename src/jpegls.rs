@@ -0,0 +1,396 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::{
+    Image,
+    Plane,
+};
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Error;
+use crate::Result;
+
+// maior valor de NEAR aceito, como no JPEG-LS
+pub const MAX_NEAR: u16 = 255;
+
+// contextos dos gradientes quantizados (9 × 9 × 9 faixas, unindo os simétricos)
+const CONTEXTS: usize = 365;
+// tabelas de erro, escolhidas pelo parâmetro k que o JPEG-LS usaria no código de Golomb
+const K_CONTEXTS: usize = 17;
+// tabelas binárias de continuação da corrida, pela faixa do comprimento já percorrido
+const RUN_CONTEXTS: usize = 16;
+// quantidade de ocorrências após a qual as estatísticas de um contexto são divididas por dois
+const RESET: i32 = 64;
+
+// parâmetros de um plano, derivados da profundidade das amostras e de NEAR
+struct Parameters {
+    max: i32,
+    near: i32,
+    range: i32,
+    thresholds: [i32; 3],
+}
+
+impl Parameters {
+    fn new(depth: u8, near: u16) -> Result<Self> {
+        let max = (1i32 << depth) - 1;
+        let near = near as i32;
+        if near > MAX_NEAR as i32 || near > max / 2 {
+            return Err(Error::UnsupportedImage("valor de NEAR muito grande para a profundidade das amostras"));
+        }
+        let range = (max + 2 * near) / (2 * near + 1) + 1;
+
+        // limiares padrão do JPEG-LS (3, 7 e 21 para 8 bits) para a quantização dos gradientes
+        let thresholds = if max >= 128 {
+            let factor = (max.min(4095) + 128) >> 8;
+            let t1 = (factor + 2 + 3 * near).clamp(near + 1, max);
+            let t2 = (4 * factor + 3 + 5 * near).clamp(t1, max);
+            let t3 = (17 * factor + 4 + 7 * near).clamp(t2, max);
+            [t1, t2, t3]
+        } else {
+            let factor = 256 / (max + 1);
+            let t1 = (3 / factor + 3 * near).max(2).clamp(near + 1, max.max(near + 1));
+            let t2 = (7 / factor + 5 * near).max(3).clamp(t1, max.max(t1));
+            let t3 = (21 / factor + 7 * near).max(4).clamp(t2, max.max(t2));
+            [t1, t2, t3]
+        };
+
+        Ok(Self {
+            max,
+            near,
+            range,
+            thresholds,
+        })
+    }
+
+    fn quantize_gradient(&self, gradient: i32) -> i32 {
+        let [t1, t2, t3] = self.thresholds;
+        match gradient {
+            _ if gradient <= -t3 => -4,
+            _ if gradient <= -t2 => -3,
+            _ if gradient <= -t1 => -2,
+            _ if gradient < -self.near => -1,
+            _ if gradient <= self.near => 0,
+            _ if gradient < t1 => 1,
+            _ if gradient < t2 => 2,
+            _ if gradient < t3 => 3,
+            _ => 4,
+        }
+    }
+
+    // erro quantizado com passo 2 * NEAR + 1 e reduzido ao intervalo de RANGE valores
+    fn quantize_error(&self, error: i32) -> i32 {
+        let step = 2 * self.near + 1;
+        let quantized = if error > 0 {
+            (error + self.near) / step
+        } else {
+            -((self.near - error) / step)
+        };
+        (quantized + self.range / 2).rem_euclid(self.range) - self.range / 2
+    }
+
+    // o mesmo valor é reconstruído no codificador e no decodificador
+    fn reconstruct(&self, predicted: i32, sign: i32, error: i32) -> i32 {
+        let step = 2 * self.near + 1;
+        let mut value = predicted + sign * error * step;
+        if value < -self.near {
+            value += self.range * step;
+        } else if value > self.max + self.near {
+            value -= self.range * step;
+        }
+        value.clamp(0, self.max)
+    }
+}
+
+// estatísticas e modelos de um plano
+struct State {
+    // soma dos módulos dos erros, soma dos erros, correção do viés e ocorrências por contexto
+    a: Vec<i32>,
+    b: Vec<i32>,
+    c: Vec<i32>,
+    n: Vec<i32>,
+    errors: Vec<AdaptiveModel>,
+    interruptions: Vec<AdaptiveModel>,
+    runs: Vec<AdaptiveModel>,
+}
+
+impl State {
+    fn new(parameters: &Parameters, max_total: u64) -> Result<Self> {
+        let range = parameters.range as usize;
+        let errors = vec![AdaptiveModel::new(range, max_total)?; K_CONTEXTS];
+        let interruptions = vec![AdaptiveModel::new(range, max_total)?; 2];
+        let runs = vec![AdaptiveModel::new(2, max_total)?; RUN_CONTEXTS];
+        Ok(Self {
+            a: vec![2.max((parameters.range + 32) / 64); CONTEXTS],
+            b: vec![0; CONTEXTS],
+            c: vec![0; CONTEXTS],
+            n: vec![1; CONTEXTS],
+            errors,
+            interruptions,
+            runs,
+        })
+    }
+
+    // o mesmo k do código de Golomb do JPEG-LS escolhe a tabela de erro
+    fn error_model(&mut self, context: usize) -> &mut AdaptiveModel {
+        let mut k = 0;
+        while (self.n[context] << k) < self.a[context] && k < K_CONTEXTS - 1 {
+            k += 1;
+        }
+        &mut self.errors[k]
+    }
+
+    fn update(&mut self, context: usize, error: i32, near: i32) {
+        self.b[context] += error * (2 * near + 1);
+        self.a[context] += error.abs();
+        if self.n[context] == RESET {
+            self.a[context] >>= 1;
+            self.b[context] = if self.b[context] >= 0 {
+                self.b[context] >> 1
+            } else {
+                -((1 - self.b[context]) >> 1)
+            };
+            self.n[context] >>= 1;
+        }
+        self.n[context] += 1;
+
+        // correção do viés da predição
+        let n = self.n[context];
+        if self.b[context] <= -n {
+            self.b[context] += n;
+            if self.c[context] > -128 {
+                self.c[context] -= 1;
+            }
+            if self.b[context] <= -n {
+                self.b[context] = -n + 1;
+            }
+        } else if self.b[context] > 0 {
+            self.b[context] -= n;
+            if self.c[context] < 127 {
+                self.c[context] += 1;
+            }
+            if self.b[context] > 0 {
+                self.b[context] = 0;
+            }
+        }
+    }
+}
+
+// memória ocupada pelos modelos e estatísticas de um plano por vez
+pub fn models_memory_len(depths: &[u8], near: u16) -> u64 {
+    depths
+        .iter()
+        .map(|&depth| {
+            let range = ((1u64 << depth) - 1 + 2 * near as u64) / (2 * near as u64 + 1) + 1;
+            AdaptiveModel::memory_len(range as usize) * (K_CONTEXTS + 2) as u64
+                + AdaptiveModel::memory_len(2) * RUN_CONTEXTS as u64
+                + (CONTEXTS * 4 * size_of::<i32>()) as u64
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// codifica a imagem; retorna o maior erro absoluto entre a imagem e a reconstrução
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image, near: u16) -> Result<u16> {
+    let mut reconstructed = Image::new(image.width, image.height, &image.depths())?;
+    let max_total = encoder.max_total();
+    for (plane, output) in image.planes.iter().zip(reconstructed.planes.iter_mut()) {
+        code_plane(encoder, max_total, Some(plane), output, image.width, image.height, near)?;
+    }
+
+    let max_error = image
+        .planes
+        .iter()
+        .zip(reconstructed.planes.iter())
+        .flat_map(|(plane, output)| plane.samples.iter().zip(output.samples.iter()))
+        .map(|(&sample, &output)| sample.abs_diff(output))
+        .max()
+        .unwrap_or(0);
+    Ok(max_error)
+}
+
+// reconstrói os planos de `image`, que já deve ter as dimensões e profundidades gravadas
+pub fn decode(decoder: &mut ArithmeticDecoder, image: &mut Image, near: u16) -> Result<()> {
+    let width = image.width;
+    let height = image.height;
+    let max_total = decoder.max_total();
+    for output in image.planes.iter_mut() {
+        code_plane(decoder, max_total, None, output, width, height, near)?;
+    }
+    Ok(())
+}
+
+// percorre um plano nos dois sentidos: `source` existe apenas na codificação, e `output`
+// recebe a reconstrução, que é a base das predições seguintes
+fn code_plane<C: Coder>(
+    coder: &mut C,
+    max_total: u64,
+    source: Option<&Plane>,
+    output: &mut Plane,
+    width: usize,
+    height: usize,
+    near: u16,
+) -> Result<()> {
+    let parameters = Parameters::new(output.depth, near)?;
+    let mut state = State::new(&parameters, max_total)?;
+    let near = parameters.near;
+    let sample_at = |x: usize, y: usize| source.map(|source| source.samples[y * width + x] as i32);
+
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            let (a, b, c, d) = neighbours(output, width, x, y);
+            let q1 = parameters.quantize_gradient(d - b);
+            let q2 = parameters.quantize_gradient(b - c);
+            let q3 = parameters.quantize_gradient(c - a);
+
+            if q1 == 0 && q2 == 0 && q3 == 0 {
+                // modo de corrida: amostras a até NEAR do vizinho à esquerda repetem esse vizinho
+                let mut run: usize = 0;
+                while x < width {
+                    let symbol = sample_at(x, y).map_or(0, |sample| ((sample - a).abs() <= near) as u32);
+                    let context = (usize::BITS - run.leading_zeros()) as usize;
+                    let model = &mut state.runs[context.min(RUN_CONTEXTS - 1)];
+                    if coder.code(model, symbol)? == 0 {
+                        break;
+                    }
+                    output.samples[y * width + x] = a as u16;
+                    run += 1;
+                    x += 1;
+                }
+                if x == width {
+                    break;
+                }
+
+                // a amostra que interrompe a corrida é predita pelo vizinho à esquerda ou acima
+                let (a, b, _, _) = neighbours(output, width, x, y);
+                let interruption = ((a - b).abs() <= near) as usize;
+                let (predicted, sign) = if interruption == 1 {
+                    (a, 1)
+                } else {
+                    (b, if a > b { -1 } else { 1 })
+                };
+                let model = &mut state.interruptions[interruption];
+                let (value, _) = code_sample(coder, model, &parameters, predicted, sign, sample_at(x, y))?;
+                output.samples[y * width + x] = value as u16;
+                x += 1;
+                continue;
+            }
+
+            // contextos simétricos são unidos invertendo o sinal do erro
+            let sign = if q1 < 0 || (q1 == 0 && (q2 < 0 || (q2 == 0 && q3 < 0))) { -1 } else { 1 };
+            let context = (sign * (q1 * 81 + q2 * 9 + q3)) as usize;
+            let predicted = (med(a, b, c) + sign * state.c[context]).clamp(0, parameters.max);
+
+            let model = state.error_model(context);
+            let (value, error) = code_sample(coder, model, &parameters, predicted, sign, sample_at(x, y))?;
+            output.samples[y * width + x] = value as u16;
+            state.update(context, error, near);
+            x += 1;
+        }
+    }
+    Ok(())
+}
+
+// codifica o erro de predição de uma amostra; retorna o valor reconstruído e o erro quantizado
+fn code_sample<C: Coder>(
+    coder: &mut C,
+    model: &mut AdaptiveModel,
+    parameters: &Parameters,
+    predicted: i32,
+    sign: i32,
+    sample: Option<i32>,
+) -> Result<(i32, i32)> {
+    let symbol = sample.map_or(0, |sample| {
+        let error = parameters.quantize_error(sign * (sample - predicted));
+        // 0, -1, 1, -2, 2, ... para 0, 1, 2, 3, 4, ...
+        if error >= 0 {
+            2 * error as u32
+        } else {
+            (-2 * error - 1) as u32
+        }
+    });
+    let symbol = coder.code(model, symbol)? as i32;
+    let error = if symbol % 2 == 0 { symbol / 2 } else { -(symbol + 1) / 2 };
+    Ok((parameters.reconstruct(predicted, sign, error), error))
+}
+
+// vizinhos reconstruídos à esquerda (a), acima (b), acima à esquerda (c) e acima à direita
+// (d); fora da imagem, a linha anterior vale zero e a primeira coluna repete o vizinho acima
+fn neighbours(plane: &Plane, width: usize, x: usize, y: usize) -> (i32, i32, i32, i32) {
+    let sample = |x: usize, y: usize| plane.samples[y * width + x] as i32;
+    let b = if y > 0 { sample(x, y - 1) } else { 0 };
+    let a = if x > 0 { sample(x - 1, y) } else { b };
+    let c = if x > 0 && y > 0 { sample(x - 1, y - 1) } else { b };
+    let d = if x + 1 < width && y > 0 { sample(x + 1, y - 1) } else { b };
+    (a, b, c, d)
+}
+
+fn med(a: i32, b: i32, c: i32) -> i32 {
+    if c >= a.max(b) {
+        a.min(b)
+    } else if c <= a.min(b) {
+        a.max(b)
+    } else {
+        a + b - c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        photo,
+    };
+
+    fn round_trip(image: &Image, near: u16) -> (Image, u16) {
+        let mut max_error = 0;
+        let encoded = encode(|encoder| {
+            max_error = super::encode(encoder, image, near)?;
+            Ok(())
+        });
+        let mut decoded = Image::new(image.width, image.height, &image.depths()).unwrap();
+        decode(&encoded, |decoder| super::decode(decoder, &mut decoded, near)).unwrap();
+        (decoded, max_error)
+    }
+
+    #[test]
+    fn lossless_round_trip() {
+        for depths in [&[8, 8, 8][..], &[1], &[4], &[16]] {
+            let image = photo(19, 11, depths);
+            let (decoded, max_error) = round_trip(&image, 0);
+            assert_eq!(decoded.planes, image.planes);
+            assert_eq!(max_error, 0);
+        }
+    }
+
+    // com NEAR, nenhuma amostra se afasta mais que NEAR da original, e o erro informado é o
+    // da imagem que o decodificador reconstrói
+    #[test]
+    fn near_lossless_round_trip() {
+        let image = photo(30, 20, &[8, 8, 8]);
+        let (decoded, max_error) = round_trip(&image, 3);
+        let observed = image
+            .planes
+            .iter()
+            .zip(decoded.planes.iter())
+            .flat_map(|(plane, output)| plane.samples.iter().zip(output.samples.iter()))
+            .map(|(&sample, &output)| sample.abs_diff(output))
+            .max()
+            .unwrap();
+        assert_eq!(observed, max_error);
+        assert!(max_error <= 3);
+    }
+
+    #[test]
+    fn near_too_large_is_rejected() {
+        let image = photo(4, 4, &[4]);
+        let mut encoder = ArithmeticEncoder::new(0, (1 << 30) - 1, Vec::new()).unwrap();
+        assert!(matches!(super::encode(&mut encoder, &image, 8), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(Parameters::new(16, MAX_NEAR + 1), Err(Error::UnsupportedImage(_))));
+    }
+}
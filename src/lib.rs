@@ -3,13 +3,49 @@ use serde::{
     Serialize,
 };
 
+pub mod audio;
+pub mod bilevel;
+pub mod bitplane;
+pub mod bmp;
+pub mod colour;
+pub mod container;
+pub mod dct;
+pub mod decoder;
+pub mod delta;
+pub mod dump;
+pub mod dpcm;
+pub mod encoder;
+pub mod error;
+pub mod float;
+pub mod histogram;
+pub mod image;
+pub mod jpegls;
+pub mod limits;
+pub mod metadata;
+pub mod model;
+pub mod netpbm;
+pub mod palette;
+pub mod predictive;
+pub mod progressive;
+pub mod sequence;
+pub mod tiles;
+pub mod wav;
+pub mod wavelet;
+#[cfg(test)]
+mod testing;
+
+pub use error::{
+    Error,
+    Result,
+};
+
 #[derive(Debug, Clone)]
 pub enum Operation {
     Decode,
     Encode,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Symbols {
     pub table: Vec<(u8, u64)>,
     pub total: u64,
@@ -52,9 +88,9 @@ impl Symbols {
 
         self.total += 1;
     }
-    
+
     pub fn calculate_accumulated_frequency(&mut self) {
-        self.table.sort_by(|a, b| a.0.cmp(&b.0));
+        self.table.sort_by_key(|s| s.0);
         let mut accumulated_frequency: u64 = 0;
         for item in self.table.iter_mut() {
             item.1 += accumulated_frequency;
@@ -62,7 +98,7 @@ impl Symbols {
         }
     }
 
-    pub fn get_low_and_high(&self, symbol: u8) -> (u64, u64) {
+    pub fn get_low_and_high(&self, symbol: u8) -> Result<(u64, u64)> {
         let position = self.table.iter().position(|s| {
             s.0 == symbol
         });
@@ -75,29 +111,44 @@ impl Symbols {
                     self.table[index - 1].1
                 };
                 let high = self.table[index].1;
-                (low, high)
-            }
-            None => {
-                println!("\n\nERRO: Símbolo não encontrado!\n");
-                std::process::exit(1);
+                Ok((low, high))
             }
+            None => Err(Error::SymbolNotFound),
         }
     }
 
-    pub fn get_symbol_by_value(&mut self, value: u64) -> u8 {
+    pub fn get_symbol_by_value(&self, value: u64) -> Result<u8> {
         let position = self.table.iter().position(|s| {
             value < s.1
         });
 
         match position {
-            Some(index) => {
-                self.table[index].0
+            Some(index) => Ok(self.table[index].0),
+            None => Err(Error::SymbolNotFound),
+        }
+    }
+
+    // verifica uma tabela de frequências acumuladas lida de um arquivo
+    pub fn validate(&self, max_total: u64) -> Result<()> {
+        let mut last_symbol: Option<u8> = None;
+        let mut last_frequency: u64 = 0;
+        for &(symbol, frequency) in self.table.iter() {
+            if last_symbol.is_some_and(|last| symbol <= last) {
+                return Err(Error::InvalidContainer("tabela de símbolos fora de ordem"));
             }
-            None => {
-                println!("\n\nERRO: Símbolo não encontrado!\n");
-                std::process::exit(1);
+            if frequency <= last_frequency {
+                return Err(Error::InvalidContainer("frequência acumulada inválida"));
             }
+            last_symbol = Some(symbol);
+            last_frequency = frequency;
+        }
+        if last_frequency != self.total {
+            return Err(Error::InvalidContainer("total de símbolos inconsistente"));
         }
+        if self.total > max_total {
+            return Err(Error::InvalidContainer("total de símbolos excede a precisão"));
+        }
+        Ok(())
     }
 }
 
@@ -110,10 +161,9 @@ pub struct ArithmeticCoding {
 }
 
 impl ArithmeticCoding {
-    pub fn new(mut low: u32, mut high: u32) -> Self {
+    pub fn new(mut low: u32, mut high: u32) -> Result<Self> {
         if low >= high {
-            println!("\n\nERRO: Low maior ou igual a high!\n");
-            std::process::exit(1);
+            return Err(Error::InvalidInterval);
         }
 
         let shift: u32 = 32 - low.leading_zeros();
@@ -123,24 +173,56 @@ impl ArithmeticCoding {
         let precision: u32 = 32 - high.leading_zeros();
 
         if precision < 4 {
-            println!("\n\nERRO: Precisão muito baixa!\n");
-            std::process::exit(1);
+            return Err(Error::LowPrecision);
         }
 
         let symbols: Symbols = Symbols::new();
 
-        Self {
+        Ok(Self {
             low,
             high,
             precision,
             symbols,
+        })
+    }
+
+    // verifica uma estrutura lida de um arquivo antes de usá-la na decodificação
+    pub fn validate(&self) -> Result<()> {
+        if !(4..=32).contains(&self.precision) {
+            return Err(Error::LowPrecision);
+        }
+        if self.low >= self.high || self.high > self.full_mask() {
+            return Err(Error::InvalidInterval);
+        }
+        if self.high - self.low < self.half_bit() {
+            return Err(Error::LowPrecision);
         }
+        self.symbols.validate(self.half_bit() as u64 - 1)
     }
 
-    pub fn verify_low_and_high(&self) {
-        if self.low >= self.high {
-            println!("\n\nERRO: Low maior ou igual a high!\n");
-            std::process::exit(1);
+    pub fn verify_low_and_high(&self) -> Result<()> {
+        if self.low > self.high {
+            return Err(Error::InvalidInterval);
+        }
+        Ok(())
+    }
+
+    // reduz o intervalo atual para o subintervalo [low_of_symbol, high_of_symbol) de total
+    pub fn narrow(&mut self, low_of_symbol: u64, high_of_symbol: u64, total: u64) -> Result<()> {
+        let range = (self.high - self.low) as u64 + 1;
+        let total = total + 1;
+        let old_low = self.low as u64;
+
+        let low = old_low + (low_of_symbol * range) / total;
+        let high = (old_low + (high_of_symbol * range) / total).checked_sub(1);
+
+        match high {
+            Some(high) if low <= high && high <= self.high as u64 => {
+                self.low = low as u32;
+                self.high = high as u32;
+                Ok(())
+            }
+            _ => Err(Error::InvalidInterval),
         }
     }
 
@@ -159,4 +241,50 @@ impl ArithmeticCoding {
     pub fn half_mask(&self) -> u32 {
         u32::MAX >> (32 - (self.precision - 1))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // os bits de `low` são descartados, e a precisão é a quantidade de bits do que resta de `high`
+    #[test]
+    fn interval_is_normalised() {
+        let ac = ArithmeticCoding::new(16, 4095).unwrap();
+        assert_eq!((ac.low, ac.high, ac.precision), (0, 127, 7));
+        let ac = ArithmeticCoding::new(0, 2147483647).unwrap();
+        assert_eq!((ac.low, ac.high, ac.precision), (0, 2147483647, 31));
+        assert!(ac.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_intervals_are_rejected() {
+        assert!(matches!(ArithmeticCoding::new(10, 10), Err(Error::InvalidInterval)));
+        assert!(matches!(ArithmeticCoding::new(200, 100), Err(Error::InvalidInterval)));
+        assert!(matches!(ArithmeticCoding::new(0, 7), Err(Error::LowPrecision)));
+    }
+
+    #[test]
+    fn symbols_accumulate_frequencies() {
+        let mut symbols = Symbols::new();
+        for &byte in b"abracadabra" {
+            symbols.add_symbol(byte);
+        }
+        symbols.calculate_accumulated_frequency();
+        assert_eq!(symbols.total, 11);
+        assert_eq!(symbols.get_low_and_high(b'a').unwrap(), (0, 5));
+        assert_eq!(symbols.get_low_and_high(b'r').unwrap(), (9, 11));
+        assert_eq!(symbols.get_symbol_by_value(5).unwrap(), b'b');
+        assert!(matches!(symbols.get_low_and_high(b'z'), Err(Error::SymbolNotFound)));
+        assert!(symbols.validate(1 << 20).is_ok());
+    }
+
+    #[test]
+    fn malformed_symbol_tables_are_rejected() {
+        let table = |table: Vec<(u8, u64)>, total| Symbols { table, total }.validate(1 << 20);
+        assert!(table(vec![(2, 3), (1, 5)], 5).is_err());
+        assert!(table(vec![(1, 3), (2, 3)], 3).is_err());
+        assert!(table(vec![(1, 3), (2, 5)], 6).is_err());
+        assert!(table(vec![(1, 3), (2, 1 << 21)], 1 << 21).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+
+use crate::Error;
+use crate::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Output,
+    Model,
+    Memory,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Output => write!(f, "tamanho da saída"),
+            Limit::Model => write!(f, "tamanho do modelo"),
+            Limit::Memory => write!(f, "memória"),
+        }
+    }
+}
+
+// limites aplicados na decodificação de arquivos não confiáveis; `None` significa sem limite
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeLimits {
+    pub max_output_bytes: Option<u64>,
+    pub max_model_size: Option<u64>,
+    pub max_memory: Option<u64>,
+}
+
+impl DecodeLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check_output(&self, len: u64) -> Result<()> {
+        Self::check(Limit::Output, self.max_output_bytes, len)
+    }
+
+    pub fn check_model(&self, len: u64) -> Result<()> {
+        Self::check(Limit::Model, self.max_model_size, len)
+    }
+
+    pub fn check_memory(&self, len: u64) -> Result<()> {
+        Self::check(Limit::Memory, self.max_memory, len)
+    }
+
+    fn check(limit: Limit, max: Option<u64>, len: u64) -> Result<()> {
+        match max {
+            Some(max) if len > max => Err(Error::LimitExceeded(limit, max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_inclusive() {
+        let limits = DecodeLimits {
+            max_output_bytes: Some(100),
+            max_model_size: None,
+            max_memory: Some(0),
+        };
+        assert!(limits.check_output(100).is_ok());
+        assert!(matches!(limits.check_output(101), Err(Error::LimitExceeded(Limit::Output, 100))));
+        assert!(limits.check_model(u64::MAX).is_ok());
+        assert!(matches!(limits.check_memory(1), Err(Error::LimitExceeded(Limit::Memory, 0))));
+        assert!(DecodeLimits::new().check_memory(u64::MAX).is_ok());
+    }
+}
@@ -1,38 +1,78 @@
 use std::env;
 use std::fs;
-use std::io::Read;
-use std::io::Seek;
-use std::mem::size_of;
-use bincode::deserialize_from;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
 
-mod decoder;
-mod encoder;
+mod output;
 
 use arithmetic_coding::Operation;
-use arithmetic_coding::ArithmeticCoding;
-use crate::{
-    decoder::ArithmeticDecoder,
-    encoder::ArithmeticEncoder,
+use arithmetic_coding::{
+    colour::ColourTransform,
+    container::{
+        decode_partial,
+        dump,
+        encode,
+        encode_sequence,
+        Container,
+        Header,
+        Mode,
+    },
+    dct::DEFAULT_QUALITY,
+    delta,
+    delta::{
+        ByteOrder,
+        DeltaOptions,
+    },
+    float::FloatOptions,
+    limits::DecodeLimits,
+    metadata::FileMetadata,
+    palette::PaletteOrder,
+    predictive::{
+        ImageOptions,
+        Predictor,
+    },
+    progressive,
+    sequence::DEFAULT_KEY_INTERVAL,
+    tiles::Region,
+    wavelet::{
+        DEFAULT_LEVELS,
+        MAX_LEVELS,
+    },
 };
+use crate::output::OutputFile;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 3 {
-        println!("\nUso: {} <parâmetros> <operação>\n", args[0]);
-        println!("Operações suportadas:");
-        println!("  -e, --encode <arquivo>    Codificar o conteúdo do arquivo informado.");
-        println!("  -d, --decode <arquivo>    Decodificar o conteúdo do arquivo informado.\n");
-        println!("Parâmetros de codificação:");
-        println!("  -l, --low <valor>         Define o valor de low.");
-        println!("  -h, --high <valor>        Define o valor de high.\n");
-        std::process::exit(1);
+        print_usage(&args[0]);
     }
 
     let mut low: Option<u32> = None;
     let mut high: Option<u32> = None;
     let mut operation: Option<Operation> = None;
     let mut file_path: Option<&str> = None;
+    let mut output_path: Option<&str> = None;
+    let mut force = false;
+    let mut preserve = false;
+    let mut mode = Mode::Bytes;
+    let mut image_options = ImageOptions::default();
+    let mut near: u16 = 0;
+    let mut quality = DEFAULT_QUALITY;
+    let mut levels: Option<u8> = None;
+    let mut preview: Option<u8> = None;
+    let mut partial = false;
+    let mut tile: Option<u32> = None;
+    let mut region: Option<Region> = None;
+    let mut dump = false;
+    let mut key_interval = DEFAULT_KEY_INTERVAL;
+    let mut motion = false;
+    let mut frame: Option<usize> = None;
+    let mut delta_options = DeltaOptions::default();
+    let mut width: Option<u8> = None;
+    let mut limits = DecodeLimits::new();
 
     let mut iter = args.iter().skip(1);
 
@@ -64,6 +104,270 @@ fn main() {
                     std::process::exit(1);
                 }
             }
+            "--output" | "-o" => {
+                if let Some(value) = iter.next() {
+                    output_path = Some(value.as_str());
+                } else {
+                    println!("Arquivo de saída não fornecido.");
+                    std::process::exit(1);
+                }
+            }
+            "--force" | "-f" => {
+                force = true;
+            }
+            "--mode" | "-m" => {
+                mode = match iter.next().map(|value| value.as_str()) {
+                    Some("bytes") => Mode::Bytes,
+                    Some("dpcm") => Mode::Dpcm,
+                    Some("image") => Mode::Image(ImageOptions::default()),
+                    Some("jpegls") => Mode::JpegLs { near: 0 },
+                    Some("dct") => Mode::Dct { quality: DEFAULT_QUALITY },
+                    Some("wavelet") => Mode::Wavelet { levels: DEFAULT_LEVELS },
+                    Some("bitplane") => Mode::BitPlanes,
+                    Some("bilevel") => Mode::Bilevel,
+                    Some("progressive") => Mode::Progressive { levels: progressive::DEFAULT_LEVELS },
+                    Some("sequence") => Mode::Sequence { key_interval: DEFAULT_KEY_INTERVAL, motion: false },
+                    Some("wav") => Mode::Wav,
+                    Some("delta") => Mode::Delta(DeltaOptions::default()),
+                    Some("float") => Mode::Float(FloatOptions::default()),
+                    Some(_) => {
+                        println!("Modo de codificação inválido.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Modo de codificação não fornecido.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--transform" | "-t" => {
+                image_options.transform = match iter.next().map(|value| value.as_str()) {
+                    Some("none") => ColourTransform::None,
+                    Some("ycocg") => ColourTransform::YCoCgR,
+                    Some("rct") => ColourTransform::Rct,
+                    Some(_) => {
+                        println!("Transformação de cor inválida.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Transformação de cor não fornecida.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--palette" => {
+                image_options.palette = match iter.next().map(|value| value.as_str()) {
+                    Some("none") => PaletteOrder::None,
+                    Some("luminance") => PaletteOrder::Luminance,
+                    Some("tour") => PaletteOrder::Tour,
+                    Some(_) => {
+                        println!("Ordem de paleta inválida.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Ordem de paleta não fornecida.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--predictor" | "-p" => {
+                image_options.predictor = match iter.next().map(|value| value.as_str()) {
+                    Some("med") => Predictor::Med,
+                    Some("png") => Predictor::Png,
+                    Some(_) => {
+                        println!("Preditor inválido.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Preditor não fornecido.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--near" => {
+                if let Some(value) = iter.next() {
+                    if let Ok(value) = value.parse::<u16>() {
+                        near = value;
+                    } else {
+                        println!("Valor de NEAR inválido.");
+                        std::process::exit(1);
+                    }
+                } else {
+                    println!("Valor de NEAR não fornecido.");
+                    std::process::exit(1);
+                }
+            }
+            "--quality" | "-q" => {
+                if let Some(value) = iter.next() {
+                    match value.parse::<u8>() {
+                        Ok(value) if (1..=100).contains(&value) => quality = value,
+                        _ => {
+                            println!("Qualidade inválida (use um valor de 1 a 100).");
+                            std::process::exit(1);
+                        }
+                    }
+                } else {
+                    println!("Qualidade não fornecida.");
+                    std::process::exit(1);
+                }
+            }
+            "--levels" | "--preview" => {
+                // na prévia, o limite depende do modo gravado no arquivo e é conferido depois
+                let max = if arg == "--levels" { MAX_LEVELS } else { 16 };
+                let value = match iter.next().map(|value| value.parse::<u8>()) {
+                    Some(Ok(value)) if value <= max => value,
+                    Some(_) => {
+                        println!("Valor de {} inválido (use um valor de 0 a {}).", arg, max);
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Valor de {} não fornecido.", arg);
+                        std::process::exit(1);
+                    }
+                };
+                match arg.as_str() {
+                    "--levels" => levels = Some(value),
+                    "--preview" => preview = Some(value),
+                    _ => unreachable!(),
+                }
+            }
+            "--tile" => {
+                tile = match iter.next().map(|value| value.parse::<u32>()) {
+                    Some(Ok(value)) if value > 0 => Some(value),
+                    Some(_) => {
+                        println!("Tamanho de bloco inválido.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Tamanho de bloco não fornecido.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--region" => {
+                let values: Option<Vec<usize>> = match iter.next() {
+                    Some(value) => value.split(',').map(|value| value.parse::<usize>().ok()).collect(),
+                    None => {
+                        println!("Região não fornecida.");
+                        std::process::exit(1);
+                    }
+                };
+                region = match values.as_deref() {
+                    Some(&[x, y, width, height]) if width > 0 && height > 0 => Some(Region::new(x, y, width, height)),
+                    _ => {
+                        println!("Região inválida (use x,y,largura,altura).");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--keyframes" => {
+                key_interval = match iter.next().map(|value| value.parse::<u32>()) {
+                    Some(Ok(value)) if value > 0 => value,
+                    Some(_) => {
+                        println!("Intervalo entre quadros-chave inválido.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Intervalo entre quadros-chave não fornecido.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--motion" => {
+                motion = true;
+            }
+            "--frame" => {
+                frame = match iter.next().map(|value| value.parse::<usize>()) {
+                    Some(Ok(value)) => Some(value),
+                    Some(Err(_)) => {
+                        println!("Número de quadro inválido.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Número de quadro não fornecido.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--width" => {
+                width = match iter.next().map(|value| value.parse::<u8>()) {
+                    Some(Ok(value)) if matches!(value, 1 | 2 | 4 | 8) => Some(value),
+                    Some(_) => {
+                        println!("Largura de elemento inválida (use 1, 2, 4 ou 8).");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Largura de elemento não fornecida.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--stride" => {
+                delta_options.stride = match iter.next().map(|value| value.parse::<u32>()) {
+                    Some(Ok(value)) if value > 0 => value,
+                    Some(_) => {
+                        println!("Distância entre elementos inválida.");
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Distância entre elementos não fornecida.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--order" => {
+                delta_options.order = match iter.next().map(|value| value.parse::<u8>()) {
+                    Some(Ok(value)) if value <= delta::MAX_ORDER => value,
+                    Some(_) => {
+                        println!("Ordem da diferença inválida (use um valor de 0 a {}).", delta::MAX_ORDER);
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Ordem da diferença não fornecida.");
+                        std::process::exit(1);
+                    }
+                };
+            }
+            "--big-endian" => {
+                delta_options.byte_order = ByteOrder::Big;
+            }
+            "--dump" => {
+                dump = true;
+            }
+            "--pack" => {
+                image_options.packed = true;
+            }
+            "--gradients" | "-g" => {
+                image_options.gradients = true;
+            }
+            "--condition" | "-c" => {
+                image_options.conditioned = true;
+            }
+            "--name" | "-N" => {
+                preserve = true;
+            }
+            "--partial" => {
+                partial = true;
+            }
+            "--max-output" | "--max-model" | "--max-memory" => {
+                let value = match iter.next().map(|value| value.parse::<u64>()) {
+                    Some(Ok(value)) => value,
+                    Some(Err(_)) => {
+                        println!("Valor de {} inválido.", arg);
+                        std::process::exit(1);
+                    }
+                    None => {
+                        println!("Valor de {} não fornecido.", arg);
+                        std::process::exit(1);
+                    }
+                };
+                match arg.as_str() {
+                    "--max-output" => limits.max_output_bytes = Some(value),
+                    "--max-model" => limits.max_model_size = Some(value),
+                    "--max-memory" => limits.max_memory = Some(value),
+                    _ => unreachable!(),
+                }
+            }
             "--decode" | "-d" | "--encode" | "-e" => {
                 operation = Some(match arg.as_str() {
                     "--decode" | "-d" => Operation::Decode,
@@ -78,18 +382,56 @@ fn main() {
                 }
             }
             _ => {
-                println!("\nUso: {} <parâmetros> <operação>\n", args[0]);
-                println!("Operações suportadas:");
-                println!("  -e, --encode <arquivo>    Codificar o conteúdo do arquivo informado.");
-                println!("  -d, --decode <arquivo>    Decodificar o conteúdo do arquivo informado.\n");
-                println!("Parâmetros de codificação:");
-                println!("  -l, --low <valor>         Define o valor de low.");
-                println!("  -h, --high <valor>        Define o valor de high.\n");
-                std::process::exit(1);
+                print_usage(&args[0]);
             }
         }
     }
 
+    // as opções dos modos podem aparecer antes ou depois de --mode
+    match &mut mode {
+        Mode::Image(options) => *options = image_options,
+        Mode::Delta(options) => {
+            *options = DeltaOptions {
+                width: width.unwrap_or(delta_options.width),
+                ..delta_options
+            }
+        }
+        Mode::Float(options) => *options = FloatOptions::new(width.unwrap_or(FloatOptions::default().width), delta_options.byte_order),
+        Mode::JpegLs { near: value } => *value = near,
+        Mode::Dct { quality: value } => *value = quality,
+        Mode::Wavelet { levels: value } => *value = levels.unwrap_or(DEFAULT_LEVELS),
+        Mode::Progressive { levels: value } => *value = levels.unwrap_or(progressive::DEFAULT_LEVELS),
+        Mode::Sequence { key_interval: interval, motion: value } => {
+            *interval = key_interval;
+            *value = motion;
+        }
+        _ => {}
+    }
+    if let Mode::Float(options) = mode {
+        if !matches!(options.width, 4 | 8) {
+            println!("\nNo modo float, a largura deve ser 4 (f32) ou 8 (f64).\n");
+            std::process::exit(1);
+        }
+    }
+    // com --tile, o modo image é codificado em blocos independentes
+    if let Some(size) = tile {
+        mode = match mode {
+            Mode::Image(options) => Mode::Tiled { size, options },
+            _ => {
+                println!("\nA opção --tile requer o modo image.\n");
+                std::process::exit(1);
+            }
+        };
+    }
+    if dump && !matches!(mode, Mode::Image(_)) {
+        println!("\nA opção --dump requer o modo image.\n");
+        std::process::exit(1);
+    }
+    if [preview.is_some(), region.is_some(), partial, frame.is_some()].iter().filter(|&&option| option).count() > 1 {
+        println!("\nAs opções --preview, --region, --partial e --frame não podem ser usadas juntas.\n");
+        std::process::exit(1);
+    }
+
     let operation = match operation {
         Some(operation) => operation,
         None => {
@@ -114,55 +456,41 @@ fn main() {
 
     match operation.clone() {
         Operation::Decode => {
-            if !file_path.ends_with(".ac") {
-                println!("\nO arquivo informado não possui a extensão \".ac\"!\n");
-                std::process::exit(1);
-            }
-
-            // abre arquivo de entrada
-            let mut input_file = match fs::File::open(file_path) {
-                Ok(input_file) => input_file,
+            // lê arquivo de entrada
+            let input = match fs::read(file_path) {
+                Ok(input) => input,
                 Err(e) => {
                     eprintln!("\nErro ao abrir o arquivo: {}\n", e);
                     std::process::exit(1);
                 }
             };
 
-            // obtém o número de dígitos do último valor gravado
-            let input_file_len = match input_file.metadata() {
-                Ok(metadata) => metadata.len(),
-                Err(e) => {
-                    eprintln!("\nErro ao obter metadados do arquivo de entrada: {}\n", e);
-                    std::process::exit(1);
+            // com --partial, o arquivo pode estar truncado e não é separado como os demais
+            if partial {
+                let output_file_path = match (output_path, file_path.strip_suffix(".ac")) {
+                    (Some(output_path), _) => PathBuf::from(output_path),
+                    (None, Some(stem)) => derived_path(stem, "partial"),
+                    (None, None) => {
+                        println!("\nO arquivo informado não possui a extensão \".ac\"!\n");
+                        std::process::exit(1);
+                    }
+                };
+                let output = create_output(&output_file_path, force);
+                match decode_partial(&input, &limits, BufWriter::new(output.file())) {
+                    Ok((passes, total)) => println!("\nPassadas decodificadas: {} de {}.\n", passes, total),
+                    Err(e) => {
+                        drop(output);
+                        eprintln!("\nErro ao decodificar: {}\n", e);
+                        std::process::exit(1);
+                    }
                 }
-            };
-
-            let last_value_shifts_position = input_file_len - size_of::<u8>() as u64;
-            input_file.seek(std::io::SeekFrom::Start(last_value_shifts_position)).unwrap();
-
-            let mut last_value_shifts_buffer: [u8; 1] = [0];
-            if let Err(e) = input_file.read_exact(&mut last_value_shifts_buffer) {
-                eprintln!("\nErro ao obter o tamanho dos dados codificados: {}\n", e);
-                std::process::exit(1);
-            };
-            let last_value_shifts = u8::from_le_bytes(last_value_shifts_buffer);
-
-            // obtém o tamanho em bytes dos dados codificados
-            let encoded_data_len_position = input_file_len - (size_of::<u64>() + size_of::<u8>()) as u64;
-            input_file.seek(std::io::SeekFrom::Start(encoded_data_len_position)).unwrap();
-
-            let mut encoded_data_len_buffer: [u8; 8] = [0,0,0,0,0,0,0,0];
-            if let Err(e) = input_file.read_exact(&mut encoded_data_len_buffer) {
-                eprintln!("\nErro ao obter o tamanho dos dados codificados: {}\n", e);
-                std::process::exit(1);
-            };
-            let encoded_data_len = u64::from_le_bytes(encoded_data_len_buffer);
-
-            // lê estrutura de dados principal
-            input_file.seek(std::io::SeekFrom::Start(encoded_data_len)).unwrap();
+                persist_output(output);
+                return;
+            }
 
-            let mut arithmetic_coding: ArithmeticCoding = match deserialize_from(&mut input_file) {
-                Ok(arithmetic_coding) => arithmetic_coding,
+            // separa dados codificados e estrutura de dados principal
+            let mut container = match Container::parse(&input, &limits) {
+                Ok(container) => container,
                 Err(e) => {
                     eprintln!("\nErro ao ler o arquivo de entrada: {}\n", e);
                     std::process::exit(1);
@@ -170,33 +498,74 @@ fn main() {
             };
 
             if let Some(low) = low {
-                arithmetic_coding.low = low;
+                container.ac.low = low;
             }
             if let Some(high) = high {
-                arithmetic_coding.high = high;
+                container.ac.high = high;
             }
 
-            // cria arquivo de saída
-            let mut output_file_path = file_path.to_string();
-            output_file_path.truncate(file_path.len() - 3);
-            output_file_path += ".dec";
+            // no modo sequence, cada quadro é gravado em um arquivo próprio
+            match container.header.mode {
+                Mode::Sequence { .. } => {
+                    decode_sequence(&container, &limits, frame, output_path, file_path, force);
+                    return;
+                }
+                _ if frame.is_some() => {
+                    println!("\nA opção --frame requer um arquivo do modo sequence.\n");
+                    std::process::exit(1);
+                }
+                _ => {}
+            }
 
-            let output_file = match fs::File::create(output_file_path) {
-                Ok(output_file) => output_file,
-                Err(e) => {
-                    eprintln!("\nErro ao criar o arquivo de saída: {}\n", e);
+            // com --name, o arquivo recebe o nome original gravado no cabeçalho
+            let metadata = if preserve {
+                container.header.metadata.clone()
+            } else {
+                None
+            };
+            let original_name = metadata.as_ref().and_then(|metadata| metadata.safe_name());
+            let output_file_path = match (output_path, original_name, file_path.strip_suffix(".ac")) {
+                (Some(output_path), _, _) => PathBuf::from(output_path),
+                // a prévia da wavelet é sempre um BMP; a dos planos de bits tem o formato original
+                (None, _, Some(stem)) if preview.is_some() => match container.header.mode {
+                    Mode::Wavelet { .. } => PathBuf::from(String::from(stem) + ".preview.bmp"),
+                    _ => derived_path(stem, "preview"),
+                },
+                (None, _, Some(stem)) if region.is_some() => derived_path(stem, "region"),
+                (None, Some(name), _) => Path::new(file_path).with_file_name(name),
+                (None, None, Some(stem)) => PathBuf::from(String::from(stem) + ".dec"),
+                (None, None, None) => {
+                    println!("\nO arquivo informado não possui a extensão \".ac\"!\n");
                     std::process::exit(1);
                 }
             };
 
-            // decodifica
-            let mut decoder = ArithmeticDecoder::new(
-                arithmetic_coding,
-                encoded_data_len / size_of::<u32>() as u64,
-                last_value_shifts as u32,
-                output_file,
-            );
-            decoder.decode(&mut input_file);
+            // cria arquivo de saída temporário
+            let output = create_output(&output_file_path, force);
+
+            // decodifica, ou apenas os níveis grossos ou planos de bits altos com --preview, ou
+            // apenas os blocos de uma região com --region
+            let result = match (preview, region) {
+                (Some(skip), _) => container.decode_preview(&limits, skip, BufWriter::new(output.file())),
+                (None, Some(region)) => container.decode_region(&limits, region, BufWriter::new(output.file())),
+                (None, None) => container.decode(&limits, BufWriter::new(output.file())),
+            };
+            if let Err(e) = result {
+                drop(output);
+                eprintln!("\nErro ao decodificar: {}\n", e);
+                std::process::exit(1);
+            }
+
+            // restaura permissões e data de modificação do arquivo original
+            if let Some(metadata) = metadata {
+                if let Err(e) = metadata.restore(output.file()) {
+                    drop(output);
+                    eprintln!("\nErro ao restaurar os metadados do arquivo: {}\n", e);
+                    std::process::exit(1);
+                }
+            }
+
+            persist_output(output);
         }
         Operation::Encode => {
             let low = match low {
@@ -214,40 +583,304 @@ fn main() {
                 }
             };
 
-            // abre arquivo de entrada
-            let mut input_file = match fs::File::open(file_path) {
-                Ok(input_file) => input_file,
-                Err(e) => {
-                    eprintln!("\nErro ao abrir o arquivo: {}\n", e);
-                    std::process::exit(1);
-                }
+            // lê arquivo de entrada; no modo sequence, também os quadros seguintes
+            let paths = match mode {
+                Mode::Sequence { .. } => numbered_frames(file_path),
+                _ => vec![PathBuf::from(file_path)],
             };
-            let input_file_len = match input_file.metadata() {
-                Ok(metadata) => metadata.len(),
-                Err(e) => {
-                    eprintln!("\nErro ao obter metadados do arquivo de entrada: {}\n", e);
-                    std::process::exit(1);
+            let mut inputs: Vec<Vec<u8>> = Vec::with_capacity(paths.len());
+            for path in paths.iter() {
+                match fs::read(path) {
+                    Ok(input) => inputs.push(input),
+                    Err(e) => {
+                        eprintln!("\nErro ao abrir o arquivo \"{}\": {}\n", path.display(), e);
+                        std::process::exit(1);
+                    }
                 }
+            }
+            if let Mode::Sequence { .. } = mode {
+                println!("\nQuadros: {} (de {} a {}).", paths.len(), paths[0].display(), paths[paths.len() - 1].display());
+            }
+
+            // com --name, grava nome, permissões e data de modificação no cabeçalho
+            let header = if preserve {
+                FileMetadata::from_path(Path::new(file_path)).map(|metadata| Header {
+                    mode,
+                    metadata: Some(metadata),
+                    ..Header::default()
+                })
+            } else {
+                Ok(Header {
+                    mode,
+                    ..Header::default()
+                })
             };
 
-            // cria arquivo de saída
-            let output_file_path = String::from(file_path) + ".ac";
-            let output_file = match fs::File::create(output_file_path) {
-                Ok(output_file) => output_file,
+            // cria arquivo de saída temporário
+            let output_file_path = match output_path {
+                Some(output_path) => PathBuf::from(output_path),
+                None => PathBuf::from(String::from(file_path) + ".ac"),
+            };
+            let output = create_output(&output_file_path, force);
+
+            // codifica
+            let result = header.and_then(|header| {
+                let frames: Vec<&[u8]> = inputs.iter().map(Vec::as_slice).collect();
+                match mode {
+                    Mode::Sequence { .. } => encode_sequence(&frames, &header, low, high, BufWriter::new(output.file())),
+                    _ => encode(frames[0], &header, low, high, BufWriter::new(output.file())),
+                }
+            });
+            let report = match result {
+                Ok(report) => report,
                 Err(e) => {
-                    eprintln!("\nErro ao criar o arquivo de saída: {}\n", e);
+                    drop(output);
+                    eprintln!("\nErro ao codificar: {}\n", e);
                     std::process::exit(1);
                 }
             };
 
-            // codifica
-            let mut encoder = ArithmeticEncoder::new(low, high, output_file);
-            encoder.verify_file_len(input_file_len);
-            encoder.encode(&mut input_file);
+            persist_output(output);
+
+            println!("\nTamanho dos dados codificados: {} bytes.", report.sizes.data_len);
+            println!("Tamanho da tabela de símbolos: {} bytes.", report.sizes.table_len);
+            if let Some(max_error) = report.max_error {
+                println!("Erro máximo observado: {}", max_error);
+            }
+            if let Some(psnr) = report.psnr {
+                println!("PSNR em relação ao original: {:.2} dB", psnr);
+            }
+            println!();
+
+            // com --dump, grava os estágios intermediários ao lado do arquivo de entrada
+            if dump {
+                write_dump(&inputs[0], mode, low, high, file_path, force);
+            }
+        }
+    }
+}
+
+// grava os planos transformados, os resíduos e o custo de cada pixel em <arquivo>.<estágio>.bmp
+fn write_dump(input: &[u8], mode: Mode, low: u32, high: u32, file_path: &str, force: bool) {
+    let header = Header {
+        mode,
+        ..Header::default()
+    };
+    let dump = match dump(input, &header, low, high) {
+        Ok(dump) => dump,
+        Err(e) => {
+            eprintln!("\nErro ao exportar os estágios: {}\n", e);
+            std::process::exit(1);
+        }
+    };
+    for (name, bytes) in [("transformed", &dump.transformed), ("residuals", &dump.residuals), ("costs", &dump.costs)] {
+        let output = create_output(&Path::new(file_path).with_extension(format!("{}.bmp", name)), force);
+        if let Err(e) = output.file().write_all(bytes) {
+            drop(output);
+            eprintln!("\nErro ao gravar o arquivo de saída: {}\n", e);
+            std::process::exit(1);
+        }
+        persist_output(output);
+    }
+    println!("\nCusto dos pixels: {:.0} bits ({:.3} bits por pixel).\n", dump.bits, dump.bits / dump.pixels.max(1) as f64);
+}
+
+// a partir de quadro0001.bmp, os arquivos quadro0002.bmp, quadro0003.bmp, ... até o primeiro
+// que não existir; o número é o último grupo de dígitos do nome
+fn numbered_frames(path: &str) -> Vec<PathBuf> {
+    let path = Path::new(path);
+    let mut frames = vec![path.to_path_buf()];
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return frames,
+    };
+    let end = match name.rfind(|c: char| c.is_ascii_digit()) {
+        Some(position) => position + 1,
+        None => return frames,
+    };
+    let start = name[..end]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_digit())
+        .last()
+        .map_or(end, |(position, _)| position);
+    let first = match name[start..end].parse::<u64>() {
+        Ok(first) => first,
+        Err(_) => return frames,
+    };
+    for number in first + 1.. {
+        let next = path.with_file_name(format!("{}{:0width$}{}", &name[..start], number, &name[end..], width = end - start));
+        if !next.is_file() {
+            break;
+        }
+        frames.push(next);
+    }
+    frames
+}
+
+// grava o quadro escolhido com --frame, ou todos, em <arquivo>.<número>.<extensão>; com -o e
+// --frame, o quadro é gravado no arquivo informado
+fn decode_sequence(container: &Container, limits: &DecodeLimits, frame: Option<usize>, output_path: Option<&str>, file_path: &str, force: bool) {
+    let base = match (output_path, file_path.strip_suffix(".ac")) {
+        (Some(output_path), _) => output_path,
+        (None, Some(stem)) => stem,
+        (None, None) => {
+            println!("\nO arquivo informado não possui a extensão \".ac\"!\n");
+            std::process::exit(1);
+        }
+    };
+    let count = container.frame_count().unwrap_or(0);
+    let digits = count.saturating_sub(1).to_string().len();
+    let (first, last) = match frame {
+        Some(index) => (index, index),
+        None => (0, count.saturating_sub(1)),
+    };
+
+    let result = container.decode_frames(limits, first, last, |index, bytes| {
+        let path = match (frame, output_path) {
+            (Some(_), Some(output_path)) => PathBuf::from(output_path),
+            _ => derived_path(base, &format!("{:0digits$}", index, digits = digits)),
+        };
+        let output = create_output(&path, force);
+        output.file().write_all(&bytes)?;
+        persist_output(output);
+        Ok(())
+    });
+    if let Err(e) = result {
+        eprintln!("\nErro ao decodificar: {}\n", e);
+        std::process::exit(1);
+    }
+    println!("\nQuadros decodificados: {} de {}.\n", last - first + 1, count);
+}
+
+// caminho de um arquivo derivado, com a extensão do original: foto.bmp gera foto.<nome>.bmp
+fn derived_path(stem: &str, name: &str) -> PathBuf {
+    match Path::new(stem).extension() {
+        Some(extension) => Path::new(stem).with_extension(Path::new(name).with_extension(extension)),
+        None => PathBuf::from(format!("{}.{}", stem, name)),
+    }
+}
+
+fn create_output(path: &Path, force: bool) -> OutputFile {
+    match OutputFile::create(path, force) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("\nErro ao criar o arquivo de saída: {}\n", e);
+            std::process::exit(1);
         }
     }
 }
 
+fn persist_output(output: OutputFile) {
+    let path = output.path().to_path_buf();
+    if let Err(e) = output.persist() {
+        eprintln!("\nErro ao gravar o arquivo de saída \"{}\": {}\n", path.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn print_usage(program: &str) -> ! {
+    println!("\nUso: {} <parâmetros> <operação>\n", program);
+    println!("Operações suportadas:");
+    println!("  -e, --encode <arquivo>    Codificar o conteúdo do arquivo informado.");
+    println!("  -d, --decode <arquivo>    Decodificar o conteúdo do arquivo informado.\n");
+    println!("Parâmetros gerais:");
+    println!("  -o, --output <arquivo>    Define o caminho do arquivo de saída.");
+    println!("  -f, --force               Sobrescreve o arquivo de saída, se existir.");
+    println!("  -N, --name                Grava (na codificação) ou restaura (na decodificação)");
+    println!("                            o nome, as permissões e a data de modificação originais.\n");
+    println!("Parâmetros de codificação:");
+    println!("  -l, --low <valor>         Define o valor de low.");
+    println!("  -h, --high <valor>        Define o valor de high.");
+    println!("  -m, --mode <modo>         Define o modo de codificação:");
+    println!("                              bytes  bytes do arquivo, sem transformação (padrão);");
+    println!("                              dpcm   resíduos da predição MED dos pixels de um BMP;");
+    println!("                              image  resíduos de cada canal de um BMP ou Netpbm (P5");
+    println!("                                     e P6), com um modelo adaptativo por canal;");
+    println!("                              jpegls preditor MED com correção de viés por contexto e");
+    println!("                                     modo de corrida, sem perdas ou com perdas limitadas;");
+    println!("                              dct    com perdas, estilo JPEG (YCbCr, DCT 8x8 e");
+    println!("                                     quantização);");
+    println!("                              wavelet sem perdas, com a wavelet inteira 5/3;");
+    println!("                              bitplane sem perdas, em planos de bits, do mais");
+    println!("                                     significativo para o menos significativo;");
+    println!("                              bilevel sem perdas, para BMP de 1 bit por pixel, com");
+    println!("                                     contextos de 16 vizinhos, como no JBIG2;");
+    println!("                              progressive sem perdas, em passadas que dobram a");
+    println!("                                     resolução, como no PNG entrelaçado (Adam7);");
+    println!("                              sequence sem perdas, para quadros numerados (por");
+    println!("                                     exemplo, quadro0001.bmp, quadro0002.bmp, ...),");
+    println!("                                     cada um previsto pelo anterior.");
+    println!("                              wav    sem perdas, para áudio WAV PCM de 8, 16 ou 24");
+    println!("                                     bits, mono ou estéreo, com predição linear.");
+    println!("                              delta  elementos inteiros (por exemplo, amostras de");
+    println!("                                     sensores) substituídos pela diferença para os");
+    println!("                                     anteriores, com um modelo por byte do elemento.");
+    println!("                              float  séries de f32 ou f64, cada valor previsto pelos");
+    println!("                                     anteriores, com o XOR dos padrões de bits.");
+    println!("                            Os modos de imagem, de image a sequence, aceitam BMP e");
+    println!("                            Netpbm.");
+    println!("  -c, --condition           No modo image, usa a magnitude do resíduo do canal");
+    println!("                            anterior como contexto do canal seguinte.");
+    println!("  -t, --transform <nome>    No modo image, aplica uma transformação de cor reversível");
+    println!("                            aos canais B, G e R: none (padrão), ycocg (YCoCg-R) ou");
+    println!("                            rct (RCT do JPEG 2000).");
+    println!("  --palette <ordem>         No modo image, reordena a paleta de imagens indexadas");
+    println!("                            antes da predição: none (padrão), luminance (da cor mais");
+    println!("                            escura para a mais clara) ou tour (cor mais próxima).");
+    println!("  --pack                    No modo image, leva os valores usados em cada canal a um");
+    println!("                            intervalo contínuo antes da predição.");
+    println!("  -p, --predictor <nome>    No modo image, define o preditor: med (padrão) ou png");
+    println!("                            (filtros None, Sub, Up, Average e Paeth, escolhidos");
+    println!("                            linha a linha).");
+    println!("  -g, --gradients           No modo image, escolhe a tabela de frequências de cada");
+    println!("                            resíduo pela atividade (gradientes) dos vizinhos.");
+    println!("  --near <valor>            No modo jpegls, maior erro absoluto permitido em cada");
+    println!("                            amostra (padrão 0, sem perdas).");
+    println!("  -q, --quality <valor>     No modo dct, qualidade de 1 a 100 (padrão 75).");
+    println!("  --levels <valor>          No modo wavelet, quantidade de níveis de decomposição,");
+    println!("                            de 0 a 10 (padrão 5). No modo progressive, quantidade");
+    println!("                            de níveis da grade inicial, de 0 a 10 (padrão 3, Adam7).");
+    println!("  --tile <pixels>           No modo image, codifica a imagem em blocos quadrados");
+    println!("                            independentes com o lado informado (por exemplo, 256),");
+    println!("                            permitindo decodificar apenas uma região.");
+    println!("  --keyframes <valor>       No modo sequence, intervalo entre os quadros-chave,");
+    println!("                            codificados sem o quadro anterior (padrão 30).");
+    println!("  --motion                  No modo sequence, procura um vetor de movimento para");
+    println!("                            cada bloco de 16x16 pixels.");
+    println!("  --width <bytes>           No modo delta, largura de cada elemento: 1, 2 (padrão),");
+    println!("                            4 ou 8 bytes. No modo float, 4 (f32) ou 8 (f64, padrão).");
+    println!("  --stride <elementos>      No modo delta, distância até o elemento usado na");
+    println!("                            predição (padrão 1); em dados intercalados, a quantidade");
+    println!("                            de canais.");
+    println!("  --order <valor>           No modo delta, ordem da diferença: 0 (nenhuma), 1");
+    println!("                            (padrão) ou 2.");
+    println!("  --big-endian              Nos modos delta e float, lê os elementos com o byte");
+    println!("                            mais significativo primeiro (o padrão é little-endian).");
+    println!("  --dump                    No modo image, grava também os planos transformados");
+    println!("                            (<arquivo>.transformed.bmp), os resíduos da predição em");
+    println!("                            tons de cinza (<arquivo>.residuals.bmp) e um mapa de calor");
+    println!("                            do custo de cada pixel em bits (<arquivo>.costs.bmp).\n");
+    println!("Parâmetros de decodificação:");
+    println!("  --max-output <bytes>      Limita o tamanho do arquivo decodificado.");
+    println!("  --max-model <bytes>       Limita o tamanho da estrutura de dados lida do arquivo.");
+    println!("  --max-memory <bytes>      Limita a memória alocada pelo decodificador.");
+    println!("  --preview <níveis>        No modo wavelet, ignora os níveis mais finos e grava uma");
+    println!("                            prévia em BMP com 1/2^níveis das dimensões originais. No");
+    println!("                            modo bitplane, ignora os planos de bits menos");
+    println!("                            significativos e grava uma aproximação do original.");
+    println!("  --region <x,y,l,a>        Em arquivos codificados com --tile, decodifica apenas os");
+    println!("                            blocos que cruzam o retângulo de largura l e altura a a");
+    println!("                            partir de (x, y) e grava o recorte no formato original.");
+    println!("  --partial                 Em arquivos do modo bitplane ou progressive, mesmo");
+    println!("                            truncados, decodifica os planos de bits ou as passadas");
+    println!("                            completas e grava uma prévia do tamanho original.");
+    println!("  --frame <número>          Em arquivos do modo sequence, decodifica apenas o quadro");
+    println!("                            informado, a partir de 0, começando pelo quadro-chave");
+    println!("                            anterior. Sem ela, todos os quadros são gravados.\n");
+    std::process::exit(1);
+}
+
 fn verify_gp2_minus_one(number: u32) -> bool {
     let number = number as u64;
     let mut gp: u64 = 1;
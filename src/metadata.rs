@@ -0,0 +1,150 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+use std::time::UNIX_EPOCH;
+
+use crate::Result;
+
+// nome, permissões e data de modificação do arquivo original, como a opção `-N` do gzip
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMetadata {
+    pub name: String,
+    pub mode: u32,
+    pub modified: Option<(i64, u32)>,
+}
+
+impl FileMetadata {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => String::new(),
+        };
+        let mode = permissions_to_mode(&metadata.permissions());
+        let modified = metadata.modified().ok().map(|modified| {
+            match modified.duration_since(UNIX_EPOCH) {
+                Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+                Err(e) => {
+                    let before = e.duration();
+                    if before.subsec_nanos() == 0 {
+                        (-(before.as_secs() as i64), 0)
+                    } else {
+                        (-(before.as_secs() as i64) - 1, 1_000_000_000 - before.subsec_nanos())
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            name,
+            mode,
+            modified,
+        })
+    }
+
+    // o nome vem de um arquivo não confiável: só é aceito se não puder sair do diretório de destino
+    pub fn safe_name(&self) -> Option<&str> {
+        let name = self.name.as_str();
+        let invalid = name.is_empty()
+            || name == "."
+            || name == ".."
+            || name.contains(['/', '\\', '\0']);
+        if invalid {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    pub fn restore(&self, file: &File) -> Result<()> {
+        if let Some((seconds, nanos)) = self.modified {
+            let modified = if seconds >= 0 {
+                UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanos.min(999_999_999)))
+            } else {
+                UNIX_EPOCH
+                    .checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+                    .and_then(|time| time.checked_add(Duration::from_nanos(nanos.min(999_999_999) as u64)))
+            };
+            if let Some(modified) = modified {
+                file.set_modified(modified)?;
+            }
+        }
+
+        file.set_permissions(mode_to_permissions(self.mode, file)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn permissions_to_mode(permissions: &fs::Permissions) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn permissions_to_mode(permissions: &fs::Permissions) -> u32 {
+    if permissions.readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+// bits setuid, setgid e sticky nunca são restaurados
+#[cfg(unix)]
+fn mode_to_permissions(mode: u32, _file: &File) -> Result<fs::Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::Permissions::from_mode(mode & 0o777))
+}
+
+#[cfg(not(unix))]
+fn mode_to_permissions(mode: u32, file: &File) -> Result<fs::Permissions> {
+    let mut permissions = file.metadata()?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    Ok(permissions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str) -> FileMetadata {
+        FileMetadata {
+            name: String::from(name),
+            mode: 0o644,
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn unsafe_names_are_ignored() {
+        assert_eq!(metadata("foto.bmp").safe_name(), Some("foto.bmp"));
+        for name in ["", ".", "..", "../foto.bmp", "dir/foto.bmp", "dir\\foto.bmp", "foto\0.bmp"] {
+            assert_eq!(metadata(name).safe_name(), None);
+        }
+    }
+
+    #[test]
+    fn metadata_is_restored() {
+        let path = std::env::temp_dir().join(format!("pidac-metadata-{}", std::process::id()));
+        let file = File::create(&path).unwrap();
+        let mut saved = metadata("original.txt");
+        saved.mode = 0o640;
+        saved.modified = Some((-86401, 500));
+        saved.restore(&file).unwrap();
+        drop(file);
+
+        let restored = FileMetadata::from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(restored.modified, saved.modified);
+        if cfg!(unix) {
+            assert_eq!(restored.mode, 0o640);
+        }
+    }
+}
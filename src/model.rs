@@ -0,0 +1,285 @@
+use crate::Error;
+use crate::Result;
+use crate::Symbols;
+
+// limite padrão para o total de um modelo adaptativo e incremento a cada símbolo codificado
+pub const MODEL_LIMIT: u64 = 1 << 16;
+pub const MODEL_INCREMENT: u32 = 32;
+
+// maior quantidade de símbolos codificados com modelos adaptativos de pelo menos `size`
+// símbolos (e no máximo MODEL_LIMIT / 16) que cabem em `len` bytes de dados codificados: como os
+// demais símbolos mantêm frequência 1 e o intervalo é maior que o total, cada símbolo custa
+// mais de (size - 1) / (MODEL_LIMIT + 1) bits, e o codificador grava todos os bits menos 2
+pub fn max_symbols(len: usize, size: usize) -> u64 {
+    (len as u64)
+        .saturating_add(1)
+        .saturating_mul(8)
+        .saturating_mul(MODEL_LIMIT + 1)
+        / (size as u64).saturating_sub(1).max(1)
+}
+
+// modelo de frequências consultado pelo codificador a cada símbolo; o total deve ser
+// menor que `ArithmeticEncoder::max_total`
+pub trait Model {
+    fn total(&self) -> u64;
+    fn low_and_high(&self, symbol: u32) -> Result<(u64, u64)>;
+    fn symbol_by_value(&self, value: u64) -> Result<u32>;
+    fn update(&mut self, symbol: u32);
+}
+
+// codifica (no codificador) ou decodifica (no decodificador) um símbolo com o modelo
+// informado, para que a mesma rotina percorra os dados nos dois sentidos; na decodificação,
+// `symbol` é ignorado e o símbolo decodificado é retornado
+pub trait Coder {
+    fn code<M: Model + ?Sized>(&mut self, model: &mut M, symbol: u32) -> Result<u32>;
+    fn max_total(&self) -> u64;
+}
+
+// percorre os símbolos como o codificador, atualizando os modelos da mesma forma, mas apenas
+// anota cada símbolo com o seu custo em bits (-log2 da probabilidade dada pelo modelo)
+pub struct CostMeter {
+    pub symbols: Vec<(u32, f64)>,
+    max_total: u64,
+}
+
+impl CostMeter {
+    pub fn new(max_total: u64) -> Self {
+        Self {
+            symbols: Vec::new(),
+            max_total,
+        }
+    }
+}
+
+impl Coder for CostMeter {
+    fn code<M: Model + ?Sized>(&mut self, model: &mut M, symbol: u32) -> Result<u32> {
+        let (low, high) = model.low_and_high(symbol)?;
+        let bits = (model.total() as f64 / (high - low) as f64).log2();
+        self.symbols.push((symbol, bits));
+        model.update(symbol);
+        Ok(symbol)
+    }
+
+    fn max_total(&self) -> u64 {
+        self.max_total
+    }
+}
+
+// a tabela estática do modo bytes, calculada antes da codificação e gravada no arquivo
+impl Model for Symbols {
+    fn total(&self) -> u64 {
+        self.total
+    }
+
+    fn low_and_high(&self, symbol: u32) -> Result<(u64, u64)> {
+        match u8::try_from(symbol) {
+            Ok(symbol) => self.get_low_and_high(symbol),
+            Err(_) => Err(Error::SymbolNotFound),
+        }
+    }
+
+    fn symbol_by_value(&self, value: u64) -> Result<u32> {
+        self.get_symbol_by_value(value).map(|symbol| symbol as u32)
+    }
+
+    fn update(&mut self, _symbol: u32) {}
+}
+
+// modelo adaptativo para os símbolos 0..size: todos começam com frequência 1, que aumenta
+// a cada ocorrência; quando o total atinge o limite, as frequências são divididas por dois.
+// As frequências acumuladas ficam em uma árvore de Fenwick.
+#[derive(Debug, Clone)]
+pub struct AdaptiveModel {
+    frequencies: Vec<u32>,
+    tree: Vec<u64>,
+    total: u64,
+    limit: u64,
+}
+
+impl AdaptiveModel {
+    pub fn new(size: usize, max_total: u64) -> Result<Self> {
+        let limit = max_total.min(MODEL_LIMIT.max(size as u64 * 16));
+        if size == 0 || limit < size as u64 + 2 * MODEL_INCREMENT as u64 {
+            return Err(Error::LowPrecision);
+        }
+        let mut model = Self {
+            frequencies: vec![1; size],
+            tree: vec![0; size + 1],
+            total: 0,
+            limit,
+        };
+        model.rebuild();
+        Ok(model)
+    }
+
+    pub fn size(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    // memória ocupada por um modelo de `size` símbolos
+    pub fn memory_len(size: usize) -> u64 {
+        (size as u64) * (size_of::<u32>() + size_of::<u64>()) as u64
+    }
+
+    fn rebuild(&mut self) {
+        let size = self.frequencies.len();
+        self.tree.iter_mut().for_each(|node| *node = 0);
+        for (index, &frequency) in self.frequencies.iter().enumerate() {
+            let node = index + 1;
+            self.tree[node] += frequency as u64;
+            let parent = node + (node & node.wrapping_neg());
+            if parent <= size {
+                self.tree[parent] += self.tree[node];
+            }
+        }
+        self.total = self.frequencies.iter().map(|&frequency| frequency as u64).sum();
+    }
+
+    // soma das frequências dos símbolos menores que `symbol`
+    fn prefix(&self, symbol: usize) -> u64 {
+        let mut node = symbol;
+        let mut sum = 0;
+        while node > 0 {
+            sum += self.tree[node];
+            node &= node - 1;
+        }
+        sum
+    }
+
+    fn add(&mut self, symbol: usize, amount: u64) {
+        let mut node = symbol + 1;
+        while node < self.tree.len() {
+            self.tree[node] += amount;
+            node += node & node.wrapping_neg();
+        }
+    }
+}
+
+impl Model for AdaptiveModel {
+    fn total(&self) -> u64 {
+        self.total
+    }
+
+    fn low_and_high(&self, symbol: u32) -> Result<(u64, u64)> {
+        let symbol = symbol as usize;
+        match self.frequencies.get(symbol) {
+            Some(&frequency) => {
+                let low = self.prefix(symbol);
+                Ok((low, low + frequency as u64))
+            }
+            None => Err(Error::SymbolNotFound),
+        }
+    }
+
+    fn symbol_by_value(&self, value: u64) -> Result<u32> {
+        if value >= self.total {
+            return Err(Error::SymbolNotFound);
+        }
+        let size = self.frequencies.len();
+        let mut position = 0;
+        let mut remaining = value;
+        let mut step = 1 << (usize::BITS - 1 - size.leading_zeros());
+        while step > 0 {
+            if position + step <= size && self.tree[position + step] <= remaining {
+                position += step;
+                remaining -= self.tree[position];
+            }
+            step >>= 1;
+        }
+        Ok(position as u32)
+    }
+
+    fn update(&mut self, symbol: u32) {
+        let symbol = symbol as usize;
+        if symbol >= self.frequencies.len() {
+            return;
+        }
+        self.frequencies[symbol] += MODEL_INCREMENT;
+        self.add(symbol, MODEL_INCREMENT as u64);
+        self.total += MODEL_INCREMENT as u64;
+
+        if self.total > self.limit - MODEL_INCREMENT as u64 {
+            for frequency in self.frequencies.iter_mut() {
+                *frequency = frequency.div_ceil(2);
+            }
+            self.rebuild();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        Rng,
+    };
+
+    #[test]
+    fn adaptive_model_ranges_cover_the_total() {
+        let mut model = AdaptiveModel::new(10, 1 << 20).unwrap();
+        let mut rng = Rng(7);
+        for _ in 0..10000 {
+            model.update(rng.below(3) as u32 * 3);
+        }
+        assert!(model.total() <= MODEL_LIMIT);
+        let mut low = 0;
+        for symbol in 0..10 {
+            let (symbol_low, high) = model.low_and_high(symbol).unwrap();
+            assert_eq!(symbol_low, low);
+            // os símbolos que não apareceram continuam codificáveis depois das divisões por dois
+            assert!(high > symbol_low);
+            assert_eq!(model.symbol_by_value(symbol_low).unwrap(), symbol);
+            assert_eq!(model.symbol_by_value(high - 1).unwrap(), symbol);
+            low = high;
+        }
+        assert_eq!(low, model.total());
+        assert!(model.symbol_by_value(model.total()).is_err());
+        assert!(model.low_and_high(10).is_err());
+    }
+
+    #[test]
+    fn small_models_are_rejected() {
+        assert!(matches!(AdaptiveModel::new(0, 1 << 20), Err(Error::LowPrecision)));
+        assert!(matches!(AdaptiveModel::new(256, 300), Err(Error::LowPrecision)));
+    }
+
+    // o custo medido é o tamanho que a codificação terá, a menos do final e da estrutura de dados
+    #[test]
+    fn cost_meter_matches_the_encoder() {
+        let mut rng = Rng(11);
+        let symbols: Vec<u32> = (0..20000).map(|_| (rng.below(16) * rng.below(16) / 16) as u32).collect();
+        let mut meter = CostMeter::new(1 << 29);
+        let mut model = AdaptiveModel::new(16, 1 << 29).unwrap();
+        for &symbol in &symbols {
+            meter.code(&mut model, symbol).unwrap();
+        }
+        let bits: f64 = meter.symbols.iter().map(|&(_, bits)| bits).sum();
+        let encoded = encode(|encoder| {
+            let mut model = AdaptiveModel::new(16, encoder.max_total())?;
+            symbols.iter().try_for_each(|&symbol| encoder.code(&mut model, symbol).map(|_| ()))
+        });
+        let overhead = (encoded.len() * 8) as f64 - bits;
+        assert!((0.0..1000.0).contains(&overhead), "{}", overhead);
+        assert_eq!(meter.symbols.iter().map(|&(symbol, _)| symbol).collect::<Vec<_>>(), symbols);
+    }
+
+    // o limite de `max_symbols` vale mesmo para a sequência mais barata possível
+    #[test]
+    fn max_symbols_bounds_the_cheapest_symbols() {
+        let count = 200000;
+        let encoded = encode(|encoder| {
+            let mut model = AdaptiveModel::new(2, encoder.max_total())?;
+            (0..count).try_for_each(|_| encoder.code(&mut model, 0).map(|_| ()))
+        });
+        let decoded = decode(&encoded, |decoder| {
+            let mut model = AdaptiveModel::new(2, decoder.max_total())?;
+            (0..count).map(|_| decoder.code(&mut model, 0)).collect::<Result<Vec<_>>>()
+        })
+        .unwrap();
+        assert!(decoded.iter().all(|&symbol| symbol == 0));
+        assert!(max_symbols(encoded.len(), 2) >= count);
+        assert!(max_symbols(encoded.len(), 256) < max_symbols(encoded.len(), 2));
+    }
+}
@@ -0,0 +1,270 @@
+use crate::bmp::BmpShell;
+use crate::image::Image;
+use crate::Error;
+use crate::Result;
+
+// cabeçalho de um arquivo Netpbm binário: P5 (tons de cinza) ou P6 (RGB), com amostras de
+// 1 byte, ou de 2 bytes big-endian quando o valor máximo passa de 255
+#[derive(Debug, Clone)]
+pub struct Netpbm {
+    pub channels: usize,
+    pub width: u32,
+    pub height: u32,
+    pub max_value: u16,
+    pub pixel_offset: usize,
+}
+
+impl Netpbm {
+    // lê apenas o cabeçalho, isto é, os bytes anteriores às amostras
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let channels = match bytes.get(..2) {
+            Some(b"P5") => 1,
+            Some(b"P6") => 3,
+            _ => return Err(Error::InvalidImage("cabeçalho Netpbm ausente")),
+        };
+        let mut position = 2;
+        let width = read_number(bytes, &mut position)?;
+        let height = read_number(bytes, &mut position)?;
+        let max_value = read_number(bytes, &mut position)?;
+
+        // um único espaço separa o valor máximo das amostras
+        match bytes.get(position) {
+            Some(byte) if byte.is_ascii_whitespace() => position += 1,
+            _ => return Err(Error::InvalidImage("cabeçalho Netpbm incompleto")),
+        }
+
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidImage("dimensões inválidas"));
+        }
+        if max_value == 0 || max_value > u16::MAX as u32 {
+            return Err(Error::UnsupportedImage("valor máximo de amostra não suportado"));
+        }
+        Image::checked_len(width as usize, height as usize)?;
+
+        Ok(Self {
+            channels,
+            width,
+            height,
+            max_value: max_value as u16,
+            pixel_offset: position,
+        })
+    }
+
+    pub fn sample_len(&self) -> usize {
+        if self.max_value > u8::MAX as u16 {
+            2
+        } else {
+            1
+        }
+    }
+
+    // tamanho da região de amostras
+    pub fn pixel_len(&self) -> usize {
+        self.width as usize * self.height as usize * self.channels * self.sample_len()
+    }
+
+    // um plano por canal, com os bits necessários para o valor máximo; no P6, os planos ficam
+    // na ordem B, G, R, como nos BMP
+    pub fn plane_depths(&self) -> Vec<u8> {
+        vec![(u16::BITS - self.max_value.leading_zeros()) as u8; self.channels]
+    }
+
+    // converte a região de amostras em planos; amostras acima do que a profundidade comporta
+    // são limitadas e devem ser corrigidas pelo chamador
+    pub fn read_image(&self, bytes: &[u8]) -> Result<Image> {
+        let len = self.pixel_len();
+        let pixels = match bytes.get(self.pixel_offset..).and_then(|pixels| pixels.get(..len)) {
+            Some(pixels) => pixels,
+            None => return Err(Error::InvalidImage("dados de pixels fora do arquivo")),
+        };
+        let mut image = Image::new(self.width as usize, self.height as usize, &self.plane_depths())?;
+        let sample_len = self.sample_len();
+        for (index, sample) in pixels.chunks_exact(sample_len).enumerate() {
+            let value = match sample {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                _ => sample[0] as u16,
+            };
+            let (plane, position) = self.locate(index);
+            let plane = &mut image.planes[plane];
+            plane.samples[position] = value.min(plane.max_value());
+        }
+        Ok(image)
+    }
+
+    pub fn write_pixels(&self, image: &Image) -> Result<Vec<u8>> {
+        if image.width != self.width as usize
+            || image.height != self.height as usize
+            || image.depths() != self.plane_depths()
+        {
+            return Err(Error::InvalidImage("planos incompatíveis com o cabeçalho Netpbm"));
+        }
+
+        let sample_len = self.sample_len();
+        let mut pixels = vec![0u8; self.pixel_len()];
+        for (index, sample) in pixels.chunks_exact_mut(sample_len).enumerate() {
+            let (plane, position) = self.locate(index);
+            let value = image.planes[plane].samples[position];
+            match sample {
+                [high, low] => [*high, *low] = value.to_be_bytes(),
+                _ => sample[0] = value as u8,
+            }
+        }
+        Ok(pixels)
+    }
+
+    // plano e posição no plano da amostra `index` do arquivo, gravada na ordem R, G, B
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let channel = index % self.channels;
+        (self.channels - 1 - channel, index / self.channels)
+    }
+}
+
+pub fn is_netpbm(bytes: &[u8]) -> bool {
+    matches!(bytes.get(..2), Some(b"P5") | Some(b"P6"))
+}
+
+// separa o arquivo como `bmp::split`: o cabeçalho, com comentários, e os bytes após as amostras
+// são mantidos, e amostras acima do valor máximo são gravadas como correções
+pub fn split(bytes: &[u8]) -> Result<(BmpShell, Image)> {
+    let netpbm = Netpbm::parse(bytes)?;
+    let image = netpbm.read_image(bytes)?;
+    let end = netpbm.pixel_offset + netpbm.pixel_len();
+    let region = &bytes[netpbm.pixel_offset..end];
+
+    let generated = netpbm.write_pixels(&image)?;
+    let mut fixups: Vec<(u32, u8)> = Vec::new();
+    for (position, (&original, &generated)) in region.iter().zip(generated.iter()).enumerate() {
+        if original != generated {
+            let position = u32::try_from(position).map_err(|_| Error::UnsupportedImage("amostras acima do valor máximo em arquivo muito grande"))?;
+            fixups.push((position, original));
+        }
+    }
+
+    let shell = BmpShell {
+        header: bytes[..netpbm.pixel_offset].to_vec(),
+        pixel_data: None,
+        fixups,
+        trailer: bytes[end..].to_vec(),
+    };
+    Ok((shell, image))
+}
+
+pub fn join(shell: &BmpShell, image: &Image) -> Result<Vec<u8>> {
+    let netpbm = Netpbm::parse(&shell.header)?;
+    let mut pixels = netpbm.write_pixels(image)?;
+    for &(position, value) in shell.fixups.iter() {
+        match pixels.get_mut(position as usize) {
+            Some(pixel) => *pixel = value,
+            None => return Err(Error::InvalidImage("correção fora da região de pixels")),
+        }
+    }
+
+    let mut output = Vec::with_capacity(shell.header.len() + pixels.len() + shell.trailer.len());
+    output.extend_from_slice(&shell.header);
+    output.extend_from_slice(&pixels);
+    output.extend_from_slice(&shell.trailer);
+    Ok(output)
+}
+
+// grava uma imagem menor, como um recorte, no mesmo formato de um arquivo original, com um
+// cabeçalho novo, sem os comentários
+pub fn write_resized(header: &[u8], image: &Image) -> Result<Vec<u8>> {
+    let original = Netpbm::parse(header)?;
+    let magic = if original.channels == 3 { "P6" } else { "P5" };
+    let header = format!("{}\n{} {}\n{}\n", magic, image.width, image.height, original.max_value);
+    let netpbm = Netpbm::parse(header.as_bytes())?;
+    let mut output = header.into_bytes();
+    output.extend_from_slice(&netpbm.write_pixels(image)?);
+    Ok(output)
+}
+
+// número decimal precedido de espaços e comentários (de `#` até o fim da linha)
+fn read_number(bytes: &[u8], position: &mut usize) -> Result<u32> {
+    loop {
+        match bytes.get(*position) {
+            Some(b'#') => {
+                while bytes.get(*position).is_some_and(|&byte| byte != b'\n' && byte != b'\r') {
+                    *position += 1;
+                }
+            }
+            Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+            _ => break,
+        }
+    }
+    let start = *position;
+    while bytes.get(*position).is_some_and(u8::is_ascii_digit) {
+        *position += 1;
+    }
+    std::str::from_utf8(&bytes[start..*position])
+        .ok()
+        .and_then(|digits| digits.parse::<u32>().ok())
+        .ok_or(Error::InvalidImage("cabeçalho Netpbm inválido"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> (BmpShell, Image) {
+        let (shell, image) = split(bytes).unwrap();
+        assert_eq!(join(&shell, &image).unwrap(), bytes);
+        (shell, image)
+    }
+
+    #[test]
+    fn gray_and_rgb_round_trip() {
+        let mut bytes = b"P5\n# comentario\n3 2\n255\n".to_vec();
+        bytes.extend_from_slice(&[0, 10, 20, 30, 40, 255]);
+        let (shell, image) = round_trip(&bytes);
+        assert_eq!(image.depths(), vec![8]);
+        assert_eq!(image.get(0, 2, 1), 255);
+        assert!(shell.header.ends_with(b"255\n"));
+
+        let mut bytes = b"P6 2 1 255 ".to_vec();
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        bytes.extend_from_slice(b"trailer");
+        let (shell, image) = round_trip(&bytes);
+        // planos na ordem B, G, R
+        assert_eq!((image.get(0, 0, 0), image.get(2, 1, 0)), (3, 4));
+        assert_eq!(shell.trailer, b"trailer");
+    }
+
+    // amostras acima do valor máximo são limitadas nos planos e gravadas como correções
+    #[test]
+    fn wide_samples_round_trip() {
+        let mut bytes = b"P5 2 2 1000\n".to_vec();
+        for value in [0u16, 999, 1000, 1023] {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        let (shell, image) = round_trip(&bytes);
+        assert_eq!(image.depths(), vec![10]);
+        assert_eq!(image.get(0, 1, 1), 1023);
+
+        let mut bytes = b"P5 2 1 7\n".to_vec();
+        bytes.extend_from_slice(&[7, 200]);
+        let (shell_over, image) = round_trip(&bytes);
+        assert_eq!(image.get(0, 1, 0), 7);
+        assert_eq!(shell_over.fixups, vec![(1, 200)]);
+        assert!(shell.fixups.is_empty());
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert!(matches!(split(b"P4 1 1 1\n\0"), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(b"P5 2 2\n"), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(b"P5 0 2 255\n"), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(b"P5 1 1 0\n\0"), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(split(b"P5 1 1 65536\n\0\0"), Err(Error::UnsupportedImage(_))));
+        assert!(matches!(split(b"P5 1 1 255"), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(b"P6 2 2 255\n\0\0\0"), Err(Error::InvalidImage(_))));
+        assert!(matches!(split(b"P5 99999999999 1 255\n"), Err(Error::InvalidImage(_))));
+    }
+
+    #[test]
+    fn resized_copies_drop_the_comments() {
+        let (shell, _) = split(b"P5\n#c\n1 1\n300\n\0\x05").unwrap();
+        let mut small = Image::new(2, 1, &[9]).unwrap();
+        small.set(0, 1, 0, 299);
+        assert_eq!(write_resized(&shell.header, &small).unwrap(), b"P5\n2 1\n300\n\0\0\x01\x2B");
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+
+// arquivo de saída gravado em um arquivo temporário no mesmo diretório do destino e
+// movido para o destino apenas quando a operação termina com sucesso
+pub struct OutputFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: File,
+    force: bool,
+    persisted: bool,
+}
+
+impl OutputFile {
+    pub fn create(path: &Path, force: bool) -> io::Result<Self> {
+        if !force && path.exists() {
+            return Err(already_exists(path));
+        }
+
+        let directory = match path.parent() {
+            Some(directory) if !directory.as_os_str().is_empty() => directory,
+            _ => Path::new("."),
+        };
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "caminho de saída inválido")),
+        };
+
+        // `create_new` garante que um arquivo temporário existente nunca seja reaproveitado
+        let mut attempt: u32 = 0;
+        loop {
+            let temp_path = directory.join(format!(".{}.{}.{}.tmp", file_name, process::id(), attempt));
+            match fs::OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                        temp_path,
+                        file,
+                        force,
+                        persisted: false,
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn persist(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        if !self.force && self.path.exists() {
+            return Err(already_exists(&self.path));
+        }
+        fs::rename(&self.temp_path, &self.path)?;
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("o arquivo \"{}\" já existe (use --force para sobrescrever)", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("pidac-output-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn entries(directory: &Path) -> usize {
+        fs::read_dir(directory).unwrap().count()
+    }
+
+    #[test]
+    fn output_is_moved_into_place_only_when_persisted() {
+        let directory = temp_dir("persist");
+        let path = directory.join("saida.ac");
+
+        let output = OutputFile::create(&path, false).unwrap();
+        io::Write::write_all(&mut output.file(), b"PIDAC").unwrap();
+        assert!(!path.exists());
+        output.persist().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"PIDAC");
+        assert_eq!(entries(&directory), 1);
+
+        // uma operação interrompida não deixa arquivos temporários para trás
+        let output = OutputFile::create(&directory.join("interrompido.ac"), false).unwrap();
+        drop(output);
+        assert_eq!(entries(&directory), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn existing_files_require_force() {
+        let directory = temp_dir("force");
+        let path = directory.join("saida.ac");
+        fs::write(&path, b"original").unwrap();
+
+        let error = OutputFile::create(&path, false).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        // o destino pode surgir enquanto a operação ainda está em andamento
+        fs::remove_file(&path).unwrap();
+        let output = OutputFile::create(&path, false).unwrap();
+        fs::write(&path, b"concorrente").unwrap();
+        assert_eq!(output.persist().unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"concorrente");
+
+        let output = OutputFile::create(&path, true).unwrap();
+        io::Write::write_all(&mut output.file(), b"novo").unwrap();
+        output.persist().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"novo");
+        assert_eq!(entries(&directory), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::image::Image;
+use crate::Error;
+use crate::Result;
+
+// reordenação da paleta aplicada aos índices antes da predição, para que cores parecidas
+// tenham índices próximos; a nova ordem é calculada a partir da paleta gravada no cabeçalho
+// do BMP, então não precisa ser gravada
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteOrder {
+    #[default]
+    None,
+    // da cor mais escura para a mais clara
+    Luminance,
+    // a partir da cor mais escura, sempre a cor ainda não visitada mais próxima
+    Tour,
+}
+
+// novo índice de cada índice original; os índices além das cores da paleta são mantidos
+pub fn permutation(order: PaletteOrder, palette: &[[u8; 3]], depth: u8) -> Vec<u16> {
+    let len = 1usize << depth;
+    let colours = palette.len().min(len);
+    let sorted: Vec<usize> = match order {
+        PaletteOrder::None => (0..colours).collect(),
+        PaletteOrder::Luminance => {
+            let mut sorted: Vec<usize> = (0..colours).collect();
+            sorted.sort_by_key(|&index| luminance(palette[index]));
+            sorted
+        }
+        PaletteOrder::Tour => tour(&palette[..colours]),
+    };
+
+    let mut positions: Vec<u16> = (0..len).map(|index| index as u16).collect();
+    for (position, &index) in sorted.iter().enumerate() {
+        positions[index] = position as u16;
+    }
+    positions
+}
+
+// troca os índices de cada pixel pelos da paleta reordenada; `palette` é a paleta do arquivo,
+// ausente em imagens sem paleta
+pub fn forward(order: PaletteOrder, palette: Option<&[[u8; 3]]>, image: &Image) -> Result<Image> {
+    remap(order, palette, image, false)
+}
+
+// restaura os índices originais a partir da paleta original, gravada no arquivo
+pub fn inverse(order: PaletteOrder, palette: Option<&[[u8; 3]]>, image: &Image) -> Result<Image> {
+    remap(order, palette, image, true)
+}
+
+fn remap(order: PaletteOrder, palette: Option<&[[u8; 3]]>, image: &Image, inverse: bool) -> Result<Image> {
+    if order == PaletteOrder::None {
+        return Ok(image.clone());
+    }
+    let palette = match palette {
+        Some(palette) if image.planes.len() == 1 => palette,
+        _ => return Err(Error::UnsupportedImage("a reordenação da paleta requer imagem com paleta")),
+    };
+    let positions = permutation(order, palette, image.planes[0].depth);
+    let mut table = positions.clone();
+    if inverse {
+        for (index, &position) in positions.iter().enumerate() {
+            table[position as usize] = index as u16;
+        }
+    }
+
+    let mut output = image.clone();
+    for sample in output.planes[0].samples.iter_mut() {
+        *sample = table[*sample as usize];
+    }
+    Ok(output)
+}
+
+// luminância (BT.601) de uma cor na ordem B, G, R
+fn luminance(colour: [u8; 3]) -> u32 {
+    let [blue, green, red] = colour.map(|channel| channel as u32);
+    299 * red + 587 * green + 114 * blue
+}
+
+// ordem de visita do vizinho mais próximo, pela distância euclidiana entre as cores; em caso
+// de empate, vale o menor índice
+fn tour(palette: &[[u8; 3]]) -> Vec<usize> {
+    let mut remaining: Vec<usize> = (0..palette.len()).collect();
+    let mut sorted = Vec::with_capacity(palette.len());
+    let mut current = match remaining.iter().min_by_key(|&&index| luminance(palette[index])) {
+        Some(&index) => index,
+        None => return sorted,
+    };
+    loop {
+        remaining.retain(|&index| index != current);
+        sorted.push(current);
+        current = match remaining.iter().min_by_key(|&&index| distance(palette[current], palette[index])) {
+            Some(&index) => index,
+            None => return sorted,
+        };
+    }
+}
+
+fn distance(first: [u8; 3], second: [u8; 3]) -> u32 {
+    first
+        .iter()
+        .zip(second.iter())
+        .map(|(&first, &second)| (first as i32 - second as i32).pow(2) as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [[u8; 3]; 4] = [[255, 255, 255], [0, 0, 0], [0, 0, 250], [128, 128, 128]];
+
+    #[test]
+    fn orders_are_permutations() {
+        assert_eq!(permutation(PaletteOrder::Luminance, &PALETTE, 2), vec![3, 0, 1, 2]);
+        // do preto ao cinza, mais próximo que o vermelho, e então ao vermelho e ao branco
+        assert_eq!(permutation(PaletteOrder::Tour, &PALETTE, 2), vec![3, 0, 2, 1]);
+        assert_eq!(permutation(PaletteOrder::Tour, &[[0, 0, 0], [255, 255, 255], [0, 0, 250], [0, 0, 200]], 2), vec![0, 3, 2, 1]);
+        // cores além da profundidade são ignoradas e índices sem cor são mantidos
+        assert_eq!(permutation(PaletteOrder::Luminance, &PALETTE[..1], 2), vec![0, 1, 2, 3]);
+        assert_eq!(permutation(PaletteOrder::Luminance, &PALETTE, 1), vec![1, 0]);
+    }
+
+    #[test]
+    fn remapping_round_trips() {
+        let mut image = Image::new(4, 1, &[2]).unwrap();
+        for x in 0..4 {
+            image.set(0, x, 0, x as u16);
+        }
+        for order in [PaletteOrder::None, PaletteOrder::Luminance, PaletteOrder::Tour] {
+            let remapped = forward(order, Some(&PALETTE), &image).unwrap();
+            assert_eq!(inverse(order, Some(&PALETTE), &remapped).unwrap().planes, image.planes);
+        }
+    }
+
+    #[test]
+    fn images_without_palette_are_rejected() {
+        let image = Image::new(2, 2, &[8, 8, 8]).unwrap();
+        assert!(matches!(forward(PaletteOrder::Tour, Some(&PALETTE), &image), Err(Error::UnsupportedImage(_))));
+        let image = Image::new(2, 2, &[8]).unwrap();
+        assert!(matches!(inverse(PaletteOrder::Luminance, None, &image), Err(Error::UnsupportedImage(_))));
+        assert!(forward(PaletteOrder::None, None, &image).is_ok());
+    }
+}
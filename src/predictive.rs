@@ -0,0 +1,397 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::colour::ColourTransform;
+use crate::decoder::ArithmeticDecoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+    CostMeter,
+};
+use crate::palette::PaletteOrder;
+use crate::Error;
+use crate::Result;
+
+// quantidade de faixas de magnitude do resíduo do canal anterior usadas como contexto
+pub const CONTEXTS: usize = 8;
+
+// quantidade de faixas de atividade local (gradientes dos vizinhos) usadas como contexto,
+// e limites inferiores das faixas para amostras de 8 bits
+pub const GRADIENT_CONTEXTS: usize = 8;
+const GRADIENT_THRESHOLDS: [u32; GRADIENT_CONTEXTS - 1] = [1, 4, 8, 15, 26, 43, 71];
+
+// filtros de linha do PNG: None, Sub, Up, Average e Paeth
+pub const FILTERS: usize = 5;
+
+// preditor das amostras: MED em todos os pixels, ou um filtro do PNG escolhido por linha
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Predictor {
+    #[default]
+    Med,
+    Png,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageOptions {
+    // cada canal usa a magnitude do resíduo do canal anterior, no mesmo pixel, como contexto
+    pub conditioned: bool,
+    // transformação de cor aplicada aos planos antes da predição
+    pub transform: ColourTransform,
+    pub predictor: Predictor,
+    // cada canal usa uma tabela por faixa de atividade dos vizinhos já codificados
+    pub gradients: bool,
+    // reordenação da paleta aplicada aos índices antes da predição
+    pub palette: PaletteOrder,
+    // os valores usados em cada plano são levados a um intervalo contínuo antes da predição
+    pub packed: bool,
+}
+
+impl ImageOptions {
+    pub fn new(conditioned: bool, transform: ColourTransform, predictor: Predictor, gradients: bool, palette: PaletteOrder, packed: bool) -> Self {
+        Self {
+            conditioned,
+            transform,
+            predictor,
+            gradients,
+            palette,
+            packed,
+        }
+    }
+
+    fn contexts(&self, plane: usize) -> usize {
+        self.channel_contexts(plane) * self.gradient_contexts()
+    }
+
+    fn channel_contexts(&self, plane: usize) -> usize {
+        if self.conditioned && plane > 0 {
+            CONTEXTS
+        } else {
+            1
+        }
+    }
+
+    fn gradient_contexts(&self) -> usize {
+        if self.gradients {
+            GRADIENT_CONTEXTS
+        } else {
+            1
+        }
+    }
+}
+
+// memória ocupada pelos modelos de uma imagem com estes planos
+pub fn models_memory_len(depths: &[u8], options: &ImageOptions) -> u64 {
+    depths
+        .iter()
+        .enumerate()
+        .map(|(plane, &depth)| {
+            AdaptiveModel::memory_len(1 << depth).saturating_mul(options.contexts(plane) as u64)
+        })
+        .fold(0, u64::saturating_add)
+        .saturating_add(AdaptiveModel::memory_len(FILTERS))
+}
+
+// os resíduos da predição são codificados pixel a pixel, com um modelo por canal; com os
+// filtros do PNG, o filtro de cada linha é codificado antes dela
+pub fn encode<C: Coder>(encoder: &mut C, image: &Image, options: &ImageOptions) -> Result<()> {
+    let mut models = create_models(&image.depths(), options, encoder.max_total())?;
+    let mut filter_model = AdaptiveModel::new(FILTERS, encoder.max_total())?;
+    let masks: Vec<u16> = image.planes.iter().map(|plane| plane.max_value()).collect();
+    let mut residuals = vec![0u16; image.planes.len()];
+
+    for y in 0..image.height {
+        let filter = match options.predictor {
+            Predictor::Med => None,
+            Predictor::Png => {
+                let filter = choose_filter(image, &masks, y);
+                encoder.code(&mut filter_model, filter as u32)?;
+                Some(filter)
+            }
+        };
+        for x in 0..image.width {
+            for plane in 0..image.planes.len() {
+                let mask = masks[plane];
+                let predicted = predict(image, plane, x, y, filter);
+                let residual = image.get(plane, x, y).wrapping_sub(predicted) & mask;
+                let context = context(image, &residuals, &masks, plane, x, y, options);
+                encoder.code(&mut models[plane][context], residual as u32)?;
+                residuals[plane] = residual;
+            }
+        }
+    }
+    Ok(())
+}
+
+// resíduo de cada amostra e custo, em bits, de cada pixel com os modelos da codificação; o
+// custo do filtro de uma linha é dividido entre os pixels dela
+pub fn costs(image: &Image, options: &ImageOptions, max_total: u64) -> Result<(Image, Vec<f64>)> {
+    let mut meter = CostMeter::new(max_total);
+    encode(&mut meter, image, options)?;
+    let mut residuals = Image::new(image.width, image.height, &image.depths())?;
+    let mut costs = vec![0.0; image.width * image.height];
+    let mut symbols = meter.symbols.into_iter();
+    let mut next = || symbols.next().ok_or(Error::InvalidImage("símbolos faltando na medição"));
+
+    for y in 0..image.height {
+        let filter_bits = match options.predictor {
+            Predictor::Med => 0.0,
+            Predictor::Png => next()?.1 / image.width as f64,
+        };
+        for x in 0..image.width {
+            let cost = &mut costs[y * image.width + x];
+            *cost += filter_bits;
+            for plane in 0..image.planes.len() {
+                let (residual, bits) = next()?;
+                residuals.set(plane, x, y, residual as u16);
+                *cost += bits;
+            }
+        }
+    }
+    Ok((residuals, costs))
+}
+
+// reconstrói os planos de `image`, que já deve ter as dimensões e profundidades gravadas
+pub fn decode(decoder: &mut ArithmeticDecoder, image: &mut Image, options: &ImageOptions) -> Result<()> {
+    let mut models = create_models(&image.depths(), options, decoder.max_total())?;
+    let mut filter_model = AdaptiveModel::new(FILTERS, decoder.max_total())?;
+    let masks: Vec<u16> = image.planes.iter().map(|plane| plane.max_value()).collect();
+    let mut residuals = vec![0u16; image.planes.len()];
+
+    for y in 0..image.height {
+        let filter = match options.predictor {
+            Predictor::Med => None,
+            Predictor::Png => Some(decoder.decode_symbol(&mut filter_model)? as u8),
+        };
+        for x in 0..image.width {
+            for plane in 0..image.planes.len() {
+                let mask = masks[plane];
+                let context = context(image, &residuals, &masks, plane, x, y, options);
+                let residual = decoder.decode_symbol(&mut models[plane][context])? as u16;
+                let predicted = predict(image, plane, x, y, filter);
+                image.set(plane, x, y, residual.wrapping_add(predicted) & mask);
+                residuals[plane] = residual;
+            }
+        }
+    }
+    Ok(())
+}
+
+// escolhe o filtro com a menor soma dos módulos dos resíduos da linha, como no libpng
+fn choose_filter(image: &Image, masks: &[u16], y: usize) -> u8 {
+    let mut best_filter = 0;
+    let mut best_sum = u64::MAX;
+    for filter in 0..FILTERS as u8 {
+        let mut sum: u64 = 0;
+        for x in 0..image.width {
+            for (plane, &mask) in masks.iter().enumerate() {
+                let predicted = predict(image, plane, x, y, Some(filter));
+                let residual = image.get(plane, x, y).wrapping_sub(predicted) & mask;
+                sum += magnitude(residual, mask) as u64;
+            }
+        }
+        if sum < best_sum {
+            best_filter = filter;
+            best_sum = sum;
+        }
+    }
+    best_filter
+}
+
+fn create_models(depths: &[u8], options: &ImageOptions, max_total: u64) -> Result<Vec<Vec<AdaptiveModel>>> {
+    let mut models = Vec::with_capacity(depths.len());
+    for (plane, &depth) in depths.iter().enumerate() {
+        let model = AdaptiveModel::new(1 << depth, max_total)?;
+        models.push(vec![model; options.contexts(plane)]);
+    }
+    Ok(models)
+}
+
+// combina a faixa da magnitude do resíduo do canal anterior (0, 1, 2, 3-4, 5-8, 9-16,
+// 17-32, 33+) com a faixa de atividade dos vizinhos
+fn context(image: &Image, residuals: &[u16], masks: &[u16], plane: usize, x: usize, y: usize, options: &ImageOptions) -> usize {
+    let channel = if options.channel_contexts(plane) == 1 {
+        0
+    } else {
+        let magnitude = magnitude(residuals[plane - 1], masks[plane - 1]);
+        match magnitude {
+            0..=2 => magnitude as usize,
+            _ => ((magnitude - 1).ilog2() as usize + 2).min(CONTEXTS - 1),
+        }
+    };
+    let gradient = if options.gradients {
+        gradient_context(image, plane, x, y)
+    } else {
+        0
+    };
+    channel * options.gradient_contexts() + gradient
+}
+
+// soma dos gradientes locais, como no JPEG-LS: d - b, b - c e c - a, onde d é o vizinho
+// acima à direita; a soma é levada à escala de 8 bits antes da quantização
+fn gradient_context(image: &Image, plane: usize, x: usize, y: usize) -> usize {
+    if y == 0 {
+        return 0;
+    }
+    let b = image.get(plane, x, y - 1) as i32;
+    let a = if x > 0 { image.get(plane, x - 1, y) as i32 } else { b };
+    let c = if x > 0 { image.get(plane, x - 1, y - 1) as i32 } else { b };
+    let d = if x + 1 < image.width { image.get(plane, x + 1, y - 1) as i32 } else { b };
+    let activity = ((d - b).abs() + (b - c).abs() + (c - a).abs()) as u32;
+
+    let depth = image.planes[plane].depth as u32;
+    let activity = if depth > 8 {
+        activity >> (depth - 8)
+    } else {
+        activity << (8 - depth)
+    };
+    GRADIENT_THRESHOLDS
+        .iter()
+        .take_while(|&&threshold| activity >= threshold)
+        .count()
+}
+
+// módulo de um resíduo gravado módulo `mask + 1`
+fn magnitude(residual: u16, mask: u16) -> u32 {
+    let residual = residual as u32;
+    residual.min(mask as u32 + 1 - residual)
+}
+
+// vizinhos à esquerda (a), acima (b) e acima à esquerda (c); sem `filter`, usa o preditor MED
+fn predict(image: &Image, plane: usize, x: usize, y: usize, filter: Option<u8>) -> u16 {
+    let filter = match filter {
+        Some(filter) => filter,
+        None => return predict_med(image, plane, x, y),
+    };
+    // como no PNG, vizinhos fora da imagem valem zero
+    let a = if x > 0 { image.get(plane, x - 1, y) } else { 0 };
+    let b = if y > 0 { image.get(plane, x, y - 1) } else { 0 };
+    let c = if x > 0 && y > 0 { image.get(plane, x - 1, y - 1) } else { 0 };
+    match filter {
+        1 => a,
+        2 => b,
+        3 => ((a as u32 + b as u32) / 2) as u16,
+        4 => paeth(a, b, c),
+        _ => 0,
+    }
+}
+
+fn predict_med(image: &Image, plane: usize, x: usize, y: usize) -> u16 {
+    match (x > 0, y > 0) {
+        (true, true) => med(
+            image.get(plane, x - 1, y),
+            image.get(plane, x, y - 1),
+            image.get(plane, x - 1, y - 1),
+        ),
+        (true, false) => image.get(plane, x - 1, y),
+        (false, true) => image.get(plane, x, y - 1),
+        (false, false) => 0,
+    }
+}
+
+fn paeth(a: u16, b: u16, c: u16) -> u16 {
+    let estimate = a as i32 + b as i32 - c as i32;
+    let distance_a = (estimate - a as i32).abs();
+    let distance_b = (estimate - b as i32).abs();
+    let distance_c = (estimate - c as i32).abs();
+    if distance_a <= distance_b && distance_a <= distance_c {
+        a
+    } else if distance_b <= distance_c {
+        b
+    } else {
+        c
+    }
+}
+
+fn med(a: u16, b: u16, c: u16) -> u16 {
+    let (min, max) = if a < b { (a, b) } else { (b, a) };
+    if c >= max {
+        min
+    } else if c <= min {
+        max
+    } else {
+        (a as i32 + b as i32 - c as i32) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::ArithmeticEncoder;
+    use crate::testing::{
+        decode,
+        encode,
+        photo,
+    };
+
+    fn round_trip(image: &Image, options: &ImageOptions) -> usize {
+        let encoded = encode(|encoder| super::encode(encoder, image, options));
+        let mut decoded = Image::new(image.width, image.height, &image.depths()).unwrap();
+        decode(&encoded, |decoder| super::decode(decoder, &mut decoded, options)).unwrap();
+        assert_eq!(decoded.planes, image.planes);
+        encoded.len()
+    }
+
+    #[test]
+    fn every_predictor_round_trips() {
+        let image = photo(23, 17, &[8, 8, 8]);
+        let raw = 23 * 17 * 3;
+        assert!(round_trip(&image, &ImageOptions::default()) < raw / 2);
+        assert!(round_trip(&image, &ImageOptions::new(false, ColourTransform::None, Predictor::Png, false, PaletteOrder::None, false)) < raw / 2);
+        round_trip(&image, &ImageOptions::new(true, ColourTransform::None, Predictor::Med, false, PaletteOrder::None, false));
+        round_trip(&image, &ImageOptions::new(true, ColourTransform::YCoCgR, Predictor::Png, false, PaletteOrder::None, false));
+        round_trip(&image, &ImageOptions::new(false, ColourTransform::None, Predictor::Med, true, PaletteOrder::None, false));
+        round_trip(&photo(5, 9, &[12, 1]), &ImageOptions::new(true, ColourTransform::None, Predictor::Png, true, PaletteOrder::None, false));
+    }
+
+    #[test]
+    fn png_filters_predict_from_the_neighbours() {
+        let mut image = Image::new(2, 2, &[8]).unwrap();
+        for (x, y, value) in [(0, 0, 10), (1, 0, 20), (0, 1, 30), (1, 1, 0)] {
+            image.set(0, x, y, value);
+        }
+        let predictions: Vec<u16> = (0..FILTERS as u8).map(|filter| predict(&image, 0, 1, 1, Some(filter))).collect();
+        assert_eq!(predictions, [0, 30, 20, 25, 30]);
+        // na primeira linha, os vizinhos de cima valem zero
+        assert_eq!(predict(&image, 0, 1, 0, Some(4)), 10);
+        assert_eq!((paeth(10, 20, 15), paeth(10, 20, 5), paeth(10, 20, 30)), (15, 20, 10));
+    }
+
+    // a atividade dos vizinhos é levada à escala de 8 bits antes da quantização
+    #[test]
+    fn gradients_select_the_context() {
+        let mut image = Image::new(3, 2, &[8, 10]).unwrap();
+        assert_eq!(gradient_context(&image, 0, 1, 1), 0);
+        image.set(0, 2, 0, 10);
+        image.set(1, 2, 0, 40);
+        assert_eq!((gradient_context(&image, 0, 1, 1), gradient_context(&image, 1, 1, 1)), (3, 3));
+        image.set(0, 2, 0, 200);
+        assert_eq!(gradient_context(&image, 0, 1, 1), GRADIENT_CONTEXTS - 1);
+        // na primeira linha não há vizinhos acima
+        assert_eq!(gradient_context(&image, 0, 1, 0), 0);
+
+        let options = ImageOptions::new(true, ColourTransform::None, Predictor::Med, true, PaletteOrder::None, false);
+        assert_eq!((options.contexts(0), options.contexts(1)), (GRADIENT_CONTEXTS, CONTEXTS * GRADIENT_CONTEXTS));
+    }
+
+    // o custo medido de cada pixel soma o tamanho da codificação
+    #[test]
+    fn costs_follow_the_encoding() {
+        let image = photo(16, 16, &[8]);
+        let options = ImageOptions::default();
+        let (residuals, costs) = costs(&image, &options, (1 << 30) - 1).unwrap();
+        let bits: f64 = costs.iter().sum();
+        let len = round_trip(&image, &options);
+        assert!(bits <= (len * 8) as f64 && bits > (len * 8) as f64 - 600.0);
+        assert_eq!(residuals.get(0, 0, 0), image.get(0, 0, 0));
+    }
+
+    #[test]
+    fn low_precision_is_rejected() {
+        let image = photo(4, 4, &[16]);
+        let mut encoder = ArithmeticEncoder::new(0, (1 << 16) - 1, Vec::new()).unwrap();
+        assert!(matches!(super::encode(&mut encoder, &image, &ImageOptions::default()), Err(Error::LowPrecision)));
+    }
+}
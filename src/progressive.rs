@@ -0,0 +1,271 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::ArithmeticCoding;
+use crate::Error;
+use crate::Result;
+
+// com 3 níveis, as passadas são as do Adam7 (PNG entrelaçado)
+pub const DEFAULT_LEVELS: u8 = 3;
+pub const MAX_LEVELS: u8 = 10;
+
+// faixas da diferença entre os dois vizinhos usados na predição, em amostras de 8 bits
+const CONTEXTS: usize = 8;
+const THRESHOLDS: [u32; CONTEXTS - 1] = [1, 3, 6, 12, 24, 48, 96];
+
+// como cada pixel de uma passada é previsto: pela MED sobre a grade inicial, ou pela média
+// dos vizinhos à esquerda e à direita, ou acima e abaixo, já decodificados
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Base,
+    Horizontal,
+    Vertical,
+}
+
+const KINDS: usize = 3;
+
+// pixels (x, y) com x = x0 + i * step_x e y = y0 + j * step_y; cada um representa, na prévia,
+// o retângulo `fill_width` x `fill_height` a partir dele, ainda não decodificado
+#[derive(Debug, Clone, Copy)]
+struct Pass {
+    kind: Kind,
+    x0: usize,
+    y0: usize,
+    step_x: usize,
+    step_y: usize,
+    // distância até os vizinhos usados na predição
+    distance: usize,
+    fill_width: usize,
+    fill_height: usize,
+}
+
+// grade inicial com um pixel a cada 2^levels, seguida, em cada nível, de uma passada com os
+// pixels entre os da grade na horizontal e outra com as linhas intermediárias; são 2 * levels + 1
+// passadas, e cada uma dobra a resolução horizontal ou vertical da anterior
+fn passes(levels: u8) -> Result<Vec<Pass>> {
+    if levels > MAX_LEVELS {
+        return Err(Error::UnsupportedImage("quantidade de níveis inválida"));
+    }
+    let size = 1usize << levels;
+    let mut passes = vec![Pass {
+        kind: Kind::Base,
+        x0: 0,
+        y0: 0,
+        step_x: size,
+        step_y: size,
+        distance: size,
+        fill_width: size,
+        fill_height: size,
+    }];
+    for level in (1..=levels).rev() {
+        let step = 1usize << level;
+        let half = step / 2;
+        passes.push(Pass {
+            kind: Kind::Horizontal,
+            x0: half,
+            y0: 0,
+            step_x: step,
+            step_y: step,
+            distance: half,
+            fill_width: half,
+            fill_height: step,
+        });
+        passes.push(Pass {
+            kind: Kind::Vertical,
+            x0: 0,
+            y0: half,
+            step_x: half,
+            step_y: step,
+            distance: half,
+            fill_width: half,
+            fill_height: half,
+        });
+    }
+    Ok(passes)
+}
+
+// memória usada pelos modelos, compartilhados pelas passadas do mesmo tipo
+pub fn memory_len(depths: &[u8]) -> u64 {
+    depths
+        .iter()
+        .map(|&depth| AdaptiveModel::memory_len(1 << depth).saturating_mul((KINDS * CONTEXTS) as u64))
+        .fold(0, u64::saturating_add)
+}
+
+// cada passada é gravada em um trecho próprio da região codificada, terminado com a última
+// palavra completa, para que possa ser decodificada sem os dados seguintes; os modelos
+// continuam se adaptando de uma passada para a outra. Retorna a posição do fim de cada trecho
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image, levels: u8) -> Result<Vec<u64>> {
+    let mut models = create_models(image, encoder.max_total())?;
+    let mut coded = image.clone();
+    let mut ends = Vec::new();
+    for pass in passes(levels)? {
+        code_pass(encoder, &mut models, &mut coded, Some(image), &pass)?;
+        ends.push(encoder.restart()?);
+    }
+    Ok(ends)
+}
+
+// decodifica as passadas cujos trechos estão inteiros em `data`, que pode ser apenas o início
+// da região codificada, e retorna quantas foram decodificadas; os pixels que faltam repetem o
+// pixel decodificado mais próximo acima e à esquerda, formando uma prévia do tamanho original
+pub fn decode(ac: &ArithmeticCoding, data: &[u8], ends: &[u64], image: &mut Image, levels: u8) -> Result<usize> {
+    let passes = passes(levels)?;
+    if ends.len() != passes.len() {
+        return Err(Error::InvalidContainer("índice de passadas inválido"));
+    }
+    ac.validate()?;
+    let mut models = create_models(image, ac.half_bit() as u64 - 1)?;
+    let mut start = 0;
+    for (index, pass) in passes.iter().enumerate() {
+        let end = ends[index];
+        if end > data.len() as u64 {
+            return Ok(index);
+        }
+        if start > end {
+            return Err(Error::InvalidContainer("índice de passadas inválido"));
+        }
+        let mut decoder = ArithmeticDecoder::new(ac.clone(), &data[start as usize..end as usize], 32)?;
+        code_pass(&mut decoder, &mut models, image, None, pass)?;
+        start = end;
+    }
+    Ok(passes.len())
+}
+
+fn create_models(image: &Image, max_total: u64) -> Result<Vec<Vec<AdaptiveModel>>> {
+    image
+        .planes
+        .iter()
+        .map(|plane| Ok(vec![AdaptiveModel::new(1 << plane.depth, max_total)?; KINDS * CONTEXTS]))
+        .collect()
+}
+
+// na decodificação, cada pixel decodificado também é copiado para o retângulo que representa
+fn code_pass<C: Coder>(coder: &mut C, models: &mut [Vec<AdaptiveModel>], image: &mut Image, source: Option<&Image>, pass: &Pass) -> Result<()> {
+    for y in (pass.y0..image.height).step_by(pass.step_y) {
+        for x in (pass.x0..image.width).step_by(pass.step_x) {
+            for (plane, models) in models.iter_mut().enumerate() {
+                let mask = image.planes[plane].max_value();
+                let (predicted, activity) = predict(image, plane, x, y, pass);
+                let context = pass.kind as usize * CONTEXTS + context(activity, image.planes[plane].depth);
+                let residual = source.map_or(0, |source| source.get(plane, x, y).wrapping_sub(predicted) & mask);
+                let residual = coder.code(&mut models[context], residual as u32)? as u16;
+                let value = predicted.wrapping_add(residual) & mask;
+                if source.is_none() {
+                    fill(image, plane, x, y, pass, value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// valor previsto e diferença absoluta entre os vizinhos usados
+fn predict(image: &Image, plane: usize, x: usize, y: usize, pass: &Pass) -> (u16, u32) {
+    let distance = pass.distance;
+    let get = |x: usize, y: usize| image.get(plane, x, y) as u32;
+    let (first, second) = match pass.kind {
+        Kind::Base => {
+            let left = (x >= distance).then(|| get(x - distance, y));
+            let up = (y >= distance).then(|| get(x, y - distance));
+            return match (left, up) {
+                (Some(left), Some(up)) => {
+                    let up_left = get(x - distance, y - distance);
+                    (med(left, up, up_left) as u16, left.abs_diff(up))
+                }
+                (Some(value), None) | (None, Some(value)) => (value as u16, 0),
+                (None, None) => (0, 0),
+            };
+        }
+        Kind::Horizontal => (get(x - distance, y), (x + distance < image.width).then(|| get(x + distance, y))),
+        Kind::Vertical => (get(x, y - distance), (y + distance < image.height).then(|| get(x, y + distance))),
+    };
+    match second {
+        Some(second) => ((first + second).div_ceil(2) as u16, first.abs_diff(second)),
+        None => (first as u16, 0),
+    }
+}
+
+fn context(activity: u32, depth: u8) -> usize {
+    let activity = if depth > 8 { activity >> (depth - 8) } else { activity << (8 - depth) };
+    THRESHOLDS.iter().filter(|&&threshold| activity >= threshold).count()
+}
+
+fn fill(image: &mut Image, plane: usize, x: usize, y: usize, pass: &Pass, value: u16) {
+    let width = image.width;
+    let right = (x + pass.fill_width).min(width);
+    let bottom = (y + pass.fill_height).min(image.height);
+    let samples = &mut image.planes[plane].samples;
+    for row in y..bottom {
+        samples[row * width + x..row * width + right].fill(value);
+    }
+}
+
+fn med(a: u32, b: u32, c: u32) -> u32 {
+    if c >= a.max(b) {
+        a.min(b)
+    } else if c <= a.min(b) {
+        a.max(b)
+    } else {
+        a + b - c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::limits::DecodeLimits;
+    use crate::testing::{
+        encode,
+        photo,
+    };
+
+    fn encode_passes(image: &Image, levels: u8) -> (Vec<u8>, Vec<u64>) {
+        let mut ends = Vec::new();
+        let encoded = encode(|encoder| {
+            ends = super::encode(encoder, image, levels)?;
+            Ok(())
+        });
+        (encoded, ends)
+    }
+
+    #[test]
+    fn every_prefix_decodes() {
+        let image = photo(19, 13, &[8, 8, 8]);
+        let (encoded, ends) = encode_passes(&image, DEFAULT_LEVELS);
+        assert_eq!(ends.len(), 2 * DEFAULT_LEVELS as usize + 1);
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+
+        let mut decoded = Image::new(19, 13, &image.depths()).unwrap();
+        let passes = decode(&container.ac, container.encoded_data, &ends, &mut decoded, DEFAULT_LEVELS).unwrap();
+        assert_eq!(passes, ends.len());
+        assert_eq!(decoded.planes, image.planes);
+
+        // só a grade inicial: os pixels dela são exatos e os demais os repetem
+        let mut preview = Image::new(19, 13, &image.depths()).unwrap();
+        let data = &container.encoded_data[..ends[0] as usize];
+        assert_eq!(decode(&container.ac, data, &ends, &mut preview, DEFAULT_LEVELS).unwrap(), 1);
+        assert_eq!(preview.get(1, 8, 8), image.get(1, 8, 8));
+        assert_eq!(preview.get(1, 11, 12), image.get(1, 8, 8));
+    }
+
+    #[test]
+    fn malformed_indexes_are_rejected() {
+        let image = photo(8, 8, &[8]);
+        let (encoded, ends) = encode_passes(&image, 1);
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let mut decoded = Image::new(8, 8, &[8]).unwrap();
+        let ac = &container.ac;
+        let data = container.encoded_data;
+        assert!(matches!(decode(ac, data, &ends[..2], &mut decoded, 1), Err(Error::InvalidContainer(_))));
+        assert!(matches!(decode(ac, data, &[ends[1], ends[0], ends[2]], &mut decoded, 1), Err(Error::InvalidContainer(_))));
+        assert!(matches!(decode(ac, data, &ends, &mut decoded, MAX_LEVELS + 1), Err(Error::UnsupportedImage(_))));
+    }
+}
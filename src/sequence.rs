@@ -0,0 +1,269 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Result;
+
+// quantidade de quadros entre dois quadros-chave, contando o próprio quadro-chave
+pub const DEFAULT_KEY_INTERVAL: u32 = 30;
+
+// lado dos blocos que recebem um vetor de movimento e maior deslocamento procurado
+pub const BLOCK_SIZE: usize = 16;
+pub const SEARCH_RANGE: i32 = 7;
+const VECTORS: usize = 2 * SEARCH_RANGE as usize + 1;
+
+// faixas de magnitude do resíduo anterior do mesmo canal (0, 1, 2, 3-4, 5-8, 9-16, 17-32,
+// 33 ou mais, em amostras de 8 bits)
+const CONTEXTS: usize = 8;
+
+// modelos de um grupo de quadros, do quadro-chave até o próximo: continuam se adaptando de
+// um quadro para o outro e são recriados em cada quadro-chave, que pode então ser decodificado
+// sem os quadros anteriores
+pub struct Models {
+    intra: Vec<Vec<AdaptiveModel>>,
+    inter: Vec<Vec<AdaptiveModel>>,
+    vector_x: AdaptiveModel,
+    vector_y: AdaptiveModel,
+}
+
+impl Models {
+    pub fn new(depths: &[u8], max_total: u64) -> Result<Self> {
+        let create = || -> Result<Vec<Vec<AdaptiveModel>>> {
+            depths
+                .iter()
+                .map(|&depth| Ok(vec![AdaptiveModel::new(1 << depth, max_total)?; CONTEXTS]))
+                .collect()
+        };
+        let intra = create()?;
+        let inter = create()?;
+        let vector_x = AdaptiveModel::new(VECTORS, max_total)?;
+        let vector_y = AdaptiveModel::new(VECTORS, max_total)?;
+        Ok(Self {
+            intra,
+            inter,
+            vector_x,
+            vector_y,
+        })
+    }
+
+    pub fn memory_len(depths: &[u8]) -> u64 {
+        depths
+            .iter()
+            .map(|&depth| AdaptiveModel::memory_len(1 << depth).saturating_mul(2 * CONTEXTS as u64))
+            .fold(0, u64::saturating_add)
+            .saturating_add(AdaptiveModel::memory_len(VECTORS).saturating_mul(2))
+    }
+}
+
+// sem `previous`, o quadro é um quadro-chave, previsto pela MED a partir dos vizinhos; nos
+// demais, cada amostra é prevista pela amostra do quadro anterior, deslocada pelo vetor de
+// movimento do bloco com `motion`
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, models: &mut Models, image: &Image, previous: Option<&Image>, motion: bool) -> Result<()> {
+    let mut coded = Image::new(image.width, image.height, &image.depths())?;
+    code_frame(encoder, models, &mut coded, Some(image), previous, motion)
+}
+
+// reconstrói os planos de `image`, que já deve ter as dimensões e profundidades gravadas
+pub fn decode(decoder: &mut ArithmeticDecoder, models: &mut Models, image: &mut Image, previous: Option<&Image>, motion: bool) -> Result<()> {
+    code_frame(decoder, models, image, None, previous, motion)
+}
+
+fn code_frame<C: Coder>(coder: &mut C, models: &mut Models, image: &mut Image, source: Option<&Image>, previous: Option<&Image>, motion: bool) -> Result<()> {
+    let mut last = vec![0u16; image.planes.len()];
+    let previous = match previous {
+        Some(previous) => previous,
+        None => {
+            for y in 0..image.height {
+                for x in 0..image.width {
+                    for (plane, models) in models.intra.iter_mut().enumerate() {
+                        let predicted = predict_med(image, plane, x, y);
+                        let model = &mut models[context(last[plane], image.planes[plane].depth)];
+                        let sample = source.map(|source| source.get(plane, x, y));
+                        let (value, residual) = code_sample(coder, model, image.planes[plane].max_value(), predicted, sample)?;
+                        image.set(plane, x, y, value);
+                        last[plane] = residual;
+                    }
+                }
+            }
+            return Ok(());
+        }
+    };
+
+    for block_y in (0..image.height).step_by(BLOCK_SIZE) {
+        for block_x in (0..image.width).step_by(BLOCK_SIZE) {
+            let bottom = (block_y + BLOCK_SIZE).min(image.height);
+            let right = (block_x + BLOCK_SIZE).min(image.width);
+            let (dx, dy) = if motion {
+                let (dx, dy) = source.map_or((0, 0), |source| search(source, previous, block_x, block_y, right, bottom));
+                let dx = coder.code(&mut models.vector_x, (dx + SEARCH_RANGE) as u32)? as i32 - SEARCH_RANGE;
+                let dy = coder.code(&mut models.vector_y, (dy + SEARCH_RANGE) as u32)? as i32 - SEARCH_RANGE;
+                (dx, dy)
+            } else {
+                (0, 0)
+            };
+            for y in block_y..bottom {
+                for x in block_x..right {
+                    for (plane, models) in models.inter.iter_mut().enumerate() {
+                        let predicted = displaced(previous, plane, x, y, dx, dy);
+                        let model = &mut models[context(last[plane], image.planes[plane].depth)];
+                        let sample = source.map(|source| source.get(plane, x, y));
+                        let (value, residual) = code_sample(coder, model, image.planes[plane].max_value(), predicted, sample)?;
+                        image.set(plane, x, y, value);
+                        last[plane] = residual;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// codifica o resíduo de uma amostra (módulo `mask` + 1); retorna o valor reconstruído e o resíduo
+fn code_sample<C: Coder>(coder: &mut C, model: &mut AdaptiveModel, mask: u16, predicted: u16, sample: Option<u16>) -> Result<(u16, u16)> {
+    let residual = sample.map_or(0, |sample| sample.wrapping_sub(predicted) & mask);
+    let residual = coder.code(model, residual as u32)? as u16;
+    Ok((predicted.wrapping_add(residual) & mask, residual))
+}
+
+fn context(residual: u16, depth: u8) -> usize {
+    let residual = residual as u32;
+    let magnitude = residual.min((1u32 << depth) - residual);
+    let magnitude = if depth > 8 { magnitude >> (depth - 8) } else { magnitude << (8 - depth) };
+    match magnitude {
+        0 => 0,
+        magnitude => ((u32::BITS - (magnitude - 1).leading_zeros()) as usize + 1).min(CONTEXTS - 1),
+    }
+}
+
+// amostra do quadro anterior deslocada, limitada às bordas da imagem
+fn displaced(previous: &Image, plane: usize, x: usize, y: usize, dx: i32, dy: i32) -> u16 {
+    let x = (x as i64 + dx as i64).clamp(0, previous.width as i64 - 1) as usize;
+    let y = (y as i64 + dy as i64).clamp(0, previous.height as i64 - 1) as usize;
+    previous.get(plane, x, y)
+}
+
+// vetor de movimento com a menor soma das diferenças absolutas no bloco, em todos os canais;
+// em caso de empate, vale o primeiro, começando pelo vetor nulo
+fn search(image: &Image, previous: &Image, block_x: usize, block_y: usize, right: usize, bottom: usize) -> (i32, i32) {
+    let cost = |dx: i32, dy: i32| -> u64 {
+        let mut cost = 0;
+        for plane in 0..image.planes.len() {
+            for y in block_y..bottom {
+                for x in block_x..right {
+                    cost += image.get(plane, x, y).abs_diff(displaced(previous, plane, x, y, dx, dy)) as u64;
+                }
+            }
+        }
+        cost
+    };
+    let mut best = (0, 0);
+    let mut best_cost = cost(0, 0);
+    for dy in -SEARCH_RANGE..=SEARCH_RANGE {
+        for dx in -SEARCH_RANGE..=SEARCH_RANGE {
+            if best_cost == 0 {
+                return best;
+            }
+            let cost = cost(dx, dy);
+            if cost < best_cost {
+                best = (dx, dy);
+                best_cost = cost;
+            }
+        }
+    }
+    best
+}
+
+fn predict_med(image: &Image, plane: usize, x: usize, y: usize) -> u16 {
+    match (x, y) {
+        (0, 0) => 0,
+        (_, 0) => image.get(plane, x - 1, y),
+        (0, _) => image.get(plane, x, y - 1),
+        _ => {
+            let a = image.get(plane, x - 1, y);
+            let b = image.get(plane, x, y - 1);
+            let c = image.get(plane, x - 1, y - 1);
+            if c >= a.max(b) {
+                a.min(b)
+            } else if c <= a.min(b) {
+                a.max(b)
+            } else {
+                a + b - c
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        photo,
+    };
+    use crate::Error;
+
+    // quadros deslocados alguns pixels em relação ao anterior
+    fn frames() -> Vec<Image> {
+        let scene = photo(40, 24, &[8, 8, 8]);
+        (0..3)
+            .map(|index| {
+                let mut frame = Image::new(32, 16, &scene.depths()).unwrap();
+                for plane in 0..3 {
+                    for y in 0..16 {
+                        for x in 0..32 {
+                            frame.set(plane, x, y, scene.get(plane, x + 3 * index, y + 2 * index));
+                        }
+                    }
+                }
+                frame
+            })
+            .collect()
+    }
+
+    fn round_trip(frames: &[Image], motion: bool) -> usize {
+        let depths = frames[0].depths();
+        let encoded = encode(|encoder| {
+            let mut models = Models::new(&depths, encoder.max_total())?;
+            for (index, frame) in frames.iter().enumerate() {
+                let previous = index.checked_sub(1).map(|previous| &frames[previous]);
+                super::encode(encoder, &mut models, frame, previous, motion)?;
+            }
+            Ok(())
+        });
+        let decoded = decode(&encoded, |decoder| {
+            let mut models = Models::new(&depths, decoder.max_total())?;
+            let mut decoded: Vec<Image> = Vec::new();
+            for _ in frames {
+                let mut frame = Image::new(32, 16, &depths)?;
+                super::decode(decoder, &mut models, &mut frame, decoded.last(), motion)?;
+                decoded.push(frame);
+            }
+            Ok(decoded)
+        })
+        .unwrap();
+        for (decoded, frame) in decoded.iter().zip(frames) {
+            assert_eq!(decoded.planes, frame.planes);
+        }
+        encoded.len()
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let frames = frames();
+        let without = round_trip(&frames, false);
+        let with = round_trip(&frames, true);
+        // os vetores de movimento acompanham o deslocamento da cena
+        assert!(with < without, "{} {}", with, without);
+    }
+
+    #[test]
+    fn low_precision_is_rejected() {
+        assert!(matches!(Models::new(&[16], 1 << 16), Err(Error::LowPrecision)));
+    }
+}
@@ -0,0 +1,151 @@
+// rotinas e dados de exemplo compartilhados pelos testes dos módulos
+
+use crate::container::Container;
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::limits::DecodeLimits;
+use crate::Result;
+
+// intervalo com a maior precisão aceita pela linha de comando
+pub const LOW: u32 = 0;
+pub const HIGH: u32 = 2147483647;
+
+// gerador pseudoaleatório xorshift, para dados de teste reproduzíveis
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+// região codificada, estrutura de dados e final gravados por `code`, sem o cabeçalho
+pub fn encode(code: impl FnOnce(&mut ArithmeticEncoder<&mut Vec<u8>>) -> Result<()>) -> Vec<u8> {
+    let mut output = Vec::new();
+    {
+        let mut encoder = ArithmeticEncoder::new(LOW, HIGH, &mut output).unwrap();
+        code(&mut encoder).unwrap();
+        encoder.finish().unwrap();
+    }
+    output
+}
+
+pub fn decode<T>(encoded: &[u8], code: impl FnOnce(&mut ArithmeticDecoder) -> Result<T>) -> Result<T> {
+    let container = Container::parse(encoded, &DecodeLimits::new())?;
+    let mut decoder = ArithmeticDecoder::new(container.ac, container.encoded_data, container.last_value_shifts)?;
+    code(&mut decoder)
+}
+
+// gradientes suaves com um pouco de ruído, como uma fotografia
+pub fn photo(width: usize, height: usize, depths: &[u8]) -> Image {
+    let mut image = Image::new(width, height, depths).unwrap();
+    let mut rng = Rng(0x2545F491);
+    for (index, plane) in image.planes.iter_mut().enumerate() {
+        let max = plane.max_value() as u64;
+        for y in 0..height {
+            for x in 0..width {
+                let value = (x * 3 + y * 2 + index * 40) as u64 * (max + 1) / (5 * (width + height) as u64 + 120);
+                let noise = rng.below(3);
+                plane.samples[y * width + x] = (value + noise).min(max) as u16;
+            }
+        }
+    }
+    image
+}
+
+// BMP de 24 bits, de baixo para cima, com as amostras de `photo`
+pub fn bmp(width: usize, height: usize) -> Vec<u8> {
+    let image = photo(width, height, &[8, 8, 8]);
+    let row_size = (width * 3).div_ceil(4) * 4;
+    let mut bytes = bmp_header(width, height, 24, row_size * height, &[]);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            for plane in 0..3 {
+                bytes.push(image.get(plane, x, y) as u8);
+            }
+        }
+        bytes.resize(bytes.len() + row_size - width * 3, 0);
+    }
+    bytes
+}
+
+// BMP de 1 bit por pixel com um círculo preto sobre fundo branco
+pub fn bilevel_bmp(width: usize, height: usize) -> Vec<u8> {
+    let row_size = width.div_ceil(32) * 4;
+    let mut bytes = bmp_header(width, height, 1, row_size * height, &[[0, 0, 0], [255, 255, 255]]);
+    for y in (0..height).rev() {
+        let mut row = vec![0u8; row_size];
+        for x in 0..width {
+            let (dx, dy) = (x as i64 - width as i64 / 2, y as i64 - height as i64 / 2);
+            if dx * dx + dy * dy > (width.min(height) as i64 / 3).pow(2) {
+                row[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        bytes.extend_from_slice(&row);
+    }
+    bytes
+}
+
+// cabeçalhos BITMAPFILEHEADER e BITMAPINFOHEADER seguidos da paleta
+pub fn bmp_header(width: usize, height: usize, bits: u16, pixel_len: usize, palette: &[[u8; 3]]) -> Vec<u8> {
+    let pixel_offset = 54 + 4 * palette.len();
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"BM");
+    bytes.extend_from_slice(&((pixel_offset + pixel_len) as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(pixel_offset as u32).to_le_bytes());
+    bytes.extend_from_slice(&40u32.to_le_bytes());
+    bytes.extend_from_slice(&(width as i32).to_le_bytes());
+    bytes.extend_from_slice(&(height as i32).to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&bits.to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(pixel_len as u32).to_le_bytes());
+    bytes.extend_from_slice(&[0; 8]);
+    bytes.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    for &[red, green, blue] in palette {
+        bytes.extend_from_slice(&[blue, green, red, 0]);
+    }
+    bytes
+}
+
+// WAV PCM de 16 bits com uma senoide em cada canal
+pub fn wav(channels: u16, frames: usize) -> Vec<u8> {
+    let data_len = frames * channels as usize * 2;
+    let mut bytes = wav_header(channels, 16, data_len);
+    for frame in 0..frames {
+        for channel in 0..channels {
+            let sample = ((frame as f64 * 0.05 + channel as f64).sin() * 8000.0) as i16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+    bytes
+}
+
+// chunks RIFF, "fmt " e o cabeçalho do chunk de dados
+pub fn wav_header(channels: u16, bits: u16, data_len: usize) -> Vec<u8> {
+    let block_align = channels * bits / 8;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&channels.to_le_bytes());
+    bytes.extend_from_slice(&44100u32.to_le_bytes());
+    bytes.extend_from_slice(&(44100 * block_align as u32).to_le_bytes());
+    bytes.extend_from_slice(&block_align.to_le_bytes());
+    bytes.extend_from_slice(&bits.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(data_len as u32).to_le_bytes());
+    bytes
+}
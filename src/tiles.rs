@@ -0,0 +1,192 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::predictive;
+use crate::predictive::ImageOptions;
+use crate::ArithmeticCoding;
+use crate::Error;
+use crate::Result;
+
+pub const DEFAULT_TILE_SIZE: u32 = 256;
+
+// retângulo em pixels, com a origem no canto superior esquerdo da imagem
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // interseção com outro retângulo, se não for vazia
+    pub fn intersection(&self, other: &Region) -> Option<Region> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        (x < right && y < bottom).then(|| Region::new(x, y, right - x, bottom - y))
+    }
+}
+
+// blocos de `size` x `size` pixels, linha a linha; os da última coluna e da última linha
+// podem ser menores
+pub fn tiles(width: usize, height: usize, size: u32) -> Result<Vec<Region>> {
+    let mut tiles = Vec::with_capacity(count(width, height, size)?);
+    let size = size as usize;
+    for y in (0..height).step_by(size) {
+        for x in (0..width).step_by(size) {
+            tiles.push(Region::new(x, y, size.min(width - x), size.min(height - y)));
+        }
+    }
+    Ok(tiles)
+}
+
+pub fn count(width: usize, height: usize, size: u32) -> Result<usize> {
+    if size == 0 {
+        return Err(Error::UnsupportedImage("tamanho de bloco inválido"));
+    }
+    let size = size as usize;
+    Ok(width.div_ceil(size) * height.div_ceil(size))
+}
+
+// cada bloco é codificado pelo modo image com modelos novos, em um trecho próprio da região
+// codificada; retorna a posição de cada trecho, o índice dos blocos
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image, size: u32, options: &ImageOptions) -> Result<Vec<u64>> {
+    let tiles = tiles(image.width, image.height, size)?;
+    let mut offsets = Vec::with_capacity(tiles.len());
+    for (index, tile) in tiles.iter().enumerate() {
+        offsets.push(if index == 0 { 0 } else { encoder.restart()? });
+        predictive::encode(encoder, &crop(image, tile)?, options)?;
+    }
+    Ok(offsets)
+}
+
+// região codificada no modo com blocos, dividida em um trecho por bloco; no modo sequence,
+// em um trecho por quadro-chave
+pub struct Segments<'a> {
+    pub ac: &'a ArithmeticCoding,
+    pub data: &'a [u8],
+    // posição do início de cada trecho, gravada no cabeçalho
+    pub offsets: &'a [u64],
+    // bits válidos da última palavra do último trecho; os demais são completados com zeros
+    pub last_value_shifts: u32,
+}
+
+impl<'a> Segments<'a> {
+    pub fn decoder(&self, index: usize) -> Result<ArithmeticDecoder<'a>> {
+        let start = self.offsets[index];
+        let (end, shifts) = match self.offsets.get(index + 1) {
+            Some(&end) => (end, 32),
+            None => (self.data.len() as u64, self.last_value_shifts),
+        };
+        if start > end || end > self.data.len() as u64 {
+            return Err(Error::InvalidContainer("índice de blocos inválido"));
+        }
+        ArithmeticDecoder::new(self.ac.clone(), &self.data[start as usize..end as usize], shifts)
+    }
+}
+
+// decodifica apenas os blocos que cruzam `region` e devolve os planos do recorte; `depths` são
+// as profundidades dos planos codificados
+pub fn decode(segments: &Segments, width: usize, height: usize, depths: &[u8], size: u32, options: &ImageOptions, region: &Region) -> Result<Image> {
+    // o índice é conferido antes de alocar a lista de blocos, cujo tamanho vem do cabeçalho
+    if segments.offsets.len() != count(width, height, size)? || segments.offsets.first() != Some(&0) {
+        return Err(Error::InvalidContainer("índice de blocos inválido"));
+    }
+    let tiles = tiles(width, height, size)?;
+    let mut output = Image::new(region.width, region.height, depths)?;
+
+    for (index, tile) in tiles.iter().enumerate() {
+        let part = match tile.intersection(region) {
+            Some(part) => part,
+            None => continue,
+        };
+        let mut decoder = segments.decoder(index)?;
+        let mut image = Image::new(tile.width, tile.height, depths)?;
+        predictive::decode(&mut decoder, &mut image, options)?;
+
+        for (target, source) in output.planes.iter_mut().zip(image.planes.iter()) {
+            for y in part.y..part.y + part.height {
+                let from = (y - tile.y) * tile.width + part.x - tile.x;
+                let to = (y - region.y) * region.width + part.x - region.x;
+                target.samples[to..to + part.width].copy_from_slice(&source.samples[from..from + part.width]);
+            }
+        }
+    }
+    Ok(output)
+}
+
+// planos de um retângulo de `image`
+pub fn crop(image: &Image, region: &Region) -> Result<Image> {
+    let mut output = Image::new(region.width, region.height, &image.depths())?;
+    for (target, source) in output.planes.iter_mut().zip(image.planes.iter()) {
+        for y in 0..region.height {
+            let from = (region.y + y) * image.width + region.x;
+            target.samples[y * region.width..(y + 1) * region.width].copy_from_slice(&source.samples[from..from + region.width]);
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::Container;
+    use crate::limits::DecodeLimits;
+    use crate::testing::{
+        encode,
+        photo,
+    };
+
+    #[test]
+    fn tiles_cover_the_image() {
+        let tiles = tiles(10, 5, 4).unwrap();
+        assert_eq!((tiles.len(), count(10, 5, 4).unwrap()), (6, 6));
+        assert_eq!((tiles[2], tiles[5]), (Region::new(8, 0, 2, 4), Region::new(8, 4, 2, 1)));
+        assert!(matches!(count(10, 5, 0), Err(Error::UnsupportedImage(_))));
+        assert_eq!(Region::new(0, 0, 4, 4).intersection(&Region::new(3, 2, 5, 5)), Some(Region::new(3, 2, 1, 2)));
+        assert_eq!(Region::new(0, 0, 4, 4).intersection(&Region::new(4, 0, 1, 1)), None);
+    }
+
+    #[test]
+    fn regions_decode_only_their_tiles() {
+        let image = photo(11, 9, &[8, 5]);
+        let options = ImageOptions::default();
+        let mut offsets = Vec::new();
+        let encoded = encode(|encoder| {
+            offsets = super::encode(encoder, &image, 4, &options)?;
+            Ok(())
+        });
+        assert_eq!(offsets.len(), 9);
+        let container = Container::parse(&encoded, &DecodeLimits::new()).unwrap();
+        let segments = Segments {
+            ac: &container.ac,
+            data: container.encoded_data,
+            offsets: &offsets,
+            last_value_shifts: container.last_value_shifts,
+        };
+        for region in [Region::new(0, 0, 11, 9), Region::new(3, 5, 6, 2)] {
+            let decoded = decode(&segments, 11, 9, &image.depths(), 4, &options, &region).unwrap();
+            assert_eq!(decoded.planes, crop(&image, &region).unwrap().planes);
+        }
+        // um índice com outra quantidade de blocos é rejeitado antes de qualquer decodificação
+        let segments = Segments {
+            offsets: &offsets[..6],
+            ..segments
+        };
+        let result = decode(&segments, 11, 9, &image.depths(), 4, &options, &Region::new(0, 0, 4, 4));
+        assert!(matches!(result, Err(Error::InvalidContainer(_))));
+    }
+}
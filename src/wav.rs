@@ -0,0 +1,255 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::bmp::read_u16;
+use crate::bmp::read_u32;
+use crate::Error;
+use crate::Result;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// formato das amostras de um arquivo WAV PCM, lido dos chunks anteriores às amostras
+#[derive(Debug, Clone)]
+pub struct Wav {
+    pub channels: usize,
+    pub bits: u16,
+    // início das amostras, logo após o cabeçalho do chunk de dados
+    pub data_offset: usize,
+    // tamanho declarado do chunk de dados, que pode passar do fim do arquivo
+    pub data_len: u32,
+}
+
+// bytes de um WAV que não são amostras, suficientes para reconstruir o arquivo original a
+// partir dos canais
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WavShell {
+    // chunks até o cabeçalho do chunk de dados, inclusive
+    pub header: Vec<u8>,
+    // bytes após o último quadro completo: um quadro incompleto, o byte de alinhamento do
+    // chunk de dados e os chunks seguintes
+    pub trailer: Vec<u8>,
+}
+
+impl Wav {
+    // lê os chunks até o início das amostras; o chunk "fmt " deve vir antes do chunk de dados
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if !is_wav(bytes) {
+            return Err(Error::InvalidAudio("cabeçalho RIFF/WAVE ausente"));
+        }
+        let mut format = None;
+        let mut position = 12;
+        while let Some(chunk) = bytes.get(position..position + 8) {
+            let size = read_u32(chunk, 4);
+            let start = position + 8;
+            match &chunk[..4] {
+                b"fmt " => {
+                    let fields = match bytes.get(start..start.saturating_add(size as usize)) {
+                        Some(fields) if fields.len() >= 16 => fields,
+                        _ => return Err(Error::InvalidAudio("chunk de formato incompleto")),
+                    };
+                    format = Some(read_format(fields)?);
+                }
+                b"data" => {
+                    let (channels, bits) = match format {
+                        Some(format) => format,
+                        None => return Err(Error::InvalidAudio("chunk de dados antes do chunk de formato")),
+                    };
+                    return Ok(Self {
+                        channels,
+                        bits,
+                        data_offset: start,
+                        data_len: size,
+                    });
+                }
+                _ => {}
+            }
+            // os chunks de tamanho ímpar são seguidos de um byte de alinhamento
+            position = match start.checked_add(size as usize + (size & 1) as usize) {
+                Some(position) => position,
+                None => break,
+            };
+        }
+        Err(Error::InvalidAudio("chunk de dados ausente"))
+    }
+
+    pub fn sample_len(&self) -> usize {
+        self.bits as usize / 8
+    }
+
+    // bytes de um quadro, com uma amostra de cada canal
+    pub fn frame_len(&self) -> usize {
+        self.channels * self.sample_len()
+    }
+
+    // converte as amostras intercaladas em um vetor por canal; as de 8 bits, sem sinal no
+    // arquivo, são centradas em zero
+    pub fn read_samples(&self, data: &[u8]) -> Vec<Vec<i32>> {
+        let frames = data.len() / self.frame_len();
+        let mut channels = vec![Vec::with_capacity(frames); self.channels];
+        for (index, sample) in data.chunks_exact(self.sample_len()).take(frames * self.channels).enumerate() {
+            let value = match *sample {
+                [value] => value as i32 - 128,
+                [low, high] => i16::from_le_bytes([low, high]) as i32,
+                [low, middle, high] => i32::from_le_bytes([0, low, middle, high]) >> 8,
+                _ => unreachable!(),
+            };
+            channels[index % self.channels].push(value);
+        }
+        channels
+    }
+
+    pub fn write_samples(&self, channels: &[Vec<i32>]) -> Result<Vec<u8>> {
+        let frames = channels.first().map_or(0, Vec::len);
+        if channels.len() != self.channels || channels.iter().any(|channel| channel.len() != frames) {
+            return Err(Error::InvalidAudio("canais incompatíveis com o cabeçalho WAV"));
+        }
+        let mut data = Vec::with_capacity(frames * self.frame_len());
+        for frame in 0..frames {
+            for channel in channels {
+                let value = channel[frame];
+                match self.bits {
+                    8 => data.push((value + 128) as u8),
+                    16 => data.extend_from_slice(&(value as i16).to_le_bytes()),
+                    _ => data.extend_from_slice(&value.to_le_bytes()[..3]),
+                }
+            }
+        }
+        Ok(data)
+    }
+}
+
+pub fn is_wav(bytes: &[u8]) -> bool {
+    bytes.get(..4) == Some(b"RIFF") && bytes.get(8..12) == Some(b"WAVE")
+}
+
+// separa os canais do resto do arquivo; um chunk de dados declarado maior que o arquivo vale
+// até o fim dele
+pub fn split(bytes: &[u8]) -> Result<(Wav, WavShell, Vec<Vec<i32>>)> {
+    let wav = Wav::parse(bytes)?;
+    let available = (bytes.len() - wav.data_offset).min(wav.data_len as usize);
+    let end = wav.data_offset + available / wav.frame_len() * wav.frame_len();
+    let channels = wav.read_samples(&bytes[wav.data_offset..end]);
+    let shell = WavShell {
+        header: bytes[..wav.data_offset].to_vec(),
+        trailer: bytes[end..].to_vec(),
+    };
+    Ok((wav, shell, channels))
+}
+
+// canais e bits por amostra do chunk "fmt ", em PCM ou WAVE_FORMAT_EXTENSIBLE com subformato PCM
+fn read_format(fields: &[u8]) -> Result<(usize, u16)> {
+    let format_tag = read_u16(fields, 0);
+    let pcm = match format_tag {
+        WAVE_FORMAT_PCM => true,
+        WAVE_FORMAT_EXTENSIBLE => fields.len() >= 26 && read_u16(fields, 24) == WAVE_FORMAT_PCM,
+        _ => false,
+    };
+    if !pcm {
+        return Err(Error::UnsupportedAudio("apenas amostras PCM são suportadas"));
+    }
+    let channels = read_u16(fields, 2) as usize;
+    let block_align = read_u16(fields, 12) as usize;
+    let bits = read_u16(fields, 14);
+    if !matches!(channels, 1 | 2) {
+        return Err(Error::UnsupportedAudio("apenas arquivos mono e estéreo são suportados"));
+    }
+    if !matches!(bits, 8 | 16 | 24) {
+        return Err(Error::UnsupportedAudio("apenas amostras de 8, 16 e 24 bits são suportadas"));
+    }
+    if block_align != channels * bits as usize / 8 {
+        return Err(Error::InvalidAudio("alinhamento de quadro inválido"));
+    }
+    Ok((channels, bits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        wav,
+        wav_header,
+    };
+
+    fn join(wav: &Wav, shell: &WavShell, channels: &[Vec<i32>]) -> Vec<u8> {
+        let mut bytes = shell.header.clone();
+        bytes.extend_from_slice(&wav.write_samples(channels).unwrap());
+        bytes.extend_from_slice(&shell.trailer);
+        bytes
+    }
+
+    #[test]
+    fn pcm_round_trip() {
+        let bytes = wav(2, 100);
+        let (format, shell, channels) = split(&bytes).unwrap();
+        assert_eq!((format.channels, format.bits), (2, 16));
+        assert_eq!(channels[1].len(), 100);
+        assert_eq!(join(&format, &shell, &channels), bytes);
+
+        for bits in [8, 24] {
+            let mut bytes = wav_header(1, bits, 7 * bits as usize / 8);
+            bytes.extend((0..7 * bits / 8).map(|index| (index * 37) as u8));
+            let (format, shell, channels) = split(&bytes).unwrap();
+            assert_eq!(channels[0].len(), 7);
+            assert_eq!(join(&format, &shell, &channels), bytes);
+        }
+    }
+
+    // chunks desconhecidos, de tamanho ímpar, antes e depois dos dados, e um quadro incompleto
+    #[test]
+    fn extra_chunks_round_trip() {
+        let header = wav_header(2, 16, 9);
+        let mut bytes = header[..12].to_vec();
+        bytes.extend_from_slice(b"LIST\x03\0\0\0abc\0");
+        bytes.extend_from_slice(&header[12..]);
+        bytes.extend_from_slice(&[1, 0, 2, 0, 3, 0, 4, 0, 5, 0]);
+        bytes.extend_from_slice(b"id3 \x01\0\0\0x\0");
+        let (format, shell, channels) = split(&bytes).unwrap();
+        assert_eq!(channels, vec![vec![1, 3], vec![2, 4]]);
+        assert_eq!(shell.trailer[..2], [5, 0]);
+        assert_eq!(join(&format, &shell, &channels), bytes);
+    }
+
+    #[test]
+    fn extensible_pcm_is_accepted() {
+        let mut bytes = wav_header(1, 16, 2);
+        bytes[16..20].copy_from_slice(&40u32.to_le_bytes());
+        bytes[20..22].copy_from_slice(&WAVE_FORMAT_EXTENSIBLE.to_le_bytes());
+        let mut extension = vec![22, 0, 16, 0, 4, 0, 0, 0];
+        extension.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        extension.extend_from_slice(&[0; 14]);
+        bytes.splice(36..36, extension);
+        bytes.extend_from_slice(&[0x34, 0x12]);
+        let (_, _, channels) = split(&bytes).unwrap();
+        assert_eq!(channels, vec![vec![0x1234]]);
+    }
+
+    #[test]
+    fn unsupported_and_malformed_files_are_rejected() {
+        assert!(matches!(split(b"RIFF\0\0\0\0WAVX"), Err(Error::InvalidAudio(_))));
+        assert!(matches!(split(b"RIFF\0\0\0\0WAVE"), Err(Error::InvalidAudio(_))));
+
+        let mut float = wav_header(1, 32, 0);
+        float[20..22].copy_from_slice(&3u16.to_le_bytes());
+        assert!(matches!(split(&float), Err(Error::UnsupportedAudio(_))));
+        assert!(matches!(split(&wav_header(1, 32, 0)), Err(Error::UnsupportedAudio(_))));
+        assert!(matches!(split(&wav_header(3, 16, 0)), Err(Error::UnsupportedAudio(_))));
+
+        let mut misaligned = wav_header(2, 16, 0);
+        misaligned[32..34].copy_from_slice(&2u16.to_le_bytes());
+        assert!(matches!(split(&misaligned), Err(Error::InvalidAudio(_))));
+
+        // o chunk de dados antes do chunk de formato
+        let header = wav_header(1, 16, 0);
+        let mut data_first = header[..12].to_vec();
+        data_first.extend_from_slice(b"data\0\0\0\0");
+        data_first.extend_from_slice(&header[12..36]);
+        assert!(matches!(split(&data_first), Err(Error::InvalidAudio(_))));
+
+        let mut short_format = wav_header(1, 16, 0);
+        short_format[16..20].copy_from_slice(&8u32.to_le_bytes());
+        assert!(matches!(split(&short_format), Err(Error::InvalidAudio(_))));
+    }
+}
@@ -0,0 +1,333 @@
+use std::io::Write;
+
+use crate::decoder::ArithmeticDecoder;
+use crate::encoder::ArithmeticEncoder;
+use crate::image::Image;
+use crate::model::{
+    AdaptiveModel,
+    Coder,
+};
+use crate::Error;
+use crate::Result;
+
+pub const DEFAULT_LEVELS: u8 = 5;
+pub const MAX_LEVELS: u8 = 10;
+
+// categorias (quantidade de bits do módulo) dos coeficientes e contextos formados pelas
+// categorias dos vizinhos à esquerda e acima na mesma sub-banda
+const CATEGORIES: usize = 40;
+const CONTEXTS: usize = 16;
+
+// módulo máximo de um coeficiente: cada passo 1D no máximo dobra o das amostras de 16 bits,
+// então arquivos válidos ficam bem abaixo disso, e arquivos corrompidos não estouram o i64
+const COEFFICIENT_LIMIT: i64 = 1 << 40;
+
+// modelos de uma sub-banda de um plano
+struct Band {
+    categories: Vec<AdaptiveModel>,
+    bits: AdaptiveModel,
+}
+
+impl Band {
+    fn new(max_total: u64) -> Result<Self> {
+        Ok(Self {
+            categories: vec![AdaptiveModel::new(CATEGORIES, max_total)?; CONTEXTS],
+            bits: AdaptiveModel::new(2, max_total)?,
+        })
+    }
+}
+
+// região de uma sub-banda dentro dos coeficientes de um plano
+struct Region {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+// dimensões da banda LL após cada nível de decomposição, a começar pela imagem
+pub fn dimensions(width: usize, height: usize, levels: u8) -> Vec<(usize, usize)> {
+    let mut dimensions = vec![(width, height)];
+    for _ in 0..levels {
+        let &(width, height) = dimensions.last().unwrap();
+        dimensions.push((width.div_ceil(2), height.div_ceil(2)));
+    }
+    dimensions
+}
+
+fn validate_levels(levels: u8, skip: u8) -> Result<()> {
+    if levels > MAX_LEVELS || skip > levels {
+        return Err(Error::UnsupportedImage("quantidade de níveis da wavelet inválida"));
+    }
+    Ok(())
+}
+
+// memória usada pelos coeficientes de uma imagem de `width` x `height` com estes planos
+pub fn memory_len(width: usize, height: usize, planes: usize) -> u64 {
+    (width as u64)
+        .saturating_mul(height as u64)
+        .saturating_mul(planes as u64)
+        .saturating_mul(size_of::<i64>() as u64)
+        .saturating_add(AdaptiveModel::memory_len(CATEGORIES) * CONTEXTS as u64)
+}
+
+pub fn encode<W: Write>(encoder: &mut ArithmeticEncoder<W>, image: &Image, levels: u8) -> Result<()> {
+    validate_levels(levels, 0)?;
+    let dimensions = dimensions(image.width, image.height, levels);
+    let mut planes: Vec<Vec<i64>> = image
+        .planes
+        .iter()
+        .map(|plane| plane.samples.iter().map(|&sample| sample as i64).collect())
+        .collect();
+    for plane in planes.iter_mut() {
+        for &(width, height) in dimensions[..levels as usize].iter() {
+            forward_2d(plane, image.width, width, height);
+        }
+    }
+    code_planes(encoder, &mut planes, &dimensions, 0)
+}
+
+// decodifica os planos; com `skip` maior que zero, os `skip` níveis mais finos não são lidos
+// e o resultado é a banda LL correspondente, uma prévia com 1/2^skip das dimensões
+pub fn decode(decoder: &mut ArithmeticDecoder, width: usize, height: usize, depths: &[u8], levels: u8, skip: u8) -> Result<Image> {
+    validate_levels(levels, skip)?;
+    let dimensions = dimensions(width, height, levels);
+    let (output_width, output_height) = dimensions[skip as usize];
+    let mut planes = vec![vec![0i64; output_width * output_height]; depths.len()];
+    code_planes(decoder, &mut planes, &dimensions, skip)?;
+
+    let mut image = Image::new(output_width, output_height, depths)?;
+    for (plane, coefficients) in image.planes.iter_mut().zip(planes.iter_mut()) {
+        for &(width, height) in dimensions[skip as usize..levels as usize].iter().rev() {
+            inverse_2d(coefficients, output_width, width, height);
+        }
+        let max = plane.max_value() as i64;
+        for (sample, &coefficient) in plane.samples.iter_mut().zip(coefficients.iter()) {
+            *sample = coefficient.clamp(0, max) as u16;
+        }
+    }
+    Ok(image)
+}
+
+// as sub-bandas são gravadas da mais grossa para a mais fina, todos os planos em cada nível,
+// para que a leitura possa parar após os níveis grossos
+fn code_planes<C: Coder>(coder: &mut C, planes: &mut [Vec<i64>], dimensions: &[(usize, usize)], skip: u8) -> Result<()> {
+    let levels = dimensions.len() - 1;
+    let stride = dimensions[skip as usize].0;
+    let max_total = coder.max_total();
+
+    let (width, height) = dimensions[levels];
+    for plane in planes.iter_mut() {
+        let region = Region {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        code_band(coder, &mut Band::new(max_total)?, plane, stride, &region, true)?;
+    }
+
+    for level in (skip as usize + 1..=levels).rev() {
+        let (width, height) = dimensions[level - 1];
+        let (low_width, low_height) = dimensions[level];
+        let regions = [
+            Region { x: low_width, y: 0, width: width - low_width, height: low_height },
+            Region { x: 0, y: low_height, width: low_width, height: height - low_height },
+            Region { x: low_width, y: low_height, width: width - low_width, height: height - low_height },
+        ];
+        for plane in planes.iter_mut() {
+            for region in regions.iter() {
+                code_band(coder, &mut Band::new(max_total)?, plane, stride, region, false)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// percorre uma sub-banda nos dois sentidos; na banda LL, codifica o resíduo da predição MED
+fn code_band<C: Coder>(coder: &mut C, band: &mut Band, plane: &mut [i64], stride: usize, region: &Region, predicted: bool) -> Result<()> {
+    let mut categories = vec![0usize; region.width * region.height];
+    for y in 0..region.height {
+        for x in 0..region.width {
+            let index = (region.y + y) * stride + region.x + x;
+            let prediction = if predicted {
+                let sample = |x: usize, y: usize| plane[(region.y + y) * stride + region.x + x];
+                match (x > 0, y > 0) {
+                    (true, true) => med(sample(x - 1, y), sample(x, y - 1), sample(x - 1, y - 1)),
+                    (true, false) => sample(x - 1, y),
+                    (false, true) => sample(x, y - 1),
+                    (false, false) => 0,
+                }
+            } else {
+                0
+            };
+
+            let left = if x > 0 { categories[y * region.width + x - 1] } else { 0 };
+            let up = if y > 0 { categories[(y - 1) * region.width + x] } else { 0 };
+            let context = (left + up).div_ceil(2).min(CONTEXTS - 1);
+
+            let value = code_value(coder, band, context, plane[index] - prediction)?;
+            plane[index] = (prediction + value).clamp(-COEFFICIENT_LIMIT, COEFFICIENT_LIMIT);
+            categories[y * region.width + x] = category(value) as usize;
+        }
+    }
+    Ok(())
+}
+
+// categoria, sinal e os bits do módulo abaixo do bit mais significativo
+fn code_value<C: Coder>(coder: &mut C, band: &mut Band, context: usize, value: i64) -> Result<i64> {
+    let size = coder.code(&mut band.categories[context], category(value))?;
+    if size == 0 {
+        return Ok(0);
+    }
+    let negative = coder.code(&mut band.bits, (value < 0) as u32)? == 1;
+    let magnitude = value.unsigned_abs();
+    let mut output: u64 = 1;
+    for shift in (0..size - 1).rev() {
+        let bit = coder.code(&mut band.bits, ((magnitude >> shift) & 1) as u32)?;
+        output = (output << 1) | bit as u64;
+    }
+    let output = output as i64;
+    Ok(if negative { -output } else { output })
+}
+
+fn category(value: i64) -> u32 {
+    (u64::BITS - value.unsigned_abs().leading_zeros()).min(CATEGORIES as u32 - 1)
+}
+
+fn med(a: i64, b: i64, c: i64) -> i64 {
+    if c >= a.max(b) {
+        a.min(b)
+    } else if c <= a.min(b) {
+        a.max(b)
+    } else {
+        a + b - c
+    }
+}
+
+// um nível da transformada 2D sobre a banda LL de `width` x `height`: linhas e depois
+// colunas, com as baixas frequências à esquerda (acima) e as altas à direita (abaixo)
+fn forward_2d(plane: &mut [i64], stride: usize, width: usize, height: usize) {
+    let mut line = Vec::with_capacity(width.max(height));
+    for y in 0..height {
+        line.clear();
+        line.extend((0..width).map(|x| plane[y * stride + x]));
+        forward_1d(&mut line);
+        for (x, &value) in line.iter().enumerate() {
+            plane[y * stride + x] = value;
+        }
+    }
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| plane[y * stride + x]));
+        forward_1d(&mut line);
+        for (y, &value) in line.iter().enumerate() {
+            plane[y * stride + x] = value;
+        }
+    }
+}
+
+fn inverse_2d(plane: &mut [i64], stride: usize, width: usize, height: usize) {
+    let mut line = Vec::with_capacity(width.max(height));
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| plane[y * stride + x]));
+        inverse_1d(&mut line);
+        for (y, &value) in line.iter().enumerate() {
+            plane[y * stride + x] = value;
+        }
+    }
+    for y in 0..height {
+        line.clear();
+        line.extend((0..width).map(|x| plane[y * stride + x]));
+        inverse_1d(&mut line);
+        for (x, &value) in line.iter().enumerate() {
+            plane[y * stride + x] = value;
+        }
+    }
+}
+
+// LeGall 5/3 reversível por lifting, com extensão simétrica nas bordas; o resultado tem as
+// amostras de baixa frequência seguidas das de alta frequência
+fn forward_1d(line: &mut [i64]) {
+    let len = line.len();
+    if len < 2 {
+        return;
+    }
+    let low_len = len.div_ceil(2);
+    let mut low = vec![0i64; low_len];
+    let mut high = vec![0i64; len / 2];
+    for (index, value) in high.iter_mut().enumerate() {
+        let left = line[2 * index];
+        let right = if 2 * index + 2 < len { line[2 * index + 2] } else { left };
+        *value = line[2 * index + 1] - ((left + right) >> 1);
+    }
+    for (index, value) in low.iter_mut().enumerate() {
+        let (left, right) = high_neighbours(&high, index);
+        *value = line[2 * index] + ((left + right + 2) >> 2);
+    }
+    line[..low_len].copy_from_slice(&low);
+    line[low_len..].copy_from_slice(&high);
+}
+
+fn inverse_1d(line: &mut [i64]) {
+    let len = line.len();
+    if len < 2 {
+        return;
+    }
+    let low_len = len.div_ceil(2);
+    let low = line[..low_len].to_vec();
+    let high = line[low_len..].to_vec();
+    for (index, &value) in low.iter().enumerate() {
+        let (left, right) = high_neighbours(&high, index);
+        line[2 * index] = value - ((left + right + 2) >> 2);
+    }
+    for (index, &value) in high.iter().enumerate() {
+        let left = line[2 * index];
+        let right = if 2 * index + 2 < len { line[2 * index + 2] } else { left };
+        line[2 * index + 1] = value + ((left + right) >> 1);
+    }
+}
+
+// coeficientes de alta frequência à esquerda e à direita da amostra par `index`
+fn high_neighbours(high: &[i64], index: usize) -> (i64, i64) {
+    let right = if index < high.len() { high[index] } else { high[index - 1] };
+    let left = if index > 0 { high[index - 1] } else { right };
+    (left, right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{
+        decode,
+        encode,
+        photo,
+    };
+
+    #[test]
+    fn lossless_round_trip_and_previews() {
+        for (depths, levels) in [(&[8, 8, 8][..], DEFAULT_LEVELS), (&[12][..], 1), (&[1][..], 0), (&[8][..], MAX_LEVELS)] {
+            let image = photo(27, 14, depths);
+            let encoded = encode(|encoder| super::encode(encoder, &image, levels));
+            let decoded = decode(&encoded, |decoder| super::decode(decoder, 27, 14, depths, levels, 0)).unwrap();
+            assert_eq!(decoded.planes, image.planes);
+
+            // a prévia tem as dimensões da banda LL do nível pulado
+            if levels >= 2 {
+                let preview = decode(&encoded, |decoder| super::decode(decoder, 27, 14, depths, levels, 2)).unwrap();
+                assert_eq!((preview.width, preview.height), (7, 4));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_levels_are_rejected() {
+        let image = photo(8, 8, &[8]);
+        let mut encoder = ArithmeticEncoder::new(0, (1 << 30) - 1, Vec::new()).unwrap();
+        assert!(matches!(super::encode(&mut encoder, &image, MAX_LEVELS + 1), Err(Error::UnsupportedImage(_))));
+        let encoded = encode(|encoder| super::encode(encoder, &image, 2));
+        let result = decode(&encoded, |decoder| super::decode(decoder, 8, 8, &[8], 2, 3));
+        assert!(matches!(result, Err(Error::UnsupportedImage(_))));
+    }
+}
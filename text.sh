@@ -1,3 +1,3 @@
 #!/bin/bash
-cargo run -- -l 0 -h 255 -e text.txt
-cargo run -- -d text.txt.ac
\ No newline at end of file
+cargo run -- -l 0 -h 255 -e text.txt -f
+cargo run -- -d text.txt.ac -f
\ No newline at end of file